@@ -1,31 +1,77 @@
 use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
 use std::fmt::Write;
 use std::hash::{DefaultHasher, Hasher};
 use std::time::SystemTime;
 
-use crate::format::FastDisplay;
-use opentelemetry::{Key, KeyValue, Value};
+use crate::error::{Diagnostics, Error};
+use crate::format::{FastDisplay, canonical_float};
+use cumulative::{AccumulatedData, AccumulatedMetric, CumulativeData, is_delta};
+use data::{HistogramLike, HistogramPoint, NumberPoint, SumLike, ToF64};
+use escaping::{escape_name, is_legacy_name};
+use exemplar::ExemplarSuffix;
+use exponential::RebucketedHistogram;
+use native::{BucketSpan, NativeHistogram};
+use opentelemetry::{InstrumentationScope, Key, KeyValue, Value};
 use opentelemetry_sdk::metrics::Temporality;
 use opentelemetry_sdk::metrics::data::{
-    AggregatedMetrics, Gauge, Histogram, MetricData, ResourceMetrics, Sum,
+    AggregatedMetrics, ExponentialHistogram, ExponentialHistogramDataPoint, Gauge, Metric,
+    MetricData, ResourceMetrics,
 };
-use opentelemetry_sdk::metrics::data::{Metric, ScopeMetrics};
+use options::DEFAULT_OPTIONS;
 use ufmt::{uDisplay, uWrite, uwriteln};
-use unit::get_unit_suffixes;
 
+mod cumulative;
+mod data;
+mod escaping;
+mod exemplar;
+mod exponential;
+mod native;
+mod negotiate;
+mod options;
+#[cfg(feature = "protobuf")]
+mod protobuf;
 #[cfg(test)]
 mod tests;
 mod unit;
 
+pub use cumulative::{Accumulated, DeltaAccumulator};
+pub use exponential::{DEFAULT_MAX_BUCKETS, ExponentialBuckets};
+#[cfg(any(feature = "server", feature = "tower"))]
+pub(crate) use negotiate::negotiate_with_escaping;
+pub use negotiate::{Format, WriteFormat, negotiate, negotiate_among, negotiate_escaping};
+pub use options::{ConvertOptions, NameEscaping, TextFormat, Timestamps};
+#[cfg(feature = "protobuf")]
+pub use protobuf::{PROTOBUF_MIME_TYPE, WriteProtobuf};
+pub use unit::UnitMapping;
+
 /// The mime type of the text produced by this metrics formatter.
 pub const MIME_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
 
+/// The mime type of the text produced in [`TextFormat::Prometheus`].
+pub const PROMETHEUS_MIME_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// The mime type of the text produced in [`TextFormat::OpenMetrics2`].
+pub const OPENMETRICS_2_MIME_TYPE: &str =
+    "application/openmetrics-text; version=2.0.0; charset=utf-8";
+
 /// Trait to write the metrics data in OpenMetrics text format.
 pub trait WriteOpenMetrics {
     /// Writes the metrics into `f` in OpenMetrics text format.
-    fn write_as_openmetrics(&self, f: &mut impl Write) -> std::fmt::Result;
+    fn write_as_openmetrics(&self, f: &mut impl Write) -> Result<(), Error> {
+        self.write_as_openmetrics_with(f, &DEFAULT_OPTIONS)?;
+        Ok(())
+    }
+    /// Writes the metrics into `f` in OpenMetrics text format, converted according to `options`.
+    ///
+    /// Returns the errors of the metrics which were skipped in lenient mode, see [`ConvertOptions::with_lenient`].
+    fn write_as_openmetrics_with(
+        &self,
+        f: &mut impl Write,
+        options: &ConvertOptions,
+    ) -> Result<Vec<Error>, Error>;
     /// Creates and returns a [String] of the metrics data in OpenMetrics text format.
-    fn to_openmetrics_string(&self) -> Result<String, std::fmt::Error> {
+    fn to_openmetrics_string(&self) -> Result<String, Error> {
         let mut out = String::new();
         self.write_as_openmetrics(&mut out)?;
         Ok(out)
@@ -38,25 +84,45 @@ struct Context<'f, W: uWrite> {
     f: W,
     /// a temporary buffer to store the serialized metric attributes
     attr_buffer: String,
-    /// the sanitized name of the current metric
+    /// the sanitized name of the current metric, escaped for quoting if [`Context::quote_name`] is set
     name: String,
-    /// the converted unit string of the current metric
+    /// whether the name of the current metric must be quoted, as it is not a legacy name
+    quote_name: bool,
+    /// the suffix of the samples of the current metric if it is a counter
+    total_suffix: &'static str,
+    /// the converted unit string of the current metric, if its name ends with it
     unit: Option<Cow<'static, str>>,
     /// the OpenMetrics metric type of the current metric
     typ: &'static str,
-    /// the name of the current scope
+    /// the name of the current scope, empty if the series are not labelled with their scope
     scope_name: &'f str,
+    /// the version of the current scope, if the series are labelled with it
+    scope_version: Option<&'f str>,
+    /// the resource attributes copied onto every series, see [`ConvertOptions::with_promoted_resource_attributes`]
+    resource_attrs: Vec<KeyValue>,
+    /// the options for the conversion
+    options: &'f ConvertOptions,
 }
 
 impl<'f, W: Write> Context<'f, WriteAsUWrite<'f, W>> {
+    #[cfg(test)]
     fn with_output(f: &'f mut W) -> Self {
+        Self::with_options(f, &DEFAULT_OPTIONS)
+    }
+
+    fn with_options(f: &'f mut W, options: &'f ConvertOptions) -> Self {
         Context {
             f: WriteAsUWrite(f),
             attr_buffer: String::with_capacity(256),
             name: String::with_capacity(64),
+            quote_name: false,
+            total_suffix: "_total",
             unit: None,
             typ: "",
             scope_name: "",
+            scope_version: None,
+            resource_attrs: Vec::new(),
+            options,
         }
     }
 }
@@ -76,79 +142,460 @@ impl<W: Write> uWrite for WriteAsUWrite<'_, W> {
 }
 
 impl WriteOpenMetrics for ResourceMetrics {
-    fn write_as_openmetrics(&self, f: &mut impl Write) -> std::fmt::Result {
-        let mut ctx = Context::with_output(f);
+    /// Writes the metrics into `f` in OpenMetrics text format, converted according to `options`.
+    ///
+    /// Sums and histograms with delta temporality are skipped, use a [`DeltaAccumulator`] to write those.
+    fn write_as_openmetrics_with(
+        &self,
+        f: &mut impl Write,
+        options: &ConvertOptions,
+    ) -> Result<Vec<Error>, Error> {
+        write_resource_metrics(f, options, self, None)
+    }
+}
+
+impl WriteOpenMetrics for Accumulated<'_> {
+    fn write_as_openmetrics_with(
+        &self,
+        f: &mut impl Write,
+        options: &ConvertOptions,
+    ) -> Result<Vec<Error>, Error> {
+        write_resource_metrics(f, options, self.metrics, Some(self.accumulator))
+    }
+}
+
+/// A metric to be written, either as reported by the SDK or as accumulated by a [`DeltaAccumulator`].
+#[derive(Clone, Copy)]
+enum MetricEntry<'a> {
+    Reported(&'a Metric),
+    Accumulated(&'a str, &'a AccumulatedMetric),
+}
+
+impl<'a> MetricEntry<'a> {
+    fn name(&self) -> &'a str {
+        match self {
+            MetricEntry::Reported(metric) => metric.name(),
+            MetricEntry::Accumulated(name, _) => name,
+        }
+    }
+
+    fn unit(&self) -> &'a str {
+        match self {
+            MetricEntry::Reported(metric) => metric.unit(),
+            MetricEntry::Accumulated(_, metric) => &metric.unit,
+        }
+    }
+
+    fn description(&self) -> &'a str {
+        match self {
+            MetricEntry::Reported(metric) => metric.description(),
+            MetricEntry::Accumulated(_, metric) => &metric.description,
+        }
+    }
+}
 
-        #[cfg(feature = "otel_scope_info")]
-        write_target_info(&mut ctx.f, self.resource())?;
+fn write_resource_metrics(
+    f: &mut impl Write,
+    options: &ConvertOptions,
+    resource_metrics: &ResourceMetrics,
+    accumulator: Option<&DeltaAccumulator>,
+) -> Result<Vec<Error>, Error> {
+    let mut ctx = Context::with_options(f, options);
+    let mut diagnostics = Diagnostics::new(options.lenient);
+    let scopes = collect_scopes(resource_metrics, accumulator);
+    let families = collect_families(&scopes, options, &mut diagnostics)?;
 
-        let mut scopes: Vec<&ScopeMetrics> = self.scope_metrics().collect();
-        scopes.sort_unstable_by_key(|s| s.scope().name());
+    if options.scope_info {
+        write_target_info(&mut ctx.f, resource_metrics.resource(), options)
+            .map_err(write_error(None))?;
+    }
+
+    ctx.resource_attrs = promote_resource_attrs(resource_metrics.resource(), options);
 
-        #[cfg(feature = "otel_scope_info")]
-        write_otel_scope_info(&mut ctx.f, &scopes)?;
+    if options.scope_info {
+        write_otel_scope_info(&mut ctx.f, scopes.iter().map(|(scope, _)| *scope), options)
+            .map_err(write_error(None))?;
+    }
 
-        for scope in scopes {
-            if cfg!(feature = "otel_scope_info") {
-                ctx.scope_name = scope.scope().name();
+    for family in families {
+        let description = family.description();
+        let label_scopes = options.scope_labels || family.spans_scopes();
+        let label_versions = family.has_ambiguous_scopes();
+        ctx.quote_name = options.name_escaping == NameEscaping::AllowUtf8
+            && !is_legacy_name(&family.name, false);
+        if ctx.quote_name {
+            ctx.name.clear();
+            let Ok(()) = write_escaped(&mut ctx.name, &family.name);
+        } else {
+            ctx.name = family.name;
+        }
+        ctx.total_suffix = if options.text_format.is_openmetrics() || family.total_suffix {
+            "_total"
+        } else {
+            ""
+        };
+        ctx.unit = family.unit.filter(|_| family.has_unit_suffix);
+        ctx.typ = family.typ;
+        let first = family.metrics[0];
+        write_header(&mut ctx, description).map_err(write_error(Some(first)))?;
+        for &(scope, metric) in &family.metrics {
+            ctx.scope_name = if label_scopes { scope.name() } else { "" };
+            ctx.scope_version = label_versions.then(|| scope.version().unwrap_or_default());
+            match (family.extreme, metric) {
+                (Some(extreme), _) => write_extreme_values(&mut ctx, metric, extreme),
+                (None, MetricEntry::Reported(metric)) => write_values(&mut ctx, metric.data()),
+                (None, MetricEntry::Accumulated(_, metric)) => {
+                    write_accumulated_values(&mut ctx, &metric.data)
+                }
             }
-            let mut metrics: Vec<_> = scope.metrics().collect();
-            metrics.sort_unstable_by_key(|met| met.name());
+            .map_err(write_error(Some((scope, metric))))?;
+        }
+    }
+    if options.text_format.is_openmetrics() {
+        f.write_str("# EOF\n").map_err(write_error(None))?;
+    }
+    Ok(diagnostics.into_errors())
+}
 
-            for metric in metrics {
-                if extract_type_unit_and_name(&mut ctx, metric) {
-                    write_header(&mut ctx, metric.description())?;
-                    write_values(&mut ctx, metric.data())?;
-                } else {
-                    #[cfg(feature = "tracing")]
-                    tracing::warn!("Unsupported metric type {metric:?}");
+/// Maps a failed write to an [`Error`], with the `metric` being written and its scope if there is one.
+fn write_error(
+    metric: Option<(&InstrumentationScope, MetricEntry)>,
+) -> impl FnOnce(std::fmt::Error) -> Error {
+    move |_| Error::Write {
+        scope: metric.map(|(scope, _)| scope.name().to_owned()),
+        metric: metric.map(|(_, metric)| metric.name().to_owned()),
+    }
+}
+
+/// Collects the metrics to write for each scope, sorted by scope name.
+/// With an `accumulator`, delta metrics are replaced by their accumulated series.
+fn collect_scopes<'a>(
+    resource_metrics: &'a ResourceMetrics,
+    accumulator: Option<&'a DeltaAccumulator>,
+) -> Vec<(&'a InstrumentationScope, Vec<MetricEntry<'a>>)> {
+    let mut scopes: Vec<(&InstrumentationScope, Vec<MetricEntry>)> = resource_metrics
+        .scope_metrics()
+        .map(|scope| {
+            let metrics = scope
+                .metrics()
+                // Delta metrics are written from the accumulated series instead
+                .filter(|metric| accumulator.is_none() || !is_delta(metric.data()))
+                .map(MetricEntry::Reported)
+                .collect();
+            (scope.scope(), metrics)
+        })
+        .collect();
+    if let Some(accumulator) = accumulator {
+        for (scope, metrics) in accumulator.scopes() {
+            let metrics = metrics.map(|(name, metric)| MetricEntry::Accumulated(name, metric));
+            match scopes.iter_mut().find(|(known, _)| *known == scope) {
+                Some((_, entries)) => entries.extend(metrics),
+                None => scopes.push((scope, metrics.collect())),
+            }
+        }
+    }
+    scopes.sort_by(|(a, _), (b, _)| (a.name(), a.version()).cmp(&(b.name(), b.version())));
+    scopes
+}
+
+/// A metric family to be written, combining the metrics of the same sanitized name from all scopes.
+struct Family<'a> {
+    /// the sanitized name of the family including its unit suffix
+    name: String,
+    /// the converted unit string of the family
+    unit: Option<Cow<'static, str>>,
+    /// whether the name ends with the unit before it was escaped, so the unit is written as metadata
+    has_unit_suffix: bool,
+    /// the OpenMetrics metric type of the family
+    typ: &'static str,
+    /// whether the samples of a counter are suffixed with `_total` even where the format does not require it
+    total_suffix: bool,
+    /// the extreme of the histograms written by this gauge family, if it is one
+    extreme: Option<Extreme>,
+    /// the metrics of the family with their scope, in the order of the scopes
+    metrics: Vec<(&'a InstrumentationScope, MetricEntry<'a>)>,
+}
+
+impl<'a> Family<'a> {
+    /// The description of the first metric which has one.
+    fn description(&self) -> &'a str {
+        self.metrics
+            .iter()
+            .map(|(_, metric)| metric.description())
+            .find(|description| !description.is_empty())
+            .unwrap_or_default()
+    }
+
+    /// Whether the single metric of `other` may join this family.
+    fn accepts(&self, other: &Family) -> bool {
+        self.typ == other.typ && self.unit == other.unit && self.extreme == other.extreme
+    }
+
+    /// The names of the samples other than the family name in the `format`, like `requests_total` of the counter
+    /// `requests`.
+    fn sample_names(&self, format: TextFormat) -> impl Iterator<Item = String> + '_ {
+        let suffixes: &[&str] = match self.typ {
+            "counter" if format.is_openmetrics() => &["_total", "_created"],
+            "counter" if self.total_suffix => &["_total"],
+            "histogram" if format.is_openmetrics() => &["_bucket", "_count", "_sum", "_created"],
+            "histogram" => &["_bucket", "_count", "_sum"],
+            _ => &[],
+        };
+        suffixes
+            .iter()
+            .map(|suffix| format!("{}{suffix}", self.name))
+    }
+
+    /// Whether the metrics stem from several scopes, so their series must be labelled with the scope.
+    fn spans_scopes(&self) -> bool {
+        self.metrics.len() > 1
+    }
+
+    /// Whether scopes of the same name contribute, so their series must be labelled with the scope version as well.
+    fn has_ambiguous_scopes(&self) -> bool {
+        self.metrics
+            .windows(2)
+            .any(|pair| pair[0].0.name() == pair[1].0.name())
+    }
+}
+
+/// Groups the metrics of all `scopes` into families by their name built according to `options`, sorted by that name.
+///
+/// A metric whose type or unit conflicts with the first metric of its family is skipped, or fails a strict
+/// conversion, and so is a metric of an unsupported type or with invalid values. The same goes for a
+/// metric whose family would take the name of a sample of another family, like a gauge `requests_total` next to a
+/// counter `requests`, or the other way round. With [`ConvertOptions::with_histogram_min_max`], the gauge families of
+/// the extremes of a histogram are collected alongside it. An accumulated metric which overflowed is reported, or
+/// fails a strict conversion, but collected with its series started over.
+fn collect_families<'a>(
+    scopes: &[(&'a InstrumentationScope, Vec<MetricEntry<'a>>)],
+    options: &ConvertOptions,
+    diagnostics: &mut Diagnostics,
+) -> Result<Vec<Family<'a>>, Error> {
+    let mut families: Vec<Family> = Vec::new();
+    let mut family_indices: HashMap<String, usize> = HashMap::new();
+    // The names of the samples of the families which are not family names themselves
+    let mut sample_names: HashSet<String> = HashSet::new();
+    if options.scope_info {
+        sample_names.extend(["target_info", "otel_scope_info"].map(String::from));
+        if options.text_format.is_openmetrics() {
+            sample_names.extend(["target", "otel_scope"].map(String::from));
+        }
+    }
+    let mut name = String::with_capacity(64);
+    for (scope, metrics) in scopes {
+        for &metric in metrics {
+            let typ = match get_entry_type(&metric) {
+                Ok(typ) => typ,
+                Err(unsupported) => {
+                    diagnostics.report(unsupported.into_error(scope, &metric))?;
+                    continue;
+                }
+            };
+            if let Err(reason) = validate_entry(&metric) {
+                diagnostics.report(Error::InvalidValue {
+                    scope: scope.name().to_owned(),
+                    metric: metric.name().to_owned(),
+                    reason,
+                })?;
+                continue;
+            }
+            // The family of the metric, followed by those of its extremes
+            let mut candidates: Vec<Family> = Vec::with_capacity(3);
+            let (unit, has_unit_suffix, has_total) =
+                write_metric_name(&mut name, &metric, typ, "", options);
+            candidates.push(Family {
+                name: name.clone(),
+                unit,
+                has_unit_suffix,
+                typ,
+                total_suffix: options.type_suffixes || has_total,
+                extreme: None,
+                metrics: vec![(scope, metric)],
+            });
+            if options.histogram_min_max && typ == "histogram" {
+                for extreme in [Extreme::Min, Extreme::Max] {
+                    let (unit, has_unit_suffix, _) =
+                        write_metric_name(&mut name, &metric, "gauge", extreme.suffix(), options);
+                    candidates.push(Family {
+                        name: name.clone(),
+                        unit,
+                        has_unit_suffix,
+                        typ: "gauge",
+                        total_suffix: false,
+                        extreme: Some(extreme),
+                        metrics: vec![(scope, metric)],
+                    });
+                }
+            }
+
+            let collision =
+                candidates
+                    .iter()
+                    .find(|candidate| match family_indices.get(&candidate.name) {
+                        Some(&index) => !families[index].accepts(candidate),
+                        None => {
+                            sample_names.contains(&candidate.name)
+                                || candidate.sample_names(options.text_format).any(|sample| {
+                                    family_indices.contains_key(&sample)
+                                        || sample_names.contains(&sample)
+                                })
+                        }
+                    });
+            if let Some(candidate) = collision {
+                diagnostics.report(Error::NameCollision {
+                    scope: scope.name().to_owned(),
+                    metric: metric.name().to_owned(),
+                    family: candidate.name.clone(),
+                })?;
+                continue;
+            }
+            if let MetricEntry::Accumulated(_, accumulated) = metric
+                && accumulated.overflowed
+            {
+                diagnostics.report(Error::Overflow {
+                    scope: scope.name().to_owned(),
+                    metric: metric.name().to_owned(),
+                })?;
+            }
+            for candidate in candidates {
+                match family_indices.get(&candidate.name) {
+                    Some(&index) => families[index].metrics.push((scope, metric)),
+                    None => {
+                        sample_names.extend(candidate.sample_names(options.text_format));
+                        family_indices.insert(candidate.name.clone(), families.len());
+                        families.push(candidate);
+                    }
                 }
             }
         }
-        f.write_str("# EOF\n")?;
-        Ok(())
     }
+    families.sort_unstable_by(|a, b| a.name.cmp(&b.name));
+    Ok(families)
 }
 
 fn write_target_info<U: uWrite>(
     f: &mut U,
     resource: &opentelemetry_sdk::Resource,
+    options: &ConvertOptions,
 ) -> Result<(), U::Error> {
-    f.write_str("# TYPE target info\n")?;
+    match options.text_format {
+        TextFormat::OpenMetrics | TextFormat::OpenMetrics2 => {
+            f.write_str("# TYPE target info\n")?
+        }
+        TextFormat::Prometheus => f.write_str("# TYPE target_info gauge\n")?,
+    }
     f.write_str("target_info{")?;
-    write_attrs_tuple(f, resource.iter())?;
+    write_attrs_tuple(f, resource.iter(), options.name_escaping)?;
     f.write_str("} 1\n")?;
     Ok(())
 }
 
-fn extract_type_unit_and_name(
-    ctx: &mut Context<'_, impl uWrite<Error = std::fmt::Error>>,
-    metric: &Metric,
-) -> bool {
-    let Ok(typ) = get_type(metric.data()) else {
-        return false;
-    };
-    ctx.typ = typ;
-    ctx.unit = get_unit_suffixes(metric.unit());
+/// Gets the OpenMetrics metric type of a [`MetricEntry`], see [`get_type`].
+fn get_entry_type(metric: &MetricEntry) -> Result<&'static str, Unsupported> {
+    match metric {
+        MetricEntry::Reported(metric) => get_type(metric.data()),
+        MetricEntry::Accumulated(_, metric) => Ok(get_accumulated_type(&metric.data)),
+    }
+}
 
-    ctx.name.clear();
-    let Ok(()) = write_sanitized_name(&mut ctx.name, metric.name());
-    if let Some(ref unit) = ctx.unit {
-        ctx.name.push('_');
-        ctx.name.push_str(unit);
+/// Replaces `name` with the family name of `metric` of type `typ`, built and sanitized according to `options`, and
+/// returns its unit, whether the name ends with the unit before escaping and whether the name of a counter ended with
+/// `_total`. A non-empty `suffix` is put between the name and its unit suffix.
+///
+/// The unit suffix is not added if the name already ends with it, and the `_total` suffix of a counter is removed,
+/// as it is added to the samples instead, according to the
+/// [spec](https://github.com/open-telemetry/opentelemetry-specification/blob/v1.45.0/specification/compatibility/prometheus_and_openmetrics.md#metric-metadata-1).
+fn write_metric_name(
+    name: &mut String,
+    metric: &MetricEntry,
+    typ: &'static str,
+    suffix: &str,
+    options: &ConvertOptions,
+) -> (Option<Cow<'static, str>>, bool, bool) {
+    let unit = options
+        .unit_mapping
+        .get_unit_suffixes(metric.name(), metric.unit());
+    name.clear();
+    if options.name_escaping == NameEscaping::Underscores {
+        let Ok(()) = write_sanitized_name(name, metric.name());
+    } else {
+        name.push_str(metric.name());
+    }
+    let has_total = typ == "counter" && has_name_suffix(name, "total");
+    if has_total {
+        name.truncate(name.len() - "_total".len());
+    }
+    if !suffix.is_empty() {
+        let unit_suffix_len = match unit {
+            Some(ref unit) if options.unit_suffixes && has_name_suffix(name, unit) => {
+                unit.len() + 1
+            }
+            _ => 0,
+        };
+        name.insert_str(name.len() - unit_suffix_len, suffix);
     }
+    if options.unit_suffixes
+        && let Some(ref unit) = unit
+        && !has_name_suffix(name, unit)
+    {
+        name.push('_');
+        name.push_str(unit);
+    }
+    if let Cow::Owned(escaped) = escape_name(name, options.name_escaping, false) {
+        *name = escaped;
+    }
+    // Escaping may change the unit suffix, like `_bytes_per_second` into `_bytes__per__second`, which then no longer
+    // matches the unit
+    let has_unit_suffix = unit
+        .as_ref()
+        .is_some_and(|unit| has_name_suffix(name, unit));
+    (unit, has_unit_suffix, has_total)
+}
 
-    true
+/// Whether `name` ends with `_` followed by `suffix`, and has more before.
+fn has_name_suffix(name: &str, suffix: &str) -> bool {
+    name.strip_suffix(suffix)
+        .and_then(|name| name.strip_suffix('_'))
+        .is_some_and(|name| !name.is_empty())
+}
+
+/// Why a metric cannot be converted, see [`get_type`].
+#[derive(Debug)]
+enum Unsupported {
+    Temporality(Temporality),
+    Aggregation(&'static str),
+}
+
+impl Unsupported {
+    fn into_error(self, scope: &InstrumentationScope, metric: &MetricEntry) -> Error {
+        let scope = scope.name().to_owned();
+        let metric = metric.name().to_owned();
+        match self {
+            Unsupported::Temporality(temporality) => Error::UnsupportedTemporality {
+                scope,
+                metric,
+                temporality,
+            },
+            Unsupported::Aggregation(aggregation) => Error::UnsupportedAggregation {
+                scope,
+                metric,
+                aggregation,
+            },
+        }
+    }
 }
 
 /// Gets the OpenMetrics metric type for this [`AggregatedMetrics`].
-/// Returns `Err(())` for unsupported metric types.
-fn get_type(metric: &AggregatedMetrics) -> Result<&'static str, ()> {
-    fn get_metric_data_type<T>(metric_data: &MetricData<T>) -> Result<&'static str, ()> {
+/// Fails for unsupported metric types, including delta temporality sums and histograms.
+fn get_type(metric: &AggregatedMetrics) -> Result<&'static str, Unsupported> {
+    fn get_metric_data_type<T>(metric_data: &MetricData<T>) -> Result<&'static str, Unsupported> {
         match metric_data {
             MetricData::Gauge(_) => Ok("gauge"),
             MetricData::Sum(sum) => {
-                if sum.is_monotonic() {
+                if sum.temporality() != Temporality::Cumulative {
+                    Err(Unsupported::Temporality(sum.temporality()))
+                } else if sum.is_monotonic() {
                     Ok("counter")
                 } else {
                     Ok("gauge")
@@ -158,10 +605,19 @@ fn get_type(metric: &AggregatedMetrics) -> Result<&'static str, ()> {
                 if hist.temporality() == Temporality::Cumulative {
                     Ok("histogram")
                 } else {
-                    Err(())
+                    Err(Unsupported::Temporality(hist.temporality()))
+                }
+            }
+            MetricData::ExponentialHistogram(hist) => {
+                if hist.temporality() == Temporality::Cumulative {
+                    Ok("histogram")
+                } else {
+                    // Not even accumulated by a `DeltaAccumulator`
+                    Err(Unsupported::Aggregation(
+                        "exponential histogram with delta temporality",
+                    ))
                 }
             }
-            _ => Err(()),
         }
     }
     match metric {
@@ -171,50 +627,190 @@ fn get_type(metric: &AggregatedMetrics) -> Result<&'static str, ()> {
     }
 }
 
-/// Write the current metric's metadata. Make sure to call [`extract_type_unit_and_name`] first.
+/// Gets the OpenMetrics metric type for this [`AccumulatedData`].
+fn get_accumulated_type(metric: &AccumulatedData) -> &'static str {
+    fn get_cumulative_data_type<T: Copy + 'static>(data: &CumulativeData<T>) -> &'static str {
+        match data {
+            CumulativeData::Sum(sum) if sum.is_monotonic() => "counter",
+            CumulativeData::Sum(_) => "gauge",
+            CumulativeData::Histogram(_) => "histogram",
+        }
+    }
+    match metric {
+        AccumulatedData::F64(data) => get_cumulative_data_type(data),
+        AccumulatedData::U64(data) => get_cumulative_data_type(data),
+        AccumulatedData::I64(data) => get_cumulative_data_type(data),
+    }
+}
+
+/// Checks the values of `metric` against the constraints of OpenMetrics, returning the violated one.
+fn validate_entry(metric: &MetricEntry) -> Result<(), &'static str> {
+    fn validate_data<T: ToF64 + 'static>(data: &MetricData<T>) -> Result<(), &'static str> {
+        match data {
+            MetricData::Sum(sum) => validate_sum(sum),
+            MetricData::Histogram(histogram) => validate_histogram(histogram),
+            MetricData::Gauge(_) | MetricData::ExponentialHistogram(_) => Ok(()),
+        }
+    }
+    fn validate_accumulated<T: ToF64 + 'static>(
+        data: &CumulativeData<T>,
+    ) -> Result<(), &'static str> {
+        match data {
+            CumulativeData::Sum(sum) => validate_sum(sum),
+            CumulativeData::Histogram(histogram) => validate_histogram(histogram),
+        }
+    }
+    match metric {
+        MetricEntry::Reported(metric) => match metric.data() {
+            AggregatedMetrics::F64(data) => validate_data(data),
+            AggregatedMetrics::U64(data) => validate_data(data),
+            AggregatedMetrics::I64(data) => validate_data(data),
+        },
+        MetricEntry::Accumulated(_, metric) => match &metric.data {
+            AccumulatedData::F64(data) => validate_accumulated(data),
+            AccumulatedData::U64(data) => validate_accumulated(data),
+            AccumulatedData::I64(data) => validate_accumulated(data),
+        },
+    }
+}
+
+/// Checks that the totals of a monotonic `sum` are neither negative nor NaN, as OpenMetrics requires of counters.
+fn validate_sum<T: ToF64>(sum: &impl SumLike<T>) -> Result<(), &'static str> {
+    let is_valid = |value: f64| value >= 0.0;
+    if sum.is_monotonic()
+        && !sum
+            .data_points()
+            .all(|point| is_valid(point.value().to_f64()))
+    {
+        return Err("counter total is negative or NaN");
+    }
+    Ok(())
+}
+
+/// Checks that each point of `histogram` has increasing bounds and a bucket count for each of them and `+Inf`.
+fn validate_histogram<T>(histogram: &impl HistogramLike<T>) -> Result<(), &'static str> {
+    for point in histogram.data_points() {
+        let mut bounds = point.bounds().peekable();
+        let mut len = 0;
+        while let Some(bound) = bounds.next() {
+            len += 1;
+            if bounds.peek().is_some_and(|&next| next <= bound) || bound.is_nan() {
+                return Err("histogram bounds are not increasing");
+            }
+        }
+        if point.bucket_counts().count() != len + 1 {
+            return Err("histogram bucket counts do not match its bounds");
+        }
+    }
+    Ok(())
+}
+
+/// Write the current metric's metadata.
 #[inline]
 fn write_header<U: uWrite>(ctx: &mut Context<'_, U>, description: &str) -> Result<(), U::Error> {
     let Context {
-        f, name, unit, typ, ..
+        f,
+        name,
+        quote_name,
+        total_suffix,
+        unit,
+        typ,
+        options,
+        ..
     } = ctx;
-    for x in &["# TYPE ", name, " ", typ, "\n"] {
+    let quote = if *quote_name { "\"" } else { "" };
+    if options.text_format == TextFormat::Prometheus {
+        let suffix = if *typ == "counter" { *total_suffix } else { "" };
+        return write_prometheus_header(f, name, suffix, quote, typ, description);
+    }
+    for x in &["# TYPE ", quote, name, quote, " ", typ, "\n"] {
         f.write_str(x)?;
     }
 
     if let Some(unit) = unit {
-        for x in &["# UNIT ", name, " ", unit, "\n"] {
+        for x in &["# UNIT ", quote, name, quote, " ", unit, "\n"] {
             f.write_str(x)?;
         }
     }
     if !description.is_empty() {
-        f.write_str("# HELP ")?;
-        f.write_str(name)?;
-        f.write_str(" ")?;
+        for x in &["# HELP ", quote, name, quote, " "] {
+            f.write_str(x)?;
+        }
         write_escaped(f, description)?;
         f.write_char('\n')?;
     }
     Ok(())
 }
 
-/// Write a `otel_scope` metric of type info for all scopes in `metrics`
+/// Write the metadata of a metric in the Prometheus text format, which has no unit and refers to the sample name
+/// instead of the metric family. The sample name is `name` followed by `suffix`, put between `quote`s.
+fn write_prometheus_header<U: uWrite>(
+    f: &mut U,
+    name: &str,
+    suffix: &str,
+    quote: &str,
+    typ: &'static str,
+    description: &str,
+) -> Result<(), U::Error> {
+    if !description.is_empty() {
+        for x in &["# HELP ", quote, name, suffix, quote, " "] {
+            f.write_str(x)?;
+        }
+        write_escaped_help(f, description)?;
+        f.write_char('\n')?;
+    }
+    for x in &[
+        "# TYPE ",
+        quote,
+        name,
+        suffix,
+        quote,
+        " ",
+        get_prometheus_type(typ),
+        "\n",
+    ] {
+        f.write_str(x)?;
+    }
+    Ok(())
+}
+
+/// Maps an OpenMetrics metric type onto the types known to the Prometheus text format.
+fn get_prometheus_type(typ: &'static str) -> &'static str {
+    match typ {
+        "counter" | "gauge" | "histogram" | "summary" => typ,
+        "info" => "gauge",
+        _ => "untyped",
+    }
+}
+
+/// Write a `otel_scope` metric of type info for all `scopes`
 /// according to the [spec](https://github.com/open-telemetry/opentelemetry-specification/blob/v1.45.0/specification/compatibility/prometheus_and_openmetrics.md#instrumentation-scope-1).
-#[cfg(feature = "otel_scope_info")]
-fn write_otel_scope_info<U: uWrite>(
+fn write_otel_scope_info<'a, U: uWrite>(
     f: &mut U,
-    metrics: &'_ Vec<&ScopeMetrics>,
+    scopes: impl Iterator<Item = &'a InstrumentationScope>,
+    options: &ConvertOptions,
 ) -> Result<(), U::Error> {
-    f.write_str("# TYPE otel_scope info\n")?;
+    match options.text_format {
+        TextFormat::OpenMetrics | TextFormat::OpenMetrics2 => {
+            f.write_str("# TYPE otel_scope info\n")?
+        }
+        TextFormat::Prometheus => f.write_str("# TYPE otel_scope_info gauge\n")?,
+    }
 
-    for scope in metrics {
+    for scope in scopes {
         let otel_attrs = &[
-            KeyValue::new("otel_scope_name", scope.scope().name().to_owned()),
+            KeyValue::new("otel_scope_name", scope.name().to_owned()),
             KeyValue::new(
                 "otel_scope_version",
-                scope.scope().version().unwrap_or_default().to_owned(),
+                scope.version().unwrap_or_default().to_owned(),
             ),
         ];
         f.write_str("otel_scope_info{")?;
-        write_attrs(f, otel_attrs.iter().chain(scope.scope().attributes()))?;
+        write_attrs(
+            f,
+            otel_attrs.iter().chain(scope.attributes()),
+            options.name_escaping,
+        )?;
         f.write_str("} 1\n")?;
     }
     Ok(())
@@ -226,120 +822,135 @@ fn write_values<U: uWrite>(
     metric: &AggregatedMetrics,
 ) -> Result<(), U::Error> {
     match metric {
-        AggregatedMetrics::F64(metric_data) => {
-            match metric_data {
-                MetricData::Gauge(gauge) => write_gauge(ctx, gauge),
-                MetricData::Sum(sum) => write_counter(ctx, sum),
-                MetricData::Histogram(histogram) => write_histogram(ctx, histogram),
-                _ => unimplemented!("only gauge/sum/histogram metrics should be constructible"),
-                // See https://github.com/open-telemetry/opentelemetry-specification/blob/v1.45.0/specification/compatibility/prometheus_and_openmetrics.md#exponential-histograms
-                // for exponential histograms
+        AggregatedMetrics::F64(metric_data) => match metric_data {
+            MetricData::Gauge(gauge) => write_gauge(ctx, gauge),
+            MetricData::Sum(sum) => write_counter(ctx, sum),
+            MetricData::Histogram(histogram) => write_histogram(ctx, histogram),
+            MetricData::ExponentialHistogram(histogram) => {
+                write_exponential_histogram(ctx, histogram)
             }
-        }
+        },
         AggregatedMetrics::U64(metric_data) => match metric_data {
             MetricData::Gauge(gauge) => write_gauge(ctx, gauge),
             MetricData::Sum(sum) => write_counter(ctx, sum),
             MetricData::Histogram(histogram) => write_histogram(ctx, histogram),
-            _ => unimplemented!("only gauge/sum/histogram metrics should be constructible"),
+            MetricData::ExponentialHistogram(histogram) => {
+                write_exponential_histogram(ctx, histogram)
+            }
         },
         AggregatedMetrics::I64(metric_data) => match metric_data {
             MetricData::Gauge(gauge) => write_gauge(ctx, gauge),
             MetricData::Sum(sum) => write_counter(ctx, sum),
             MetricData::Histogram(histogram) => write_histogram(ctx, histogram),
-            _ => unimplemented!("only gauge/sum/histogram metrics should be constructible"),
+            MetricData::ExponentialHistogram(histogram) => {
+                write_exponential_histogram(ctx, histogram)
+            }
         },
     }
 }
 
-fn write_histogram<T: FastDisplay + Copy, U: uWrite>(
+/// Write all data points of an accumulated metric
+fn write_accumulated_values<U: uWrite>(
+    ctx: &mut Context<'_, U>,
+    metric: &AccumulatedData,
+) -> Result<(), U::Error> {
+    match metric {
+        AccumulatedData::F64(CumulativeData::Sum(sum)) => write_counter(ctx, sum),
+        AccumulatedData::F64(CumulativeData::Histogram(histogram)) => {
+            write_histogram(ctx, histogram)
+        }
+        AccumulatedData::U64(CumulativeData::Sum(sum)) => write_counter(ctx, sum),
+        AccumulatedData::U64(CumulativeData::Histogram(histogram)) => {
+            write_histogram(ctx, histogram)
+        }
+        AccumulatedData::I64(CumulativeData::Sum(sum)) => write_counter(ctx, sum),
+        AccumulatedData::I64(CumulativeData::Histogram(histogram)) => {
+            write_histogram(ctx, histogram)
+        }
+    }
+}
+
+/// Write a cumulative histogram. Histograms with other temporalities are rejected by [`get_type`] beforehand.
+fn write_histogram<T: FastDisplay + ToF64, U: uWrite>(
     ctx: &mut Context<'_, U>,
-    histogram: &Histogram<T>,
+    histogram: &impl HistogramLike<T>,
 ) -> Result<(), U::Error> {
-    let scope_name_attrs = make_scope_name_attrs(ctx.scope_name);
-    let ts = to_timestamp(histogram.time());
-    let created = to_timestamp(histogram.start_time());
-    ctx.attr_buffer.clear();
+    let scope_name_attrs = make_scope_attrs(ctx.scope_name, ctx.scope_version);
+    let format = ctx.options.text_format;
+    let time = SampleTimestamp::new(histogram.time(), ctx.options);
     let attrs = &mut ctx.attr_buffer;
-    let Ok(()) = write_attrs(attrs, scope_name_attrs.iter());
-    uwriteln!(
-        ctx.f,
-        "{}_created{{{}}} {} {}"
-        ctx.name,
-        attrs,
-        created,
-        ts,
-    )?;
-    assert_eq!(
-        histogram.temporality(),
-        Temporality::Cumulative,
-        "Only cumulative Histograms are supported"
-    );
 
     let mut points: Vec<_> = histogram.data_points().collect();
     points.sort_by_cached_key(|p| hash_attrs(p.attributes()));
 
     for point in points {
+        let ts = time.with_start(histogram.start_time(point), ctx.options);
         attrs.clear();
-        let Ok(()) = write_attrs(attrs, point.attributes().chain(scope_name_attrs.iter()));
+        let Ok(()) = write_attrs(
+            attrs,
+            series_attrs(
+                || point.attributes(),
+                &scope_name_attrs,
+                &ctx.resource_attrs,
+                ctx.options.name_escaping,
+            ),
+            ctx.options.name_escaping,
+        );
 
         uwriteln!(
             ctx.f,
-            "{}_count{{{}}} {} {}",
-            ctx.name,
+            "{}{}}} {}{}",
+            SampleName::new(&ctx.name, ctx.quote_name, "_count", !attrs.is_empty()),
             attrs,
             point.count().fast_display(),
             ts
         )?;
-        uwriteln!(
-            ctx.f,
-            "{}_sum{{{}}} {} {}",
-            ctx.name,
-            attrs,
-            point.sum().fast_display(),
-            ts,
-        )?;
-
-        #[cfg(feature = "experimental-histogram-min-max")]
-        {
-            // Non-compliant but useful
-            // TODO: Expose as a separate gauge?
-            if let Some(min) = point.min() {
-                uwriteln!(
-                    ctx.f,
-                    "{}_min{{{}}} {} {}",
-                    ctx.name,
-                    attrs,
-                    min.fast_display(),
-                    ts,
-                )?;
-            }
-            if let Some(max) = point.max() {
-                uwriteln!(
-                    ctx.f,
-                    "{}_max{{{}}} {} {}",
-                    ctx.name,
-                    attrs,
-                    max.fast_display(),
-                    ts,
-                )?;
-            }
+        // Histograms with negative buckets must not have a sum according to the OpenMetrics spec
+        if format == TextFormat::Prometheus || !point.bounds().any(|bound| bound < 0.0) {
+            uwriteln!(
+                ctx.f,
+                "{}{}}} {}{}",
+                SampleName::new(&ctx.name, ctx.quote_name, "_sum", !attrs.is_empty()),
+                attrs,
+                point.sum().fast_display(),
+                ts,
+            )?;
+        }
+        if let Some(created) = to_created(histogram.start_time(point), ctx.options) {
+            uwriteln!(
+                ctx.f,
+                "{}{}}} {}{}",
+                SampleName::new(&ctx.name, ctx.quote_name, "_created", !attrs.is_empty()),
+                attrs,
+                created,
+                time,
+            )?;
         }
 
         if !attrs.is_empty() {
             attrs.push(',');
         }
+        let exemplars = if has_exemplars(ctx.options) {
+            exemplar::by_bucket(point.bounds(), point.exemplars())
+        } else {
+            Vec::new()
+        };
+        let bucket_exemplar =
+            |i: usize| ExemplarSuffix::new(exemplars.get(i).copied().flatten(), ctx.options);
         let mut cumulative_count = 0;
-        for (bound, count) in std::iter::zip(point.bounds(), point.bucket_counts()) {
+        for (i, (bound, count)) in std::iter::zip(point.bounds(), point.bucket_counts()).enumerate()
+        {
             cumulative_count += count;
             uwriteln!(
                 // Not using write! here is a ~19% speedup
                 ctx.f,
-                "{}_bucket{{{}le=\"{}\"}} {} {}"
-                ctx.name,
+                "{}{}le=\"{}\"}} {}{}{}"
+                SampleName::new(&ctx.name, ctx.quote_name, "_bucket", true),
                 attrs,
-                bound.fast_display(),
+                canonical_float(bound),
                 cumulative_count.fast_display(),
                 ts,
+                bucket_exemplar(i),
             )?;
             // writeln!(
             //     f,
@@ -350,57 +961,326 @@ fn write_histogram<T: FastDisplay + Copy, U: uWrite>(
         }
         uwriteln!(
             ctx.f,
-            "{}_bucket{{{}le=\"+Inf\"}} {} {}",
-            ctx.name,
+            "{}{}le=\"+Inf\"}} {}{}{}",
+            SampleName::new(&ctx.name, ctx.quote_name, "_bucket", true),
             attrs,
             point.count().fast_display(),
             ts,
+            bucket_exemplar(exemplars.len().saturating_sub(1)),
+        )?;
+    }
+    Ok(())
+}
+
+/// The minimum or maximum of the values recorded by histograms, which is written as a gauge family of its own, as
+/// OpenMetrics histograms have no place for it.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Extreme {
+    Min,
+    Max,
+}
+
+impl Extreme {
+    /// The suffix of the gauge family, which is put before the unit suffix of the histogram.
+    fn suffix(self) -> &'static str {
+        match self {
+            Extreme::Min => "_min",
+            Extreme::Max => "_max",
+        }
+    }
+
+    fn select<T>(self, min: Option<T>, max: Option<T>) -> Option<T> {
+        match self {
+            Extreme::Min => min,
+            Extreme::Max => max,
+        }
+    }
+}
+
+/// Writes the `extreme` of each point of the histogram `metric` as a gauge sample.
+fn write_extreme_values<U: uWrite>(
+    ctx: &mut Context<'_, U>,
+    metric: MetricEntry,
+    extreme: Extreme,
+) -> Result<(), U::Error> {
+    match metric {
+        MetricEntry::Reported(metric) => match metric.data() {
+            AggregatedMetrics::F64(data) => write_extreme_data(ctx, data, extreme),
+            AggregatedMetrics::U64(data) => write_extreme_data(ctx, data, extreme),
+            AggregatedMetrics::I64(data) => write_extreme_data(ctx, data, extreme),
+        },
+        MetricEntry::Accumulated(_, metric) => match &metric.data {
+            AccumulatedData::F64(CumulativeData::Histogram(histogram)) => {
+                write_extremes(ctx, histogram, extreme)
+            }
+            AccumulatedData::U64(CumulativeData::Histogram(histogram)) => {
+                write_extremes(ctx, histogram, extreme)
+            }
+            AccumulatedData::I64(CumulativeData::Histogram(histogram)) => {
+                write_extremes(ctx, histogram, extreme)
+            }
+            _ => Ok(()),
+        },
+    }
+}
+
+/// Writes the `extreme` of each point of a histogram in `data` as a gauge sample.
+fn write_extreme_data<T: FastDisplay + Copy + 'static, U: uWrite>(
+    ctx: &mut Context<'_, U>,
+    data: &MetricData<T>,
+    extreme: Extreme,
+) -> Result<(), U::Error> {
+    match data {
+        MetricData::Histogram(histogram) => write_extremes(ctx, histogram, extreme),
+        MetricData::ExponentialHistogram(histogram) => write_extreme_samples(
+            ctx,
+            histogram.time(),
+            histogram
+                .data_points()
+                .map(|point| (point.attributes(), extreme.select(point.min(), point.max()))),
+        ),
+        _ => Ok(()),
+    }
+}
+
+/// Writes the `extreme` of each point of `histogram` as a gauge sample.
+fn write_extremes<T: FastDisplay + Copy, U: uWrite>(
+    ctx: &mut Context<'_, U>,
+    histogram: &impl HistogramLike<T>,
+    extreme: Extreme,
+) -> Result<(), U::Error> {
+    write_extreme_samples(
+        ctx,
+        histogram.time(),
+        histogram
+            .data_points()
+            .map(|point| (point.attributes(), extreme.select(point.min(), point.max()))),
+    )
+}
+
+/// Writes a gauge sample for each series of `points` which has a value, with the labels of its histogram.
+fn write_extreme_samples<'a, T: FastDisplay + Copy, U: uWrite>(
+    ctx: &mut Context<'_, U>,
+    time: SystemTime,
+    points: impl Iterator<Item = (impl Iterator<Item = &'a KeyValue>, Option<T>)>,
+) -> Result<(), U::Error> {
+    let scope_name_attrs = make_scope_attrs(ctx.scope_name, ctx.scope_version);
+    let ts = SampleTimestamp::new(time, ctx.options);
+    let mut points: Vec<(Vec<&KeyValue>, T)> = points
+        .filter_map(|(attributes, value)| Some((attributes.collect(), value?)))
+        .collect();
+    points.sort_by_cached_key(|(attributes, _)| hash_attrs(attributes.iter().copied()));
+
+    let attrs = &mut ctx.attr_buffer;
+    for (attributes, value) in points {
+        attrs.clear();
+        let Ok(()) = write_attrs(
+            attrs,
+            series_attrs(
+                || attributes.iter().copied(),
+                &scope_name_attrs,
+                &ctx.resource_attrs,
+                ctx.options.name_escaping,
+            ),
+            ctx.options.name_escaping,
+        );
+        uwriteln!(
+            ctx.f,
+            "{}{}}} {}{}",
+            SampleName::new(&ctx.name, ctx.quote_name, "", !attrs.is_empty()),
+            attrs,
+            value.fast_display(),
+            ts,
         )?;
     }
     Ok(())
 }
 
-fn write_counter<T: FastDisplay + Copy, U: uWrite>(
+/// Write an exponential histogram as a histogram with the buckets configured in [`ConvertOptions`].
+/// See the [spec](https://github.com/open-telemetry/opentelemetry-specification/blob/v1.45.0/specification/compatibility/prometheus_and_openmetrics.md#exponential-histograms).
+fn write_exponential_histogram<T: FastDisplay + ToF64 + 'static, U: uWrite>(
+    ctx: &mut Context<'_, U>,
+    histogram: &ExponentialHistogram<T>,
+) -> Result<(), U::Error> {
+    if ctx.options.native_histograms && ctx.options.text_format == TextFormat::OpenMetrics2 {
+        let native: Option<Vec<_>> = histogram
+            .data_points()
+            .map(|point| NativeHistogram::new(point).map(|native| (point, native)))
+            .collect();
+        if let Some(points) = native {
+            return write_native_histogram(ctx, histogram, points);
+        }
+    }
+    let histogram = RebucketedHistogram::new(histogram, &ctx.options.exponential_buckets);
+    write_histogram(ctx, &histogram)
+}
+
+/// Write an exponential histogram as a Prometheus native histogram, using the composite value syntax of the
+/// [OpenMetrics 2.0 draft](https://github.com/prometheus/OpenMetrics/blob/main/specification/OpenMetrics.md).
+fn write_native_histogram<T: FastDisplay + Copy, U: uWrite>(
     ctx: &mut Context<'_, U>,
-    sum: &Sum<T>,
+    histogram: &ExponentialHistogram<T>,
+    mut points: Vec<(&ExponentialHistogramDataPoint<T>, NativeHistogram)>,
 ) -> Result<(), U::Error> {
+    let scope_name_attrs = make_scope_attrs(ctx.scope_name, ctx.scope_version);
+    let time = SampleTimestamp::new(histogram.time(), ctx.options);
+    let start_time = histogram.start_time();
+    let created = to_created(start_time, ctx.options);
     let attrs = &mut ctx.attr_buffer;
-    let scope_name_attrs = make_scope_name_attrs(ctx.scope_name);
-    assert_eq!(
-        sum.temporality(),
-        opentelemetry_sdk::metrics::Temporality::Cumulative,
-        "Only cumulative sums are supported"
-    );
+
+    points.sort_by_cached_key(|(p, _)| hash_attrs(p.attributes()));
+
+    for (point, native) in points {
+        attrs.clear();
+        let Ok(()) = write_attrs(
+            attrs,
+            series_attrs(
+                || point.attributes(),
+                &scope_name_attrs,
+                &ctx.resource_attrs,
+                ctx.options.name_escaping,
+            ),
+            ctx.options.name_escaping,
+        );
+        ufmt::uwrite!(
+            ctx.f,
+            "{}{}}} {{count:{},sum:{},schema:{},zero_threshold:{},zero_count:{}",
+            SampleName::new(&ctx.name, ctx.quote_name, "", !attrs.is_empty()),
+            attrs,
+            (point.count() as u64).fast_display(),
+            point.sum().fast_display(),
+            i64::from(native.schema).fast_display(),
+            native.zero_threshold.fast_display(),
+            native.zero_count.fast_display(),
+        )?;
+        if !native.negative_spans.is_empty() {
+            ctx.f.write_str(",negative_spans:")?;
+            write_spans(&mut ctx.f, &native.negative_spans)?;
+            ctx.f.write_str(",negative_deltas:")?;
+            write_deltas(&mut ctx.f, &native.negative_deltas)?;
+        }
+        if !native.positive_spans.is_empty() {
+            ctx.f.write_str(",positive_spans:")?;
+            write_spans(&mut ctx.f, &native.positive_spans)?;
+            ctx.f.write_str(",positive_deltas:")?;
+            write_deltas(&mut ctx.f, &native.positive_deltas)?;
+        }
+        uwriteln!(ctx.f, "}}{}", time.with_start(start_time, ctx.options))?;
+        if let Some(created) = created {
+            uwriteln!(
+                ctx.f,
+                "{}{}}} {}{}",
+                SampleName::new(&ctx.name, ctx.quote_name, "_created", !attrs.is_empty()),
+                attrs,
+                created,
+                time,
+            )?;
+        }
+    }
+    Ok(())
+}
+
+fn write_spans<U: uWrite>(f: &mut U, spans: &[BucketSpan]) -> Result<(), U::Error> {
+    f.write_char('[')?;
+    for (i, span) in spans.iter().enumerate() {
+        if i > 0 {
+            f.write_char(',')?;
+        }
+        ufmt::uwrite!(
+            f,
+            "{}:{}",
+            i64::from(span.offset).fast_display(),
+            u64::from(span.length).fast_display()
+        )?;
+    }
+    f.write_char(']')
+}
+
+fn write_deltas<U: uWrite>(f: &mut U, deltas: &[i64]) -> Result<(), U::Error> {
+    f.write_char('[')?;
+    for (i, delta) in deltas.iter().enumerate() {
+        if i > 0 {
+            f.write_char(',')?;
+        }
+        ufmt::uwrite!(f, "{}", delta.fast_display())?;
+    }
+    f.write_char(']')
+}
+
+/// Write a cumulative sum. Sums with other temporalities are rejected by [`get_type`] beforehand.
+fn write_counter<T: FastDisplay + ToF64, U: uWrite>(
+    ctx: &mut Context<'_, U>,
+    sum: &impl SumLike<T>,
+) -> Result<(), U::Error> {
+    let attrs = &mut ctx.attr_buffer;
+    let scope_name_attrs = make_scope_attrs(ctx.scope_name, ctx.scope_version);
 
     let mut points: Vec<_> = sum.data_points().collect();
     points.sort_by_cached_key(|p| hash_attrs(p.attributes()));
 
-    let ts = to_timestamp(sum.time());
+    let time = SampleTimestamp::new(sum.time(), ctx.options);
 
     if sum.is_monotonic() {
         for point in points {
             attrs.clear();
-            let Ok(()) = write_attrs(attrs, point.attributes().chain(scope_name_attrs.iter()));
+            let Ok(()) = write_attrs(
+                attrs,
+                series_attrs(
+                    || point.attributes(),
+                    &scope_name_attrs,
+                    &ctx.resource_attrs,
+                    ctx.options.name_escaping,
+                ),
+                ctx.options.name_escaping,
+            );
+            let exemplar = has_exemplars(ctx.options)
+                .then(|| exemplar::latest(point.exemplars()))
+                .flatten();
             uwriteln!(
                 ctx.f,
-                "{}_total{{{}}} {} {}",
-                ctx.name,
+                "{}{}}} {}{}{}",
+                SampleName::new(
+                    &ctx.name,
+                    ctx.quote_name,
+                    ctx.total_suffix,
+                    !attrs.is_empty()
+                ),
                 attrs,
                 point.value().fast_display(),
-                ts,
+                time.with_start(sum.start_time(point), ctx.options),
+                ExemplarSuffix::new(exemplar, ctx.options),
             )?;
+            if let Some(created) = to_created(sum.start_time(point), ctx.options) {
+                uwriteln!(
+                    ctx.f,
+                    "{}{}}} {}{}",
+                    SampleName::new(&ctx.name, ctx.quote_name, "_created", !attrs.is_empty()),
+                    attrs,
+                    created,
+                    time,
+                )?;
+            }
         }
     } else {
         for point in points {
             attrs.clear();
-            let Ok(()) = write_attrs(attrs, point.attributes().chain(scope_name_attrs.iter()));
+            let Ok(()) = write_attrs(
+                attrs,
+                series_attrs(
+                    || point.attributes(),
+                    &scope_name_attrs,
+                    &ctx.resource_attrs,
+                    ctx.options.name_escaping,
+                ),
+                ctx.options.name_escaping,
+            );
             uwriteln!(
                 ctx.f,
-                "{}{{{}}} {} {}",
-                ctx.name,
+                "{}{}}} {}{}",
+                SampleName::new(&ctx.name, ctx.quote_name, "", !attrs.is_empty()),
                 attrs,
                 point.value().fast_display(),
-                ts,
+                time,
             )?;
         }
     }
@@ -412,17 +1292,26 @@ fn write_gauge<T: FastDisplay + Copy, U: uWrite>(
     gauge: &Gauge<T>,
 ) -> Result<(), U::Error> {
     let attrs = &mut ctx.attr_buffer;
-    let scope_name_attrs = make_scope_name_attrs(ctx.scope_name);
-    let ts = to_timestamp(gauge.time());
+    let scope_name_attrs = make_scope_attrs(ctx.scope_name, ctx.scope_version);
+    let ts = SampleTimestamp::new(gauge.time(), ctx.options);
     let mut points: Vec<_> = gauge.data_points().collect();
     points.sort_by_cached_key(|p| hash_attrs(p.attributes()));
     for point in points {
         attrs.clear();
-        let Ok(()) = write_attrs(attrs, point.attributes().chain(scope_name_attrs.iter()));
+        let Ok(()) = write_attrs(
+            attrs,
+            series_attrs(
+                || point.attributes(),
+                &scope_name_attrs,
+                &ctx.resource_attrs,
+                ctx.options.name_escaping,
+            ),
+            ctx.options.name_escaping,
+        );
         uwriteln!(
             ctx.f,
-            "{}{{{}}} {} {}",
-            ctx.name,
+            "{}{}}} {}{}",
+            SampleName::new(&ctx.name, ctx.quote_name, "", !attrs.is_empty()),
             attrs,
             point.value().fast_display(),
             ts,
@@ -431,46 +1320,174 @@ fn write_gauge<T: FastDisplay + Copy, U: uWrite>(
     Ok(())
 }
 
-/// Makes an `otel_scope_name` attribute with the specified `scope_name` if the `otel_scope_info` feature is active.
+/// Makes the `otel_scope_name` attribute and, if given, the `otel_scope_version` attribute labelling the series of a
+/// scope. There are no attributes for an empty `scope_name`.
 #[inline]
-fn make_scope_name_attrs(scope_name: &str) -> Option<KeyValue> {
-    if cfg!(feature = "otel_scope_info") {
-        Some(KeyValue::new("otel_scope_name", scope_name.to_owned()))
-    } else {
-        None
+fn make_scope_attrs(scope_name: &str, scope_version: Option<&str>) -> Vec<KeyValue> {
+    if scope_name.is_empty() {
+        return Vec::new();
+    }
+    let mut attrs = vec![KeyValue::new("otel_scope_name", scope_name.to_owned())];
+    if let Some(version) = scope_version {
+        attrs.push(KeyValue::new("otel_scope_version", version.to_owned()));
+    }
+    attrs
+}
+
+/// Selects the resource attributes to copy onto every series according to
+/// [`ConvertOptions::with_promoted_resource_attributes`].
+fn promote_resource_attrs(
+    resource: &opentelemetry_sdk::Resource,
+    options: &ConvertOptions,
+) -> Vec<KeyValue> {
+    if options.promoted_resource_attributes.is_empty() {
+        return Vec::new();
     }
+    resource
+        .iter()
+        .filter(|(key, _)| {
+            options.promoted_resource_attributes.iter().any(|selected| {
+                match selected.strip_suffix('*') {
+                    Some(prefix) => key.as_str().starts_with(prefix),
+                    None => key.as_str() == selected,
+                }
+            })
+        })
+        .map(|(key, value)| KeyValue::new(key.clone(), value.clone()))
+        .collect()
+}
+
+/// Chains the attributes of a data point with the labels of its scope and the promoted resource attributes, leaving
+/// out the resource attributes whose label names collide with the former.
+fn series_attrs<'a, I: Iterator<Item = &'a KeyValue>>(
+    point_attrs: impl Fn() -> I,
+    scope_attrs: &'a [KeyValue],
+    resource_attrs: &'a [KeyValue],
+    escaping: NameEscaping,
+) -> impl Iterator<Item = &'a KeyValue> {
+    let taken: Vec<_> = if resource_attrs.is_empty() {
+        Vec::new()
+    } else {
+        point_attrs()
+            .chain(scope_attrs)
+            .map(|kv| sanitize_label_name(kv.key.as_str(), escaping))
+            .collect()
+    };
+    let resource_attrs = resource_attrs
+        .iter()
+        .filter(move |kv| !taken.contains(&sanitize_label_name(kv.key.as_str(), escaping)));
+    point_attrs().chain(scope_attrs).chain(resource_attrs)
 }
 
-/// Write the attribute string for attrs. Does not write curly braces.
+/// Write the attribute string for attrs, with label names sanitized according to `escaping`. Does not write curly
+/// braces.
 fn write_attrs<'a, I: Iterator<Item = &'a KeyValue>, U: uWrite>(
     f: &mut U,
     attrs: I,
+    escaping: NameEscaping,
 ) -> Result<(), U::Error> {
-    write_attrs_tuple(f, attrs.map(|kv| (&kv.key, &kv.value)))
+    write_attrs_tuple(f, attrs.map(|kv| (&kv.key, &kv.value)), escaping)
 }
 
 fn write_attrs_tuple<'a, I: Iterator<Item = (&'a Key, &'a Value)>, U: uWrite>(
     f: &mut U,
     attrs: I,
+    escaping: NameEscaping,
 ) -> Result<(), U::Error> {
     let mut first = true;
 
-    let mut attrs: Vec<_> = attrs.collect();
-    attrs.sort_unstable_by_key(|attr| attr.0);
-
-    for attr in attrs {
+    for (name, value) in sanitize_labels(attrs, escaping) {
         if !first {
             f.write_char(',')?;
         }
-        write_sanitized_name(f, attr.0.as_str())?;
-        f.write_str("=\"")?;
-        write_escaped(f, &attr.1.as_str())?;
-        f.write_char('"')?;
+        write_label(f, &name, &value, escaping)?;
         first = false;
     }
     Ok(())
 }
 
+/// Writes a single label of a sanitized `name` and `value`, quoting the name if `escaping` requires it.
+fn write_label<U: uWrite>(
+    f: &mut U,
+    name: &str,
+    value: &str,
+    escaping: NameEscaping,
+) -> Result<(), U::Error> {
+    if escaping == NameEscaping::AllowUtf8 && !is_legacy_name(name, true) {
+        f.write_char('"')?;
+        write_escaped(f, name)?;
+        f.write_char('"')?;
+    } else {
+        f.write_str(name)?;
+    }
+    f.write_str("=\"")?;
+    write_escaped(f, value)?;
+    f.write_char('"')
+}
+
+/// Converts attributes into labels with names sanitized according to `escaping`, sorted by their name.
+///
+/// Attributes whose keys sanitize to the same label name are merged into one label, their values concatenated with
+/// `;` in the lexicographical order of the original keys according to the
+/// [spec](https://github.com/open-telemetry/opentelemetry-specification/blob/v1.45.0/specification/compatibility/prometheus_and_openmetrics.md#metric-attributes).
+fn sanitize_labels<'a>(
+    attrs: impl Iterator<Item = (&'a Key, &'a Value)>,
+    escaping: NameEscaping,
+) -> Vec<(Cow<'a, str>, Cow<'a, str>)> {
+    let mut labels: Vec<_> = attrs
+        .map(|(key, value)| {
+            (
+                sanitize_label_name(key.as_str(), escaping),
+                key.as_str(),
+                value.as_str(),
+            )
+        })
+        .collect();
+    labels.sort_unstable_by(|a, b| (&a.0, a.1).cmp(&(&b.0, b.1)));
+
+    let mut merged: Vec<(Cow<str>, Cow<str>)> = Vec::with_capacity(labels.len());
+    for (name, _, value) in labels {
+        match merged.last_mut() {
+            Some((last_name, values)) if *last_name == name => {
+                let values = values.to_mut();
+                values.push(';');
+                values.push_str(&value);
+            }
+            _ => merged.push((name, value)),
+        }
+    }
+    merged
+}
+
+/// Converts an attribute `key` into a label name, replacing each illegal character with an underscore.
+///
+/// Like the OpenTelemetry Collector, keys starting with a digit are prefixed with `key_` and keys starting with an
+/// underscore after sanitization with `key`, so that no label name uses the `__` prefix reserved by Prometheus.
+///
+/// Other than [`NameEscaping::Underscores`], the keys are escaped with [`escape_name`] instead.
+fn sanitize_label_name(key: &str, escaping: NameEscaping) -> Cow<'_, str> {
+    if escaping != NameEscaping::Underscores {
+        return escape_name(key, escaping, true);
+    }
+    let is_valid = key.starts_with(|c: char| c.is_ascii_alphabetic())
+        && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if is_valid {
+        return Cow::Borrowed(key);
+    }
+
+    let mut name = String::with_capacity(key.len() + 4);
+    if key.starts_with(|c: char| c.is_ascii_digit()) {
+        name.push_str("key_");
+    } else if !key.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        name.push_str("key");
+    }
+    for c in key.chars() {
+        // Allowed characters are `a-z A-Z 0-9 _`
+        name.push(if c.is_ascii_alphanumeric() { c } else { '_' });
+    }
+    Cow::Owned(name)
+}
+
 /// Calculates a hash of the [`KeyValue`] pairs which is invariant under reordering of the [`KeyValue`]s within the [`Iterator`].
 fn hash_attrs<'a, I: Iterator<Item = &'a KeyValue>>(attrs: I) -> u64 {
     let mut hash = 0;
@@ -486,30 +1503,43 @@ fn hash_attrs<'a, I: Iterator<Item = &'a KeyValue>>(attrs: I) -> u64 {
 /// Writes to `f` the contents of `value` as an escaped string. Does not put quotes around the value.
 /// The chars to escape are `\`, `"` and `\n`.
 fn write_escaped<U: uWrite>(f: &mut U, value: &str) -> Result<(), U::Error> {
+    write_escaped_chars(f, value, true)
+}
+
+/// Writes to `f` the contents of `value` escaped as a docstring of the Prometheus text format.
+/// Unlike [`write_escaped`], `"` is not escaped.
+fn write_escaped_help<U: uWrite>(f: &mut U, value: &str) -> Result<(), U::Error> {
+    write_escaped_chars(f, value, false)
+}
+
+fn write_escaped_chars<U: uWrite>(f: &mut U, value: &str, quotes: bool) -> Result<(), U::Error> {
     #[inline]
-    fn next_escape_char(bytes: &[u8]) -> Option<usize> {
+    fn next_escape_char(bytes: &[u8], quotes: bool) -> Option<usize> {
         #[cfg(feature = "fast")]
-        return memchr::memchr3(b'\\', b'"', b'\n', bytes);
+        return if quotes {
+            memchr::memchr3(b'\\', b'"', b'\n', bytes)
+        } else {
+            memchr::memchr2(b'\\', b'\n', bytes)
+        };
         #[cfg(not(feature = "fast"))]
         bytes
             .iter()
-            .position(|&byte| byte == b'\\' || byte == b'"' || byte == b'\n')
+            .position(|&byte| byte == b'\\' || (quotes && byte == b'"') || byte == b'\n')
     }
 
-    let mut bytes = value.as_bytes();
+    let mut rest = value;
 
-    while let Some(next_escape) = next_escape_char(bytes) {
-        let (head, tail) = bytes.split_at(next_escape);
-        f.write_str(str::from_utf8(head).expect("escapable chars should be on a char boundary"))?;
-        match tail[0] {
-            b'\\' => f.write_str("\\\\"),
-            b'"' => f.write_str("\\\""),
-            b'\n' => f.write_str("\\n"),
-            _ => unreachable!("next_escape_char should find one of the 3 escapable chars"),
-        }?;
-        bytes = &tail[1..];
+    // The escapable chars are ASCII, so slicing around them keeps to char boundaries
+    while let Some(next_escape) = next_escape_char(rest.as_bytes(), quotes) {
+        f.write_str(&rest[..next_escape])?;
+        f.write_str(match rest.as_bytes()[next_escape] {
+            b'\\' => "\\\\",
+            b'"' => "\\\"",
+            _ => "\\n",
+        })?;
+        rest = &rest[next_escape + 1..];
     }
-    f.write_str(str::from_utf8(bytes).expect("escaped string should be valid utf-8"))
+    f.write_str(rest)
 }
 
 /// Write `name` as an OpenMetrics metrics name, replacing any illegal characters with underscore according to the
@@ -538,11 +1568,154 @@ fn write_sanitized_name<U: uWrite>(f: &mut U, name: &str) -> Result<(), U::Error
     Ok(())
 }
 
-/// Get a [`Display`] implementation which shows [`SystemTime`] as a unix timestamp in float seconds.
-fn to_timestamp(time: SystemTime) -> impl uDisplay {
-    let ts = time
-        .duration_since(SystemTime::UNIX_EPOCH)
-        .expect("Time went backwards")
-        .as_secs_f64();
-    ts.fast_display()
+/// Get a [`uDisplay`] implementation which shows [`SystemTime`] as a unix timestamp in float seconds, or in integer
+/// milliseconds for [`TextFormat::Prometheus`]. Times before the epoch are negative.
+fn to_timestamp(time: SystemTime, format: TextFormat) -> Timestamp {
+    match format {
+        TextFormat::OpenMetrics | TextFormat::OpenMetrics2 => {
+            Timestamp::Seconds(seconds_since_epoch(time))
+        }
+        TextFormat::Prometheus => Timestamp::Milliseconds(millis_since_epoch(time)),
+    }
+}
+
+/// The seconds between the unix epoch and `time`, negative for times before the epoch.
+fn seconds_since_epoch(time: SystemTime) -> f64 {
+    match time.duration_since(SystemTime::UNIX_EPOCH) {
+        Ok(since_epoch) => since_epoch.as_secs_f64(),
+        Err(before_epoch) => -before_epoch.duration().as_secs_f64(),
+    }
+}
+
+/// The whole milliseconds between the unix epoch and `time`, negative for times before the epoch.
+fn millis_since_epoch(time: SystemTime) -> i64 {
+    match time.duration_since(SystemTime::UNIX_EPOCH) {
+        Ok(since_epoch) => since_epoch.as_millis().try_into().unwrap_or(i64::MAX),
+        Err(before_epoch) => (-i128::try_from(before_epoch.duration().as_millis())
+            .unwrap_or(i128::MAX))
+        .try_into()
+        .unwrap_or(i64::MIN),
+    }
+}
+
+/// A [`uDisplay`] implementation which shows the name of a sample up to its labels, like `name_suffix{` or, if the
+/// name must be quoted, `{"name_suffix",` where the `,` is only shown if labels follow.
+struct SampleName<'a> {
+    name: &'a str,
+    quoted: bool,
+    suffix: &'static str,
+    labels_follow: bool,
+}
+
+impl<'a> SampleName<'a> {
+    #[inline]
+    fn new(name: &'a str, quoted: bool, suffix: &'static str, labels_follow: bool) -> Self {
+        SampleName {
+            name,
+            quoted,
+            suffix,
+            labels_follow,
+        }
+    }
+}
+
+impl uDisplay for SampleName<'_> {
+    fn fmt<W>(&self, f: &mut ufmt::Formatter<'_, W>) -> Result<(), W::Error>
+    where
+        W: uWrite + ?Sized,
+    {
+        if !self.quoted {
+            f.write_str(self.name)?;
+            f.write_str(self.suffix)?;
+            return f.write_str("{");
+        }
+        f.write_str("{\"")?;
+        f.write_str(self.name)?;
+        f.write_str(self.suffix)?;
+        f.write_str(if self.labels_follow { "\"," } else { "\"" })
+    }
+}
+
+/// Whether exemplars are written according to `options`.
+fn has_exemplars(options: &ConvertOptions) -> bool {
+    options.exemplars && options.text_format.is_openmetrics()
+}
+
+/// Get the timestamp of the `_created` sample of a series which started at `start_time`, if it is written according
+/// to `options`.
+fn to_created(start_time: SystemTime, options: &ConvertOptions) -> Option<Timestamp> {
+    (options.text_format.is_openmetrics() && !has_start_timestamps(options))
+        .then(|| to_timestamp(start_time, TextFormat::OpenMetrics))
+}
+
+/// Whether start timestamps are written instead of `_created` samples according to `options`, which only
+/// [`TextFormat::OpenMetrics2`] can.
+fn has_start_timestamps(options: &ConvertOptions) -> bool {
+    options.start_timestamps && options.text_format == TextFormat::OpenMetrics2
+}
+
+/// A [`uDisplay`] implementation which shows the timestamp of a sample with a leading space, followed by its start
+/// timestamp in the `st@` syntax of the OpenMetrics 2.0 draft. Both are optional.
+#[derive(Clone, Copy)]
+struct SampleTimestamp {
+    time: Option<Timestamp>,
+    start: Option<Timestamp>,
+}
+
+impl SampleTimestamp {
+    /// The timestamp of a sample taken at `time`, if it is written according to `options`.
+    fn new(time: SystemTime, options: &ConvertOptions) -> Self {
+        let time = match (options.timestamps, options.text_format) {
+            (Timestamps::Omit, _) => None,
+            (Timestamps::Seconds, _) => Some(Timestamp::Seconds(seconds_since_epoch(time))),
+            (Timestamps::Milliseconds, _) => {
+                Some(Timestamp::Milliseconds(millis_since_epoch(time)))
+            }
+            (Timestamps::Native, format) => Some(to_timestamp(time, format)),
+        };
+        SampleTimestamp { time, start: None }
+    }
+
+    /// Adds the `st@` start timestamp of a series which started at `start_time`, if it is written according to
+    /// `options`.
+    fn with_start(self, start_time: SystemTime, options: &ConvertOptions) -> Self {
+        let start = has_start_timestamps(options)
+            .then(|| to_timestamp(start_time, TextFormat::OpenMetrics));
+        SampleTimestamp { start, ..self }
+    }
+}
+
+impl uDisplay for SampleTimestamp {
+    fn fmt<W>(&self, f: &mut ufmt::Formatter<'_, W>) -> Result<(), W::Error>
+    where
+        W: uWrite + ?Sized,
+    {
+        if let Some(time) = self.time {
+            f.write_str(" ")?;
+            time.fmt(f)?;
+        }
+        if let Some(start) = self.start {
+            f.write_str(" st@")?;
+            start.fmt(f)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Timestamp {
+    Seconds(f64),
+    Milliseconds(i64),
+}
+
+impl uDisplay for Timestamp {
+    fn fmt<W>(&self, f: &mut ufmt::Formatter<'_, W>) -> Result<(), W::Error>
+    where
+        W: uWrite + ?Sized,
+    {
+        match self {
+            Timestamp::Seconds(seconds) => seconds.fast_display().fmt(f),
+            Timestamp::Milliseconds(millis) => millis.fast_display().fmt(f),
+        }
+    }
 }
@@ -0,0 +1,506 @@
+//! Conversion of delta temporality sums and histograms into cumulative series.
+//!
+//! OpenMetrics only knows cumulative counters and histograms. When the SDK reports deltas, the
+//! [`DeltaAccumulator`] folds each reported interval into running totals, keyed by the instrumentation scope, the
+//! metric name and the attribute set of each series.
+
+use std::collections::HashMap;
+use std::time::SystemTime;
+
+use opentelemetry::{InstrumentationScope, KeyValue};
+use opentelemetry_sdk::metrics::Temporality;
+use opentelemetry_sdk::metrics::data::{
+    AggregatedMetrics, Exemplar, Metric, MetricData, ResourceMetrics,
+};
+
+use super::data::{ExemplarLike, HistogramLike, HistogramPoint, NumberPoint, SumLike};
+
+/// Accumulates delta temporality [`Sum`](opentelemetry_sdk::metrics::data::Sum)s and
+/// [`Histogram`](opentelemetry_sdk::metrics::data::Histogram)s into cumulative series.
+///
+/// Metrics with cumulative temporality and gauges are passed through unchanged. Series which were seen once are
+/// kept (and exported) for the lifetime of the accumulator. A series whose total exceeds the range of its number type
+/// starts over with the latest delta, which is reported as [`Error::Overflow`](crate::Error::Overflow) when the
+/// accumulated metrics are written.
+///
+/// ```
+/// # use opentelemetry_sdk::metrics::data::ResourceMetrics;
+/// use ottotom::convert::{DeltaAccumulator, WriteOpenMetrics};
+///
+/// let mut accumulator = DeltaAccumulator::default();
+/// # let metrics = ResourceMetrics::default();
+/// // `metrics` was collected by a reader with delta temporality
+/// let text = accumulator.accumulate(&metrics).to_openmetrics_string().unwrap();
+/// ```
+#[derive(Debug, Default)]
+pub struct DeltaAccumulator {
+    scopes: HashMap<InstrumentationScope, HashMap<String, AccumulatedMetric>>,
+}
+
+/// A [`ResourceMetrics`] combined with the cumulative state of a [`DeltaAccumulator`], ready to be written with
+/// [`super::WriteOpenMetrics`].
+#[derive(Debug, Clone, Copy)]
+pub struct Accumulated<'a> {
+    pub(super) metrics: &'a ResourceMetrics,
+    pub(super) accumulator: &'a DeltaAccumulator,
+}
+
+#[derive(Debug)]
+pub(super) struct AccumulatedMetric {
+    pub(super) description: String,
+    pub(super) unit: String,
+    pub(super) data: AccumulatedData,
+    /// whether the latest accumulation reset a series whose cumulative value overflowed
+    pub(super) overflowed: bool,
+}
+
+#[derive(Debug)]
+pub(super) enum AccumulatedData {
+    F64(CumulativeData<f64>),
+    U64(CumulativeData<u64>),
+    I64(CumulativeData<i64>),
+}
+
+#[derive(Debug)]
+pub(super) enum CumulativeData<T> {
+    Sum(CumulativeSum<T>),
+    Histogram(CumulativeHistogram<T>),
+}
+
+/// The cumulative series of a sum, with exemplars of type `E` like those of the SDK.
+#[derive(Debug)]
+pub(super) struct CumulativeSum<T, E = Exemplar<T>> {
+    time: SystemTime,
+    is_monotonic: bool,
+    series: HashMap<Vec<KeyValue>, SumSeries<T, E>>,
+}
+
+#[derive(Debug)]
+pub(super) struct SumSeries<T, E = Exemplar<T>> {
+    attributes: Vec<KeyValue>,
+    start_time: SystemTime,
+    value: T,
+    /// the exemplars of the latest delta which had any
+    exemplars: Vec<E>,
+}
+
+/// The cumulative series of a histogram, with exemplars of type `E` like those of the SDK.
+#[derive(Debug)]
+pub(super) struct CumulativeHistogram<T, E = Exemplar<T>> {
+    time: SystemTime,
+    series: HashMap<Vec<KeyValue>, HistogramSeries<T, E>>,
+}
+
+#[derive(Debug)]
+pub(super) struct HistogramSeries<T, E = Exemplar<T>> {
+    attributes: Vec<KeyValue>,
+    start_time: SystemTime,
+    count: u64,
+    sum: T,
+    min: Option<T>,
+    max: Option<T>,
+    bounds: Vec<f64>,
+    bucket_counts: Vec<u64>,
+    /// the exemplars of the latest delta which had any
+    exemplars: Vec<E>,
+}
+
+impl DeltaAccumulator {
+    /// Folds all delta temporality sums and histograms of `metrics` into the running cumulative series and returns
+    /// a view of `metrics` in which those are replaced by their cumulative counterparts.
+    ///
+    /// Metrics whose reported interval does not end after the last accumulated one are ignored, so accumulating
+    /// the same [`ResourceMetrics`] twice does not count its values twice.
+    pub fn accumulate<'a>(&'a mut self, metrics: &'a ResourceMetrics) -> Accumulated<'a> {
+        for accumulated in self.scopes.values_mut().flat_map(HashMap::values_mut) {
+            accumulated.overflowed = false;
+        }
+        for scope in metrics.scope_metrics() {
+            for metric in scope.metrics().filter(|metric| is_delta(metric.data())) {
+                self.update(scope.scope(), metric);
+            }
+        }
+        Accumulated {
+            metrics,
+            accumulator: self,
+        }
+    }
+
+    /// Discards all accumulated series.
+    pub fn clear(&mut self) {
+        self.scopes.clear();
+    }
+
+    pub(super) fn scopes(
+        &self,
+    ) -> impl Iterator<
+        Item = (
+            &InstrumentationScope,
+            impl Iterator<Item = (&str, &AccumulatedMetric)>,
+        ),
+    > {
+        self.scopes.iter().map(|(scope, metrics)| {
+            (
+                scope,
+                metrics.iter().map(|(name, metric)| (name.as_str(), metric)),
+            )
+        })
+    }
+
+    fn update(&mut self, scope: &InstrumentationScope, metric: &Metric) {
+        let metrics = match self.scopes.get_mut(scope) {
+            Some(metrics) => metrics,
+            None => self.scopes.entry(scope.clone()).or_default(),
+        };
+        let accumulated = match metrics.get_mut(metric.name()) {
+            Some(accumulated) => accumulated,
+            None => metrics
+                .entry(metric.name().to_owned())
+                .or_insert_with(|| AccumulatedMetric::new(metric.data())),
+        };
+        accumulated.description.clear();
+        accumulated.description.push_str(metric.description());
+        accumulated.unit.clear();
+        accumulated.unit.push_str(metric.unit());
+        accumulated.update(metric.data());
+    }
+}
+
+/// Returns whether `data` is a sum or histogram with delta temporality, which need to be accumulated before being
+/// written as OpenMetrics.
+pub(super) fn is_delta(data: &AggregatedMetrics) -> bool {
+    fn is_delta_inner<T>(data: &MetricData<T>) -> bool {
+        match data {
+            MetricData::Sum(sum) => sum.temporality() == Temporality::Delta,
+            MetricData::Histogram(histogram) => histogram.temporality() == Temporality::Delta,
+            _ => false,
+        }
+    }
+    match data {
+        AggregatedMetrics::F64(data) => is_delta_inner(data),
+        AggregatedMetrics::U64(data) => is_delta_inner(data),
+        AggregatedMetrics::I64(data) => is_delta_inner(data),
+    }
+}
+
+impl AccumulatedMetric {
+    fn new(data: &AggregatedMetrics) -> Self {
+        let data = match data {
+            AggregatedMetrics::F64(data) => AccumulatedData::F64(CumulativeData::new(data)),
+            AggregatedMetrics::U64(data) => AccumulatedData::U64(CumulativeData::new(data)),
+            AggregatedMetrics::I64(data) => AccumulatedData::I64(CumulativeData::new(data)),
+        };
+        AccumulatedMetric {
+            description: String::new(),
+            unit: String::new(),
+            data,
+            overflowed: false,
+        }
+    }
+
+    fn update(&mut self, delta: &AggregatedMetrics) {
+        match (&mut self.data, delta) {
+            (AccumulatedData::F64(data), AggregatedMetrics::F64(delta)) => {
+                self.overflowed |= data.update(delta)
+            }
+            (AccumulatedData::U64(data), AggregatedMetrics::U64(delta)) => {
+                self.overflowed |= data.update(delta)
+            }
+            (AccumulatedData::I64(data), AggregatedMetrics::I64(delta)) => {
+                self.overflowed |= data.update(delta)
+            }
+            _ => {
+                // The instrument changed its number type, start over
+                self.data = AccumulatedMetric::new(delta).data;
+                self.update(delta);
+            }
+        }
+    }
+}
+
+impl<T: CheckedAdd + Default + PartialOrd + 'static> CumulativeData<T> {
+    fn new(data: &MetricData<T>) -> Self {
+        match data {
+            MetricData::Sum(sum) => CumulativeData::Sum(CumulativeSum::new(sum.is_monotonic())),
+            _ => CumulativeData::Histogram(CumulativeHistogram::new()),
+        }
+    }
+
+    /// Adds the `delta` to the series, returning whether a series overflowed and was reset.
+    fn update(&mut self, delta: &MetricData<T>) -> bool {
+        match (&mut *self, delta) {
+            (CumulativeData::Sum(sum), MetricData::Sum(delta)) => sum.update(delta),
+            (CumulativeData::Histogram(histogram), MetricData::Histogram(delta)) => {
+                histogram.update(delta)
+            }
+            (_, MetricData::Sum(_) | MetricData::Histogram(_)) => {
+                // The instrument changed its kind, start over
+                *self = CumulativeData::new(delta);
+                self.update(delta)
+            }
+            _ => false,
+        }
+    }
+}
+
+impl<T: CheckedAdd, E: Clone> CumulativeSum<T, E> {
+    pub(super) fn new(is_monotonic: bool) -> Self {
+        CumulativeSum {
+            time: SystemTime::UNIX_EPOCH,
+            is_monotonic,
+            series: HashMap::new(),
+        }
+    }
+
+    /// Adds the points of the `delta` to their series, starting a series over if its value overflows.
+    ///
+    /// Returns whether any series overflowed.
+    pub(super) fn update<S>(&mut self, delta: &S) -> bool
+    where
+        S: SumLike<T>,
+        S::Point: NumberPoint<T, Exemplar = E>,
+    {
+        if delta.time() <= self.time {
+            return false;
+        }
+        self.time = delta.time();
+        self.is_monotonic = delta.is_monotonic();
+        let mut overflowed = false;
+        for point in delta.data_points() {
+            let attributes = sorted_attributes(point.attributes());
+            match self.series.get_mut(&attributes) {
+                Some(series) => {
+                    match series.value.checked_add(point.value()) {
+                        Some(value) => series.value = value,
+                        None => {
+                            series.start_time = delta.start_time(point);
+                            series.value = point.value();
+                            overflowed = true;
+                        }
+                    }
+                    update_exemplars(&mut series.exemplars, point.exemplars());
+                }
+                None => {
+                    let series = SumSeries {
+                        attributes: attributes.clone(),
+                        start_time: delta.start_time(point),
+                        value: point.value(),
+                        exemplars: point.exemplars().cloned().collect(),
+                    };
+                    self.series.insert(attributes, series);
+                }
+            }
+        }
+        overflowed
+    }
+}
+
+impl<T: CheckedAdd + Default + PartialOrd, E: Clone> CumulativeHistogram<T, E> {
+    pub(super) fn new() -> Self {
+        CumulativeHistogram {
+            time: SystemTime::UNIX_EPOCH,
+            series: HashMap::new(),
+        }
+    }
+
+    /// Adds the points of the `delta` to their series, starting a series over if its buckets changed or its count
+    /// or sum overflows.
+    ///
+    /// Returns whether any series overflowed.
+    pub(super) fn update<H>(&mut self, delta: &H) -> bool
+    where
+        H: HistogramLike<T>,
+        H::Point: HistogramPoint<T, Exemplar = E>,
+    {
+        if delta.time() <= self.time {
+            return false;
+        }
+        self.time = delta.time();
+        let mut overflowed = false;
+        for point in delta.data_points() {
+            let attributes = sorted_attributes(point.attributes());
+            let series = self
+                .series
+                .entry(attributes)
+                .or_insert_with_key(|attributes| HistogramSeries {
+                    attributes: attributes.clone(),
+                    start_time: delta.start_time(point),
+                    count: 0,
+                    sum: T::default(),
+                    min: None,
+                    max: None,
+                    bounds: point.bounds().collect(),
+                    bucket_counts: vec![0; point.bucket_counts().count()],
+                    exemplars: Vec::new(),
+                });
+
+            let count = series.count.checked_add(point.count());
+            let sum = series.sum.checked_add(point.sum());
+            match (count, sum) {
+                (Some(count), Some(sum)) if series.bounds.iter().copied().eq(point.bounds()) => {
+                    series.count = count;
+                    series.sum = sum;
+                    series.min = merge_extreme(series.min, point.min(), |a, b| a < b);
+                    series.max = merge_extreme(series.max, point.max(), |a, b| a > b);
+                    for (total, count) in
+                        std::iter::zip(&mut series.bucket_counts, point.bucket_counts())
+                    {
+                        *total += count;
+                    }
+                }
+                (count, sum) => {
+                    // Counts of differing buckets can not be added up, nor totals beyond the range of their type,
+                    // start the series over instead
+                    overflowed |= count.is_none() || sum.is_none();
+                    series.start_time = delta.start_time(point);
+                    series.count = point.count();
+                    series.sum = point.sum();
+                    series.min = point.min();
+                    series.max = point.max();
+                    series.bounds = point.bounds().collect();
+                    series.bucket_counts = point.bucket_counts().collect();
+                }
+            }
+            update_exemplars(&mut series.exemplars, point.exemplars());
+        }
+        overflowed
+    }
+}
+
+/// Replaces the `exemplars` of a series with those of a delta, unless the delta has none.
+fn update_exemplars<'a, E: Clone + 'a>(exemplars: &mut Vec<E>, delta: impl Iterator<Item = &'a E>) {
+    let mut delta = delta.peekable();
+    if delta.peek().is_some() {
+        exemplars.clear();
+        exemplars.extend(delta.cloned());
+    }
+}
+
+/// Returns `b` if it `exceeds` `a`, otherwise `a`, or whichever of both is present.
+fn merge_extreme<T: Copy>(a: Option<T>, b: Option<T>, exceeds: impl Fn(T, T) -> bool) -> Option<T> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(if exceeds(b, a) { b } else { a }),
+        (a, b) => a.or(b),
+    }
+}
+
+/// Addition of the numeric types of the SDK which detects overflows, as the integer totals of long running
+/// series can exceed their range.
+pub(super) trait CheckedAdd: Copy {
+    fn checked_add(self, other: Self) -> Option<Self>;
+}
+
+impl CheckedAdd for f64 {
+    fn checked_add(self, other: Self) -> Option<Self> {
+        Some(self + other)
+    }
+}
+
+impl CheckedAdd for u64 {
+    fn checked_add(self, other: Self) -> Option<Self> {
+        u64::checked_add(self, other)
+    }
+}
+
+impl CheckedAdd for i64 {
+    fn checked_add(self, other: Self) -> Option<Self> {
+        i64::checked_add(self, other)
+    }
+}
+
+/// Collects the attributes sorted by key, so they can be used to identify a series.
+fn sorted_attributes<'a>(attributes: impl Iterator<Item = &'a KeyValue>) -> Vec<KeyValue> {
+    let mut attributes: Vec<KeyValue> = attributes.cloned().collect();
+    attributes.sort_unstable_by(|a, b| a.key.cmp(&b.key));
+    attributes
+}
+
+impl<T: Copy + 'static, E: ExemplarLike<T> + 'static> SumLike<T> for CumulativeSum<T, E> {
+    type Point = SumSeries<T, E>;
+
+    fn data_points(&self) -> impl Iterator<Item = &Self::Point> {
+        self.series.values()
+    }
+
+    fn start_time(&self, point: &Self::Point) -> SystemTime {
+        point.start_time
+    }
+
+    fn time(&self) -> SystemTime {
+        self.time
+    }
+
+    fn is_monotonic(&self) -> bool {
+        self.is_monotonic
+    }
+}
+
+impl<T: Copy, E: ExemplarLike<T> + 'static> NumberPoint<T> for SumSeries<T, E> {
+    type Exemplar = E;
+
+    fn attributes(&self) -> impl Iterator<Item = &KeyValue> {
+        self.attributes.iter()
+    }
+
+    fn value(&self) -> T {
+        self.value
+    }
+
+    fn exemplars(&self) -> impl Iterator<Item = &Self::Exemplar> {
+        self.exemplars.iter()
+    }
+}
+
+impl<T: Copy + 'static, E: ExemplarLike<T> + 'static> HistogramLike<T>
+    for CumulativeHistogram<T, E>
+{
+    type Point = HistogramSeries<T, E>;
+
+    fn data_points(&self) -> impl Iterator<Item = &Self::Point> {
+        self.series.values()
+    }
+
+    fn start_time(&self, point: &Self::Point) -> SystemTime {
+        point.start_time
+    }
+
+    fn time(&self) -> SystemTime {
+        self.time
+    }
+}
+
+impl<T: Copy, E: ExemplarLike<T> + 'static> HistogramPoint<T> for HistogramSeries<T, E> {
+    type Exemplar = E;
+
+    fn attributes(&self) -> impl Iterator<Item = &KeyValue> {
+        self.attributes.iter()
+    }
+
+    fn count(&self) -> u64 {
+        self.count
+    }
+
+    fn sum(&self) -> T {
+        self.sum
+    }
+
+    fn min(&self) -> Option<T> {
+        self.min
+    }
+
+    fn max(&self) -> Option<T> {
+        self.max
+    }
+
+    fn bounds(&self) -> impl Iterator<Item = f64> {
+        self.bounds.iter().copied()
+    }
+
+    fn bucket_counts(&self) -> impl Iterator<Item = u64> {
+        self.bucket_counts.iter().copied()
+    }
+
+    fn exemplars(&self) -> impl Iterator<Item = &Self::Exemplar> {
+        self.exemplars.iter()
+    }
+}
@@ -0,0 +1,375 @@
+//! Read-only views on aggregated metric data.
+//!
+//! The writers in [`super`] are generic over these traits so that they can render both the data produced by the
+//! SDK and the cumulative series kept by the [`super::DeltaAccumulator`].
+
+use std::time::SystemTime;
+
+use opentelemetry::KeyValue;
+use opentelemetry_sdk::metrics::data::{
+    Exemplar, Histogram, HistogramDataPoint, Sum, SumDataPoint,
+};
+
+/// A sum aggregation, see [`Sum`].
+pub(crate) trait SumLike<T> {
+    type Point: NumberPoint<T> + 'static;
+    fn data_points(&self) -> impl Iterator<Item = &Self::Point>;
+    /// The start of the interval the value of `point` was aggregated over.
+    fn start_time(&self, point: &Self::Point) -> SystemTime;
+    fn time(&self) -> SystemTime;
+    fn is_monotonic(&self) -> bool;
+}
+
+/// A single data point of a sum, see [`SumDataPoint`].
+pub(crate) trait NumberPoint<T> {
+    type Exemplar: ExemplarLike<T> + 'static;
+    fn attributes(&self) -> impl Iterator<Item = &KeyValue>;
+    fn value(&self) -> T;
+    fn exemplars(&self) -> impl Iterator<Item = &Self::Exemplar>;
+}
+
+/// A histogram aggregation, see [`Histogram`].
+pub(crate) trait HistogramLike<T> {
+    type Point: HistogramPoint<T> + 'static;
+    fn data_points(&self) -> impl Iterator<Item = &Self::Point>;
+    /// The start of the interval the values of `point` were aggregated over.
+    fn start_time(&self, point: &Self::Point) -> SystemTime;
+    fn time(&self) -> SystemTime;
+}
+
+/// A single data point of a histogram, see [`HistogramDataPoint`].
+pub(crate) trait HistogramPoint<T> {
+    type Exemplar: ExemplarLike<T> + 'static;
+    fn attributes(&self) -> impl Iterator<Item = &KeyValue>;
+    fn count(&self) -> u64;
+    fn sum(&self) -> T;
+    fn min(&self) -> Option<T>;
+    fn max(&self) -> Option<T>;
+    fn bounds(&self) -> impl Iterator<Item = f64>;
+    fn bucket_counts(&self) -> impl Iterator<Item = u64>;
+    fn exemplars(&self) -> impl Iterator<Item = &Self::Exemplar>;
+}
+
+/// An exemplar of a data point, see [`Exemplar`].
+pub(crate) trait ExemplarLike<T> {
+    fn filtered_attributes(&self) -> impl Iterator<Item = &KeyValue>;
+    fn value(&self) -> T;
+    fn time(&self) -> SystemTime;
+    /// The ID of the trace the exemplar was recorded in, all zeros if there was none.
+    fn trace_id(&self) -> [u8; 16];
+    fn span_id(&self) -> [u8; 8];
+}
+
+/// The numeric types of the SDK, which are compared with the `f64` bounds of histogram buckets and written as the
+/// doubles of the protobuf format.
+pub(crate) trait ToF64: Copy {
+    fn to_f64(self) -> f64;
+}
+
+impl ToF64 for f64 {
+    fn to_f64(self) -> f64 {
+        self
+    }
+}
+
+impl ToF64 for u64 {
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+}
+
+impl ToF64 for i64 {
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+}
+
+impl<T: Copy + 'static> SumLike<T> for Sum<T> {
+    type Point = SumDataPoint<T>;
+
+    fn data_points(&self) -> impl Iterator<Item = &Self::Point> {
+        Sum::data_points(self)
+    }
+
+    fn start_time(&self, _point: &Self::Point) -> SystemTime {
+        Sum::start_time(self)
+    }
+
+    fn time(&self) -> SystemTime {
+        Sum::time(self)
+    }
+
+    fn is_monotonic(&self) -> bool {
+        Sum::is_monotonic(self)
+    }
+}
+
+impl<T: Copy + 'static> NumberPoint<T> for SumDataPoint<T> {
+    type Exemplar = Exemplar<T>;
+
+    fn attributes(&self) -> impl Iterator<Item = &KeyValue> {
+        SumDataPoint::attributes(self)
+    }
+
+    fn value(&self) -> T {
+        SumDataPoint::value(self)
+    }
+
+    fn exemplars(&self) -> impl Iterator<Item = &Self::Exemplar> {
+        SumDataPoint::exemplars(self)
+    }
+}
+
+impl<T: Copy + 'static> HistogramLike<T> for Histogram<T> {
+    type Point = HistogramDataPoint<T>;
+
+    fn data_points(&self) -> impl Iterator<Item = &Self::Point> {
+        Histogram::data_points(self)
+    }
+
+    fn start_time(&self, _point: &Self::Point) -> SystemTime {
+        Histogram::start_time(self)
+    }
+
+    fn time(&self) -> SystemTime {
+        Histogram::time(self)
+    }
+}
+
+impl<T: Copy + 'static> HistogramPoint<T> for HistogramDataPoint<T> {
+    type Exemplar = Exemplar<T>;
+
+    fn attributes(&self) -> impl Iterator<Item = &KeyValue> {
+        HistogramDataPoint::attributes(self)
+    }
+
+    fn count(&self) -> u64 {
+        HistogramDataPoint::count(self)
+    }
+
+    fn sum(&self) -> T {
+        HistogramDataPoint::sum(self)
+    }
+
+    fn min(&self) -> Option<T> {
+        HistogramDataPoint::min(self)
+    }
+
+    fn max(&self) -> Option<T> {
+        HistogramDataPoint::max(self)
+    }
+
+    fn bounds(&self) -> impl Iterator<Item = f64> {
+        HistogramDataPoint::bounds(self)
+    }
+
+    fn bucket_counts(&self) -> impl Iterator<Item = u64> {
+        HistogramDataPoint::bucket_counts(self)
+    }
+
+    fn exemplars(&self) -> impl Iterator<Item = &Self::Exemplar> {
+        HistogramDataPoint::exemplars(self)
+    }
+}
+
+impl<T: Copy> ExemplarLike<T> for Exemplar<T> {
+    fn filtered_attributes(&self) -> impl Iterator<Item = &KeyValue> {
+        Exemplar::filtered_attributes(self)
+    }
+
+    fn value(&self) -> T {
+        self.value
+    }
+
+    fn time(&self) -> SystemTime {
+        Exemplar::time(self)
+    }
+
+    fn trace_id(&self) -> [u8; 16] {
+        *Exemplar::trace_id(self)
+    }
+
+    fn span_id(&self) -> [u8; 8] {
+        *Exemplar::span_id(self)
+    }
+}
+
+/// Data with exemplars, which the SDK does not record so far.
+#[cfg(test)]
+pub(super) mod test {
+    use std::time::{Duration, SystemTime};
+
+    use opentelemetry::KeyValue;
+
+    use super::*;
+
+    /// The time `secs` seconds after the epoch.
+    pub(crate) fn at(secs: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    #[derive(Debug, Clone)]
+    pub(crate) struct TestExemplar {
+        pub(crate) attributes: Vec<KeyValue>,
+        pub(crate) value: f64,
+        pub(crate) time: SystemTime,
+        pub(crate) trace_id: [u8; 16],
+    }
+
+    impl TestExemplar {
+        pub(crate) fn new(value: f64, secs: u64) -> Self {
+            TestExemplar {
+                attributes: Vec::new(),
+                value,
+                time: at(secs),
+                trace_id: [0; 16],
+            }
+        }
+
+        /// An exemplar recorded in the trace with IDs of repeated `byte`s.
+        pub(crate) fn in_trace(value: f64, secs: u64, byte: u8) -> Self {
+            TestExemplar {
+                trace_id: [byte; 16],
+                ..TestExemplar::new(value, secs)
+            }
+        }
+    }
+
+    impl ExemplarLike<f64> for TestExemplar {
+        fn filtered_attributes(&self) -> impl Iterator<Item = &KeyValue> {
+            self.attributes.iter()
+        }
+
+        fn value(&self) -> f64 {
+            self.value
+        }
+
+        fn time(&self) -> SystemTime {
+            self.time
+        }
+
+        fn trace_id(&self) -> [u8; 16] {
+            self.trace_id
+        }
+
+        fn span_id(&self) -> [u8; 8] {
+            [0xab; 8]
+        }
+    }
+
+    /// A monotonic sum over the interval between `start_time` and `time`.
+    pub(crate) struct TestSum {
+        pub(crate) start_time: SystemTime,
+        pub(crate) time: SystemTime,
+        pub(crate) points: Vec<TestPoint>,
+    }
+
+    pub(crate) struct TestPoint {
+        pub(crate) attributes: Vec<KeyValue>,
+        pub(crate) value: f64,
+        pub(crate) exemplars: Vec<TestExemplar>,
+    }
+
+    impl SumLike<f64> for TestSum {
+        type Point = TestPoint;
+
+        fn data_points(&self) -> impl Iterator<Item = &Self::Point> {
+            self.points.iter()
+        }
+
+        fn start_time(&self, _point: &Self::Point) -> SystemTime {
+            self.start_time
+        }
+
+        fn time(&self) -> SystemTime {
+            self.time
+        }
+
+        fn is_monotonic(&self) -> bool {
+            true
+        }
+    }
+
+    impl NumberPoint<f64> for TestPoint {
+        type Exemplar = TestExemplar;
+
+        fn attributes(&self) -> impl Iterator<Item = &KeyValue> {
+            self.attributes.iter()
+        }
+
+        fn value(&self) -> f64 {
+            self.value
+        }
+
+        fn exemplars(&self) -> impl Iterator<Item = &Self::Exemplar> {
+            self.exemplars.iter()
+        }
+    }
+
+    /// A histogram over the interval between `start_time` and `time`.
+    pub(crate) struct TestHistogram {
+        pub(crate) start_time: SystemTime,
+        pub(crate) time: SystemTime,
+        pub(crate) points: Vec<TestHistogramPoint>,
+    }
+
+    pub(crate) struct TestHistogramPoint {
+        pub(crate) attributes: Vec<KeyValue>,
+        pub(crate) sum: f64,
+        pub(crate) bounds: Vec<f64>,
+        pub(crate) bucket_counts: Vec<u64>,
+        pub(crate) exemplars: Vec<TestExemplar>,
+    }
+
+    impl HistogramLike<f64> for TestHistogram {
+        type Point = TestHistogramPoint;
+
+        fn data_points(&self) -> impl Iterator<Item = &Self::Point> {
+            self.points.iter()
+        }
+
+        fn start_time(&self, _point: &Self::Point) -> SystemTime {
+            self.start_time
+        }
+
+        fn time(&self) -> SystemTime {
+            self.time
+        }
+    }
+
+    impl HistogramPoint<f64> for TestHistogramPoint {
+        type Exemplar = TestExemplar;
+
+        fn attributes(&self) -> impl Iterator<Item = &KeyValue> {
+            self.attributes.iter()
+        }
+
+        fn count(&self) -> u64 {
+            self.bucket_counts.iter().sum()
+        }
+
+        fn sum(&self) -> f64 {
+            self.sum
+        }
+
+        fn min(&self) -> Option<f64> {
+            None
+        }
+
+        fn max(&self) -> Option<f64> {
+            None
+        }
+
+        fn bounds(&self) -> impl Iterator<Item = f64> {
+            self.bounds.iter().copied()
+        }
+
+        fn bucket_counts(&self) -> impl Iterator<Item = u64> {
+            self.bucket_counts.iter().copied()
+        }
+
+        fn exemplars(&self) -> impl Iterator<Item = &Self::Exemplar> {
+            self.exemplars.iter()
+        }
+    }
+}
@@ -0,0 +1,107 @@
+//! Escaping of metric and label names outside of the legacy Prometheus character set, see [`NameEscaping`].
+
+use std::borrow::Cow;
+use std::fmt::Write;
+
+use super::NameEscaping;
+
+/// Whether `name` only uses the legacy Prometheus character set `[a-zA-Z_:][a-zA-Z0-9_:]*`, which label names
+/// (`is_label`) share except for `:`.
+pub(crate) fn is_legacy_name(name: &str, is_label: bool) -> bool {
+    let is_legacy_char = |c: char| c.is_ascii_alphanumeric() || c == '_' || (!is_label && c == ':');
+    !name.is_empty()
+        && !name.starts_with(|c: char| c.is_ascii_digit())
+        && name.chars().all(is_legacy_char)
+}
+
+/// Escapes `name` according to the [`NameEscaping::Dots`] and [`NameEscaping::Values`] schemes.
+///
+/// Names are returned unchanged for the other schemes, which are not applied character by character.
+pub(crate) fn escape_name(name: &str, escaping: NameEscaping, is_label: bool) -> Cow<'_, str> {
+    let is_legacy_char = |c: char, first: bool| {
+        c.is_ascii_alphabetic()
+            || c == '_'
+            || (!is_label && c == ':')
+            || (!first && c.is_ascii_digit())
+    };
+    match escaping {
+        NameEscaping::Underscores | NameEscaping::AllowUtf8 => Cow::Borrowed(name),
+        NameEscaping::Dots => {
+            let mut escaped = String::with_capacity(name.len() + 8);
+            for (i, c) in name.chars().enumerate() {
+                match c {
+                    '_' => escaped.push_str("__"),
+                    '.' => escaped.push_str("_dot_"),
+                    c if is_legacy_char(c, i == 0) => escaped.push(c),
+                    _ => escaped.push_str("__"),
+                }
+            }
+            Cow::Owned(escaped)
+        }
+        NameEscaping::Values => {
+            if is_legacy_name(name, is_label) {
+                return Cow::Borrowed(name);
+            }
+            let mut escaped = String::with_capacity(name.len() + 16);
+            escaped.push_str("U__");
+            for (i, c) in name.chars().enumerate() {
+                match c {
+                    '_' => escaped.push_str("__"),
+                    c if is_legacy_char(c, i == 0) => escaped.push(c),
+                    c => write!(escaped, "_{:x}_", c as u32)
+                        .expect("writing to a String should not fail"),
+                }
+            }
+            Cow::Owned(escaped)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_is_legacy_name() {
+        assert!(is_legacy_name("http_requests:rate5m", false));
+        assert!(is_legacy_name("_private", true));
+        assert!(!is_legacy_name("http:method", true));
+        assert!(!is_legacy_name("http.server.duration", false));
+        assert!(!is_legacy_name("1st", false));
+        assert!(!is_legacy_name("", false));
+    }
+
+    #[test]
+    fn test_escape_name() {
+        let escape = |name, escaping| escape_name(name, escaping, false).into_owned();
+
+        assert_eq!(
+            escape("http.server_duration", NameEscaping::Underscores),
+            "http.server_duration"
+        );
+        assert_eq!(
+            escape("http.server_duration", NameEscaping::AllowUtf8),
+            "http.server_duration"
+        );
+
+        assert_eq!(
+            escape("http.server_duration", NameEscaping::Dots),
+            "http_dot_server__duration"
+        );
+        assert_eq!(escape("a:b-c", NameEscaping::Dots), "a:b__c");
+        assert_eq!(escape("1st", NameEscaping::Dots), "__st");
+        assert_eq!(escape_name("a:b", NameEscaping::Dots, true), "a__b");
+
+        assert_eq!(
+            escape("http_requests", NameEscaping::Values),
+            "http_requests"
+        );
+        assert_eq!(
+            escape("http.server_duration", NameEscaping::Values),
+            "U__http_2e_server__duration"
+        );
+        assert_eq!(escape("1st", NameEscaping::Values), "U___31_st");
+        assert_eq!(escape("温度", NameEscaping::Values), "U___6e29__5ea6_");
+        assert_eq!(escape_name("a:b", NameEscaping::Values, true), "U__a_3a_b");
+    }
+}
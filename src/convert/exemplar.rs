@@ -0,0 +1,210 @@
+//! Exemplars linking the samples of counters and histogram buckets to the traces they were recorded in, see the
+//! [spec](https://github.com/prometheus/OpenMetrics/blob/main/specification/OpenMetrics.md#exemplars).
+
+use std::marker::PhantomData;
+
+use ufmt::{uDisplay, uWrite};
+
+use super::data::{ExemplarLike, ToF64};
+use super::{ConvertOptions, SampleTimestamp, sanitize_labels, write_label};
+use crate::format::FastDisplay;
+
+/// The maximum number of characters of the label names and values of an exemplar combined.
+const MAX_LABELS_LEN: usize = 128;
+
+/// The labels carrying the IDs of the trace and span an exemplar was recorded in.
+const TRACE_ID: &str = "trace_id";
+const SPAN_ID: &str = "span_id";
+
+/// Picks the most recent of `exemplars`.
+pub(crate) fn latest<'a, T, E: ExemplarLike<T> + 'a>(
+    exemplars: impl Iterator<Item = &'a E>,
+) -> Option<&'a E> {
+    exemplars.max_by_key(|exemplar| exemplar.time())
+}
+
+/// Picks the most recent of `exemplars` for each of the buckets with the upper `bounds`, followed by the `+Inf`
+/// bucket. Empty if there are no exemplars.
+pub(crate) fn by_bucket<'a, T: ToF64, E: ExemplarLike<T> + 'a>(
+    bounds: impl Iterator<Item = f64>,
+    exemplars: impl Iterator<Item = &'a E>,
+) -> Vec<Option<&'a E>> {
+    let mut exemplars = exemplars.peekable();
+    if exemplars.peek().is_none() {
+        return Vec::new();
+    }
+    let bounds: Vec<f64> = bounds.collect();
+    let mut buckets = vec![None; bounds.len() + 1];
+    for exemplar in exemplars {
+        let value = exemplar.value().to_f64();
+        let bucket = &mut buckets[bounds.partition_point(|&bound| bound < value)];
+        if bucket.is_none_or(|latest: &E| latest.time() < exemplar.time()) {
+            *bucket = Some(exemplar);
+        }
+    }
+    buckets
+}
+
+/// A [`uDisplay`] implementation which shows an exemplar after the value of a sample, like
+/// ` # {trace_id="...",span_id="..."} 0.5 1625097600.5`, or nothing without one.
+///
+/// The IDs of the trace and span are only written if the exemplar was recorded in a trace. The filtered attributes
+/// follow as far as they fit into the limit of 128 characters.
+pub(crate) struct ExemplarSuffix<'a, T, E> {
+    exemplar: Option<&'a E>,
+    options: &'a ConvertOptions,
+    value: PhantomData<fn() -> T>,
+}
+
+impl<'a, T, E> ExemplarSuffix<'a, T, E> {
+    pub(crate) fn new(exemplar: Option<&'a E>, options: &'a ConvertOptions) -> Self {
+        ExemplarSuffix {
+            exemplar,
+            options,
+            value: PhantomData,
+        }
+    }
+}
+
+impl<T: FastDisplay, E: ExemplarLike<T>> uDisplay for ExemplarSuffix<'_, T, E> {
+    fn fmt<W>(&self, f: &mut ufmt::Formatter<'_, W>) -> Result<(), W::Error>
+    where
+        W: uWrite + ?Sized,
+    {
+        let Some(exemplar) = self.exemplar else {
+            return Ok(());
+        };
+        f.write_str(" # {")?;
+        let trace_id = exemplar.trace_id();
+        let has_trace = trace_id != [0; 16];
+        let mut len = 0;
+        let mut first = !has_trace;
+        if has_trace {
+            f.write_str(TRACE_ID)?;
+            f.write_str("=\"")?;
+            write_hex(f, &trace_id)?;
+            f.write_str("\",")?;
+            f.write_str(SPAN_ID)?;
+            f.write_str("=\"")?;
+            write_hex(f, &exemplar.span_id())?;
+            f.write_char('"')?;
+            len = TRACE_ID.len() + 2 * trace_id.len() + SPAN_ID.len() + 2 * 8;
+        }
+        let attrs = exemplar
+            .filtered_attributes()
+            .map(|kv| (&kv.key, &kv.value));
+        for (name, value) in sanitize_labels(attrs, self.options.name_escaping) {
+            let label_len = name.chars().count() + value.chars().count();
+            if (has_trace && (name == TRACE_ID || name == SPAN_ID))
+                || len + label_len > MAX_LABELS_LEN
+            {
+                continue;
+            }
+            if !first {
+                f.write_char(',')?;
+            }
+            write_label(
+                &mut FormatterWrite(f),
+                &name,
+                &value,
+                self.options.name_escaping,
+            )?;
+            len += label_len;
+            first = false;
+        }
+        f.write_str("} ")?;
+        exemplar.value().fast_display().fmt(f)?;
+        SampleTimestamp::new(exemplar.time(), self.options).fmt(f)
+    }
+}
+
+/// Adapts a [`ufmt::Formatter`] to the label writers, which take a [`uWrite`].
+struct FormatterWrite<'a, 'f, W: uWrite + ?Sized>(&'a mut ufmt::Formatter<'f, W>);
+
+impl<W: uWrite + ?Sized> uWrite for FormatterWrite<'_, '_, W> {
+    type Error = W::Error;
+
+    fn write_str(&mut self, s: &str) -> Result<(), Self::Error> {
+        self.0.write_str(s)
+    }
+
+    fn write_char(&mut self, c: char) -> Result<(), Self::Error> {
+        self.0.write_char(c)
+    }
+}
+
+/// Writes `bytes` in lowercase hex, as trace and span IDs are written in W3C trace context.
+fn write_hex<W: uWrite + ?Sized>(
+    f: &mut ufmt::Formatter<'_, W>,
+    bytes: &[u8],
+) -> Result<(), W::Error> {
+    const DIGITS: &[u8; 16] = b"0123456789abcdef";
+    for byte in bytes {
+        f.write_char(char::from(DIGITS[usize::from(byte >> 4)]))?;
+        f.write_char(char::from(DIGITS[usize::from(byte & 0xf)]))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use opentelemetry::KeyValue;
+
+    use super::*;
+    use crate::convert::Timestamps;
+    use crate::convert::data::test::TestExemplar;
+
+    fn show(exemplar: &TestExemplar, options: &ConvertOptions) -> String {
+        let mut output = String::new();
+        ufmt::uwrite!(output, "{}", ExemplarSuffix::new(Some(exemplar), options)).unwrap();
+        output
+    }
+
+    #[test]
+    fn test_by_bucket() {
+        let exemplars = [
+            TestExemplar::new(0.5, 1),
+            TestExemplar::new(0.7, 2),
+            TestExemplar::new(1.0, 1),
+            TestExemplar::new(5.0, 1),
+        ];
+        let buckets = by_bucket([1.0, 2.0].into_iter(), exemplars.iter());
+        let values: Vec<_> = buckets.iter().map(|e| e.map(|e| e.value)).collect();
+        assert_eq!(values, [Some(0.7), None, Some(5.0)]);
+
+        let none = by_bucket::<f64, TestExemplar>([1.0].into_iter(), [].iter());
+        assert!(none.is_empty());
+        assert_eq!(latest(exemplars.iter()).unwrap().value, 0.7);
+    }
+
+    #[test]
+    fn test_exemplar_suffix() {
+        let options = ConvertOptions::default();
+        let mut exemplar = TestExemplar::new(0.5, 1625097600);
+        assert_eq!(show(&exemplar, &options), " # {} 0.5 1625097600");
+
+        exemplar.trace_id = [0x01; 16];
+        exemplar.attributes = vec![KeyValue::new("http.method", "GET")];
+        assert_eq!(
+            show(&exemplar, &options),
+            " # {trace_id=\"01010101010101010101010101010101\",span_id=\"abababababababab\",\
+             http_method=\"GET\"} 0.5 1625097600"
+        );
+
+        let options = options.with_timestamps(Timestamps::Omit);
+        exemplar.attributes = vec![
+            KeyValue::new("a", "x".repeat(70)),
+            KeyValue::new("b", "y".repeat(2)),
+        ];
+        // The first attribute exceeds the limit together with the trace and span IDs, the second one fits
+        assert_eq!(
+            show(&exemplar, &options),
+            " # {trace_id=\"01010101010101010101010101010101\",span_id=\"abababababababab\",\
+             b=\"yy\"} 0.5"
+        );
+
+        exemplar.attributes = Vec::new();
+        exemplar.value = f64::INFINITY;
+        assert!(show(&exemplar, &options).ends_with("\"} +Inf"));
+    }
+}
@@ -0,0 +1,303 @@
+//! Conversion of exponential histograms into histograms with explicit bucket boundaries.
+//!
+//! OpenMetrics 1.0 has no representation of exponential histograms, so their buckets are either merged down to a
+//! coarser scale or regrouped into a configured set of bounds. The zero bucket and the negative buckets are kept
+//! as buckets below the positive ones.
+
+use std::time::SystemTime;
+
+use opentelemetry::KeyValue;
+use opentelemetry_sdk::metrics::data::{
+    Exemplar, ExponentialBucket, ExponentialHistogram, ExponentialHistogramDataPoint,
+};
+
+use super::data::{HistogramLike, HistogramPoint};
+
+/// The default number of buckets for [`ExponentialBuckets::Scale`].
+pub const DEFAULT_MAX_BUCKETS: usize = 40;
+
+/// The buckets an exponential histogram is converted into, see
+/// [`ConvertOptions::with_exponential_buckets`](super::ConvertOptions::with_exponential_buckets).
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExponentialBuckets {
+    /// Keep the bucket boundaries of the exponential histogram, reducing its scale until the negative buckets, the
+    /// zero bucket and the positive buckets together fit into `max_buckets`.
+    Scale { max_buckets: usize },
+    /// Regroup the buckets into these upper bounds. Each exponential bucket is counted in the smallest bound which
+    /// is not below the bucket's upper boundary.
+    Bounds(Vec<f64>),
+}
+
+impl ExponentialBuckets {
+    pub(crate) const DEFAULT: Self = ExponentialBuckets::Scale {
+        max_buckets: DEFAULT_MAX_BUCKETS,
+    };
+
+    /// Sorts and deduplicates the bounds. `+Inf` is dropped since it is always written.
+    pub(crate) fn normalized(self) -> Self {
+        match self {
+            ExponentialBuckets::Bounds(mut bounds) => {
+                bounds.retain(|bound| bound.is_finite());
+                bounds.sort_unstable_by(f64::total_cmp);
+                bounds.dedup();
+                ExponentialBuckets::Bounds(bounds)
+            }
+            scale => scale,
+        }
+    }
+}
+
+impl Default for ExponentialBuckets {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// An [`ExponentialHistogram`] converted into explicit buckets.
+#[derive(Debug)]
+pub(super) struct RebucketedHistogram<T> {
+    start_time: SystemTime,
+    time: SystemTime,
+    points: Vec<RebucketedPoint<T>>,
+}
+
+#[derive(Debug)]
+pub(super) struct RebucketedPoint<T> {
+    attributes: Vec<KeyValue>,
+    count: u64,
+    sum: T,
+    min: Option<T>,
+    max: Option<T>,
+    bounds: Vec<f64>,
+    bucket_counts: Vec<u64>,
+    exemplars: Vec<Exemplar<T>>,
+}
+
+impl<T: Copy> RebucketedHistogram<T> {
+    pub(super) fn new(histogram: &ExponentialHistogram<T>, buckets: &ExponentialBuckets) -> Self {
+        let points = histogram
+            .data_points()
+            .map(|point| {
+                let (bounds, bucket_counts) = match buckets {
+                    ExponentialBuckets::Scale { max_buckets } => {
+                        native_buckets(point, required_shift(point, *max_buckets))
+                    }
+                    ExponentialBuckets::Bounds(bounds) => {
+                        let (native_bounds, native_counts) = native_buckets(point, 0);
+                        let counts = regroup(&native_bounds, &native_counts, bounds);
+                        (bounds.clone(), counts)
+                    }
+                };
+                RebucketedPoint {
+                    attributes: point.attributes().cloned().collect(),
+                    count: point.count() as u64,
+                    sum: point.sum(),
+                    min: point.min(),
+                    max: point.max(),
+                    bounds,
+                    bucket_counts,
+                    exemplars: point.exemplars().cloned().collect(),
+                }
+            })
+            .collect();
+        RebucketedHistogram {
+            start_time: histogram.start_time(),
+            time: histogram.time(),
+            points,
+        }
+    }
+}
+
+/// Returns by how much the scale of `point` must be reduced for its buckets to fit into `max_buckets`.
+fn required_shift<T>(point: &ExponentialHistogramDataPoint<T>, max_buckets: usize) -> u32 {
+    // Beyond a shift of 32 all indices of a bucket range collapse into at most two buckets
+    (0..32)
+        .find(|&shift| {
+            let zero_bucket = 1;
+            downscaled_len(point.negative_bucket(), shift)
+                + downscaled_len(point.positive_bucket(), shift)
+                + zero_bucket
+                <= max_buckets
+        })
+        .unwrap_or(32)
+}
+
+/// Returns the ascending upper bounds of the buckets of `point` and their (non-cumulative) counts, after reducing
+/// its scale by `shift`.
+fn native_buckets<T>(point: &ExponentialHistogramDataPoint<T>, shift: u32) -> (Vec<f64>, Vec<u64>) {
+    let scale = i32::from(point.scale()) - shift as i32;
+    // The bucket at `index` contains the values in (base^index, base^(index + 1)], where base = 2^(2^-scale).
+    // Boundaries beyond the range of f64 are clamped, so that they do not collide with the `+Inf` bucket
+    let boundary =
+        |index: i64| f64::exp2(index as f64 * f64::exp2(-f64::from(scale))).min(f64::MAX);
+
+    let (negative_offset, negative_counts) = downscale(point.negative_bucket(), shift);
+    let (positive_offset, positive_counts) = downscale(point.positive_bucket(), shift);
+    let len = negative_counts.len() + 1 + positive_counts.len();
+    let mut bounds = Vec::with_capacity(len);
+    let mut counts = Vec::with_capacity(len);
+
+    // Negative buckets mirror the positive ones, so the most negative values are in the highest index
+    for (i, count) in negative_counts.into_iter().enumerate().rev() {
+        push_bucket(
+            &mut bounds,
+            &mut counts,
+            -boundary(negative_offset + i as i64),
+            count,
+        );
+    }
+    push_bucket(
+        &mut bounds,
+        &mut counts,
+        point.zero_threshold(),
+        point.zero_count(),
+    );
+    for (i, count) in positive_counts.into_iter().enumerate() {
+        push_bucket(
+            &mut bounds,
+            &mut counts,
+            boundary(positive_offset + i as i64 + 1),
+            count,
+        );
+    }
+    (bounds, counts)
+}
+
+/// Appends a bucket, merging it into the previous one if clamping or rounding made its bound not increase.
+fn push_bucket(bounds: &mut Vec<f64>, counts: &mut Vec<u64>, bound: f64, count: u64) {
+    match (bounds.last(), counts.last_mut()) {
+        (Some(&last), Some(total)) if bound <= last => *total += count,
+        _ => {
+            bounds.push(bound);
+            counts.push(count);
+        }
+    }
+}
+
+/// Merges the counts of `bucket` as if its scale was reduced by `shift`. Returns the new offset and counts.
+pub(super) fn downscale(bucket: &ExponentialBucket, shift: u32) -> (i64, Vec<u64>) {
+    let offset = i64::from(bucket.offset()) >> shift;
+    let mut counts = Vec::with_capacity(downscaled_len(bucket, shift));
+    for (i, count) in bucket.counts().enumerate() {
+        let index = ((i64::from(bucket.offset()) + i as i64) >> shift) - offset;
+        let index = index as usize;
+        if index >= counts.len() {
+            counts.resize(index + 1, 0);
+        }
+        counts[index] += count;
+    }
+    (offset, counts)
+}
+
+/// The number of buckets of `bucket` if its scale was reduced by `shift`.
+fn downscaled_len(bucket: &ExponentialBucket, shift: u32) -> usize {
+    let len = bucket.counts().count();
+    if len == 0 {
+        return 0;
+    }
+    let first = i64::from(bucket.offset());
+    let last = first + len as i64 - 1;
+    ((last >> shift) - (first >> shift) + 1) as usize
+}
+
+/// Regroups buckets with the ascending upper bounds `native_bounds` into the ascending upper `bounds`.
+/// Buckets above the largest bound are only counted in the implicit `+Inf` bucket.
+fn regroup(native_bounds: &[f64], native_counts: &[u64], bounds: &[f64]) -> Vec<u64> {
+    let mut counts = vec![0; bounds.len()];
+    for (&upper, &count) in std::iter::zip(native_bounds, native_counts) {
+        let index = bounds.partition_point(|&bound| bound < upper);
+        if let Some(total) = counts.get_mut(index) {
+            *total += count;
+        }
+    }
+    counts
+}
+
+impl<T: Copy + 'static> HistogramLike<T> for RebucketedHistogram<T> {
+    type Point = RebucketedPoint<T>;
+
+    fn data_points(&self) -> impl Iterator<Item = &Self::Point> {
+        self.points.iter()
+    }
+
+    fn start_time(&self, _point: &Self::Point) -> SystemTime {
+        self.start_time
+    }
+
+    fn time(&self) -> SystemTime {
+        self.time
+    }
+}
+
+impl<T: Copy + 'static> HistogramPoint<T> for RebucketedPoint<T> {
+    type Exemplar = Exemplar<T>;
+
+    fn attributes(&self) -> impl Iterator<Item = &KeyValue> {
+        self.attributes.iter()
+    }
+
+    fn count(&self) -> u64 {
+        self.count
+    }
+
+    fn sum(&self) -> T {
+        self.sum
+    }
+
+    fn min(&self) -> Option<T> {
+        self.min
+    }
+
+    fn max(&self) -> Option<T> {
+        self.max
+    }
+
+    fn bounds(&self) -> impl Iterator<Item = f64> {
+        self.bounds.iter().copied()
+    }
+
+    fn bucket_counts(&self) -> impl Iterator<Item = u64> {
+        self.bucket_counts.iter().copied()
+    }
+
+    fn exemplars(&self) -> impl Iterator<Item = &Self::Exemplar> {
+        self.exemplars.iter()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_regroup() {
+        let native_bounds = [-2.0, -1.0, 0.0, 1.0, 2.0, 4.0, 8.0];
+        let native_counts = [1, 2, 3, 4, 5, 6, 7];
+
+        let counts = regroup(&native_bounds, &native_counts, &[0.0, 3.0, 5.0]);
+        assert_eq!(counts, vec![1 + 2 + 3, 4 + 5, 6]);
+
+        let counts = regroup(&native_bounds, &native_counts, &[]);
+        assert_eq!(counts, Vec::<u64>::new());
+    }
+
+    #[test]
+    fn test_push_bucket() {
+        let mut bounds = Vec::new();
+        let mut counts = Vec::new();
+        push_bucket(&mut bounds, &mut counts, 1.0, 1);
+        push_bucket(&mut bounds, &mut counts, f64::MAX, 2);
+        push_bucket(&mut bounds, &mut counts, f64::MAX, 3);
+        assert_eq!(bounds, [1.0, f64::MAX]);
+        assert_eq!(counts, [1, 2 + 3]);
+    }
+
+    #[test]
+    fn test_normalized() {
+        let buckets = ExponentialBuckets::Bounds(vec![5.0, f64::INFINITY, 1.0, 5.0, f64::NAN]);
+        assert_eq!(
+            buckets.normalized(),
+            ExponentialBuckets::Bounds(vec![1.0, 5.0])
+        );
+    }
+}
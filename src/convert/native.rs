@@ -0,0 +1,121 @@
+//! Mapping of exponential histograms onto Prometheus native histograms.
+//!
+//! Both use buckets with exponentially growing boundaries at powers of `2^(2^-scale)`, where Prometheus calls the
+//! scale the schema. Prometheus supports the schemas -4 to 8 and shifts the bucket indices by one.
+
+use opentelemetry_sdk::metrics::data::ExponentialHistogramDataPoint;
+
+use super::exponential::downscale;
+
+/// The highest resolution supported by Prometheus native histograms.
+const MAX_SCHEMA: i8 = 8;
+/// The lowest resolution supported by Prometheus native histograms.
+const MIN_SCHEMA: i8 = -4;
+
+/// The buckets of an exponential histogram data point in the representation of a Prometheus native histogram.
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct NativeHistogram {
+    pub(super) schema: i32,
+    pub(super) zero_threshold: f64,
+    pub(super) zero_count: u64,
+    pub(super) negative_spans: Vec<BucketSpan>,
+    pub(super) negative_deltas: Vec<i64>,
+    pub(super) positive_spans: Vec<BucketSpan>,
+    pub(super) positive_deltas: Vec<i64>,
+}
+
+/// A run of consecutive buckets, starting `offset` buckets after the end of the previous span.
+/// The offset of the first span is the index of its first bucket.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(super) struct BucketSpan {
+    pub(super) offset: i32,
+    pub(super) length: u32,
+}
+
+impl NativeHistogram {
+    /// Maps the buckets of `point` onto a native histogram, reducing the scale if it is too high.
+    /// Returns `None` if the scale is too low to be represented.
+    pub(super) fn new<T>(point: &ExponentialHistogramDataPoint<T>) -> Option<Self> {
+        if point.scale() < MIN_SCHEMA {
+            return None;
+        }
+        let shift = (point.scale() - MAX_SCHEMA).max(0) as u32;
+        let (negative_offset, negative_counts) = downscale(point.negative_bucket(), shift);
+        let (negative_spans, negative_deltas) = spans_and_deltas(negative_offset, negative_counts);
+        let (positive_offset, positive_counts) = downscale(point.positive_bucket(), shift);
+        let (positive_spans, positive_deltas) = spans_and_deltas(positive_offset, positive_counts);
+        Some(NativeHistogram {
+            schema: i32::from(point.scale()) - shift as i32,
+            zero_threshold: point.zero_threshold(),
+            zero_count: point.zero_count(),
+            negative_spans,
+            negative_deltas,
+            positive_spans,
+            positive_deltas,
+        })
+    }
+}
+
+/// Encodes the non-empty buckets starting at the OpenTelemetry bucket index `offset` as spans and the count deltas
+/// between consecutive buckets.
+fn spans_and_deltas(offset: i64, counts: Vec<u64>) -> (Vec<BucketSpan>, Vec<i64>) {
+    let mut spans: Vec<BucketSpan> = Vec::new();
+    let mut deltas = Vec::with_capacity(counts.len());
+    let mut previous_count = 0;
+    let mut end_of_span = 0;
+
+    for (i, count) in counts.into_iter().enumerate() {
+        if count == 0 {
+            continue;
+        }
+        // OpenTelemetry's bucket i contains (base^i, base^(i+1)], Prometheus' bucket i contains (base^(i-1), base^i]
+        let index = offset + i as i64 + 1;
+        match spans.last_mut() {
+            Some(span) if index == end_of_span => span.length += 1,
+            Some(_) => spans.push(BucketSpan {
+                offset: (index - end_of_span) as i32,
+                length: 1,
+            }),
+            None => spans.push(BucketSpan {
+                offset: index as i32,
+                length: 1,
+            }),
+        }
+        end_of_span = index + 1;
+        deltas.push(count as i64 - previous_count);
+        previous_count = count as i64;
+    }
+    (spans, deltas)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_spans_and_deltas() {
+        let (spans, deltas) = spans_and_deltas(-2, vec![3, 0, 0, 2, 2, 0, 5]);
+        assert_eq!(
+            spans,
+            vec![
+                BucketSpan {
+                    offset: -1,
+                    length: 1
+                },
+                BucketSpan {
+                    offset: 2,
+                    length: 2
+                },
+                BucketSpan {
+                    offset: 1,
+                    length: 1
+                },
+            ]
+        );
+        assert_eq!(deltas, vec![3, -1, 0, 3]);
+
+        let (spans, deltas) = spans_and_deltas(-1, vec![]);
+        assert_eq!(spans, vec![]);
+        assert_eq!(deltas, vec![]);
+    }
+}
@@ -0,0 +1,518 @@
+//! Content negotiation of the format metrics are written in.
+//!
+//! Follows the negotiation of the Prometheus client libraries: the media ranges of an `Accept` header are tried in
+//! order of their quality, the first supported one wins and the Prometheus text format is the fallback.
+
+use std::fmt::Write;
+
+use opentelemetry_sdk::metrics::data::ResourceMetrics;
+
+#[cfg(feature = "protobuf")]
+use super::WriteProtobuf;
+use super::{Accumulated, ConvertOptions, NameEscaping, TextFormat, WriteOpenMetrics};
+use crate::error::Error;
+
+/// A format which metrics can be written in, see [`negotiate`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// One of the text formats.
+    Text(TextFormat),
+    /// The Prometheus protobuf exposition format, see [`WriteProtobuf`].
+    #[cfg(feature = "protobuf")]
+    Protobuf,
+}
+
+impl Format {
+    /// The value of the `Content-Type` header for metrics written in this format.
+    pub const fn content_type(self) -> &'static str {
+        match self {
+            Format::Text(format) => format.mime_type(),
+            #[cfg(feature = "protobuf")]
+            Format::Protobuf => super::PROTOBUF_MIME_TYPE,
+        }
+    }
+
+    /// The value of the `Content-Type` header for metrics written in this format with the name `escaping`.
+    #[cfg(any(feature = "server", feature = "tower"))]
+    pub(crate) fn content_type_with_escaping(self, escaping: NameEscaping) -> String {
+        format!(
+            "{}; escaping={}",
+            self.content_type(),
+            escaping_param(escaping)
+        )
+    }
+}
+
+impl Default for Format {
+    fn default() -> Self {
+        Format::Text(TextFormat::default())
+    }
+}
+
+/// Picks the format to answer a request with the HTTP `Accept` header `accept`.
+///
+/// Media ranges are tried in order of their `q` value, media ranges with `q=0` are never chosen. Wildcards match no
+/// format, so [`TextFormat::Prometheus`] is returned if no media range is supported.
+///
+/// ```
+/// use ottotom::convert::{Format, TextFormat, negotiate};
+///
+/// let format = negotiate("application/openmetrics-text;version=1.0.0,text/plain;version=0.0.4;q=0.5");
+/// assert_eq!(format, Format::Text(TextFormat::OpenMetrics));
+/// assert_eq!(negotiate("*/*"), Format::Text(TextFormat::Prometheus));
+/// ```
+pub fn negotiate(accept: &str) -> Format {
+    let mut ranges: Vec<MediaRange> = accept.split(',').filter_map(MediaRange::parse).collect();
+    // Stable, so that media ranges of the same quality keep their order
+    ranges.sort_by(|a, b| b.quality.total_cmp(&a.quality));
+    ranges
+        .iter()
+        .filter(|range| range.quality > 0.0)
+        .find_map(MediaRange::format)
+        .unwrap_or(Format::Text(TextFormat::Prometheus))
+}
+
+/// Picks one of the `available` formats to answer a request with the HTTP `Accept` header `accept`.
+///
+/// Unlike [`negotiate`], wildcards match any of the `available` formats, the first one matching wins. `None` is
+/// returned if none is acceptable. An empty `accept` header accepts anything.
+///
+/// ```
+/// use ottotom::convert::{Format, TextFormat, negotiate_among};
+///
+/// let available = [Format::Text(TextFormat::OpenMetrics)];
+/// assert_eq!(negotiate_among("text/plain;q=0.5,*/*;q=0.1", &available), Some(available[0]));
+/// assert_eq!(negotiate_among("text/plain", &available), None);
+/// ```
+pub fn negotiate_among(accept: &str, available: &[Format]) -> Option<Format> {
+    negotiate_with_escaping(accept, available).map(|(format, _)| format)
+}
+
+/// Picks one of the `available` formats like [`negotiate_among`], along with the [`NameEscaping`] of the `escaping`
+/// parameter of the chosen media range, `None` if it is missing or unknown.
+pub(crate) fn negotiate_with_escaping(
+    accept: &str,
+    available: &[Format],
+) -> Option<(Format, Option<NameEscaping>)> {
+    if accept.trim().is_empty() {
+        return available.first().map(|&format| (format, None));
+    }
+    let mut ranges: Vec<MediaRange> = accept.split(',').filter_map(MediaRange::parse).collect();
+    ranges.sort_by(|a, b| b.quality.total_cmp(&a.quality));
+    ranges
+        .iter()
+        .filter(|range| range.quality > 0.0)
+        .find_map(|range| {
+            let format = available
+                .iter()
+                .copied()
+                .find(|&format| range.matches(format))?;
+            Some((format, range.param("escaping").and_then(parse_escaping)))
+        })
+}
+
+/// Picks the [`NameEscaping`] to answer a request with the HTTP `Accept` header `accept`.
+///
+/// The `escaping` parameter of the media range chosen by [`negotiate`] is used, [`NameEscaping::Underscores`] if it is
+/// missing or unknown.
+///
+/// ```
+/// use ottotom::convert::{ConvertOptions, NameEscaping, negotiate_escaping};
+///
+/// let accept = "application/openmetrics-text;version=1.0.0;escaping=allow-utf-8,text/plain;q=0.5";
+/// assert_eq!(negotiate_escaping(accept), NameEscaping::AllowUtf8);
+/// let options = ConvertOptions::default().with_name_escaping(negotiate_escaping(accept));
+/// ```
+pub fn negotiate_escaping(accept: &str) -> NameEscaping {
+    let mut ranges: Vec<MediaRange> = accept.split(',').filter_map(MediaRange::parse).collect();
+    ranges.sort_by(|a, b| b.quality.total_cmp(&a.quality));
+    ranges
+        .iter()
+        .filter(|range| range.quality > 0.0)
+        .find(|range| range.format().is_some())
+        .and_then(|range| range.param("escaping"))
+        .and_then(parse_escaping)
+        .unwrap_or(NameEscaping::Underscores)
+}
+
+/// Parses the value of an `escaping` parameter.
+fn parse_escaping(value: &str) -> Option<NameEscaping> {
+    match value {
+        "underscores" => Some(NameEscaping::Underscores),
+        "allow-utf-8" => Some(NameEscaping::AllowUtf8),
+        "dots" => Some(NameEscaping::Dots),
+        "values" => Some(NameEscaping::Values),
+        _ => None,
+    }
+}
+
+/// The value of the `escaping` parameter for `escaping`, the inverse of [`parse_escaping`].
+#[cfg(any(feature = "server", feature = "tower"))]
+fn escaping_param(escaping: NameEscaping) -> &'static str {
+    match escaping {
+        NameEscaping::Underscores => "underscores",
+        NameEscaping::AllowUtf8 => "allow-utf-8",
+        NameEscaping::Dots => "dots",
+        NameEscaping::Values => "values",
+    }
+}
+
+/// A single media range of an `Accept` header.
+#[derive(Debug)]
+struct MediaRange<'a> {
+    /// the lowercase `type/subtype`
+    media_type: String,
+    params: Vec<(String, &'a str)>,
+    quality: f32,
+}
+
+impl<'a> MediaRange<'a> {
+    fn parse(range: &'a str) -> Option<Self> {
+        let mut parts = range.split(';');
+        let media_type = parts.next()?.trim().to_ascii_lowercase();
+        if media_type.is_empty() {
+            return None;
+        }
+        let mut params = Vec::new();
+        let mut quality = 1.0;
+        for param in parts {
+            let Some((name, value)) = param.split_once('=') else {
+                continue;
+            };
+            let name = name.trim().to_ascii_lowercase();
+            let value = value.trim().trim_matches('"');
+            if name == "q" {
+                quality = value.parse().unwrap_or(0.0);
+            } else {
+                params.push((name, value));
+            }
+        }
+        Some(MediaRange {
+            media_type,
+            params,
+            quality,
+        })
+    }
+
+    fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(param, _)| param == name)
+            .map(|(_, value)| *value)
+    }
+
+    /// The supported format matching this media range, if any.
+    fn format(&self) -> Option<Format> {
+        let version = self.param("version");
+        match self.media_type.as_str() {
+            "application/openmetrics-text"
+                if matches!(version, None | Some("1.0.0") | Some("0.0.1")) =>
+            {
+                Some(Format::Text(TextFormat::OpenMetrics))
+            }
+            "application/openmetrics-text" if version == Some("2.0.0") => {
+                Some(Format::Text(TextFormat::OpenMetrics2))
+            }
+            "text/plain" if matches!(version, None | Some("0.0.4")) => {
+                Some(Format::Text(TextFormat::Prometheus))
+            }
+            #[cfg(feature = "protobuf")]
+            "application/vnd.google.protobuf"
+                if self.param("proto") == Some("io.prometheus.client.MetricFamily")
+                    && self.param("encoding") == Some("delimited") =>
+            {
+                Some(Format::Protobuf)
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether this media range matches `format`, either exactly or by a wildcard.
+    fn matches(&self, format: Format) -> bool {
+        if let Some(main_type) = self.media_type.strip_suffix("/*") {
+            main_type == "*" || format.content_type().starts_with(&format!("{main_type}/"))
+        } else {
+            self.format() == Some(format)
+        }
+    }
+}
+
+/// Trait to write the metrics data in any [`Format`].
+pub trait WriteFormat {
+    /// Appends the metrics to `buf` in `format`, converted according to `options`.
+    /// The text format of `options` is replaced by the one of `format`.
+    ///
+    /// Returns the errors of the metrics which were skipped in lenient mode, see [`ConvertOptions::with_lenient`].
+    fn write_as_format(
+        &self,
+        format: Format,
+        buf: &mut Vec<u8>,
+        options: &ConvertOptions,
+    ) -> Result<Vec<Error>, Error>;
+}
+
+impl WriteFormat for ResourceMetrics {
+    fn write_as_format(
+        &self,
+        format: Format,
+        buf: &mut Vec<u8>,
+        options: &ConvertOptions,
+    ) -> Result<Vec<Error>, Error> {
+        match format {
+            Format::Text(text_format) => write_text(self, text_format, buf, options),
+            #[cfg(feature = "protobuf")]
+            Format::Protobuf => self.write_as_protobuf_with(buf, options),
+        }
+    }
+}
+
+impl WriteFormat for Accumulated<'_> {
+    fn write_as_format(
+        &self,
+        format: Format,
+        buf: &mut Vec<u8>,
+        options: &ConvertOptions,
+    ) -> Result<Vec<Error>, Error> {
+        match format {
+            Format::Text(text_format) => write_text(self, text_format, buf, options),
+            #[cfg(feature = "protobuf")]
+            Format::Protobuf => self.write_as_protobuf_with(buf, options),
+        }
+    }
+}
+
+fn write_text(
+    metrics: &impl WriteOpenMetrics,
+    format: TextFormat,
+    buf: &mut Vec<u8>,
+    options: &ConvertOptions,
+) -> Result<Vec<Error>, Error> {
+    if options.text_format == format {
+        metrics.write_as_openmetrics_with(&mut Utf8Buffer(buf), options)
+    } else {
+        let options = options.clone().with_text_format(format);
+        metrics.write_as_openmetrics_with(&mut Utf8Buffer(buf), &options)
+    }
+}
+
+/// Adapter to write text into a byte buffer.
+struct Utf8Buffer<'b>(&'b mut Vec<u8>);
+
+impl Write for Utf8Buffer<'_> {
+    fn write_str(&mut self, s: &str) -> std::fmt::Result {
+        self.0.extend_from_slice(s.as_bytes());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_negotiate() {
+        let openmetrics = Format::Text(TextFormat::OpenMetrics);
+        let prometheus = Format::Text(TextFormat::Prometheus);
+
+        assert_eq!(negotiate(""), prometheus);
+        assert_eq!(negotiate("*/*"), prometheus);
+        assert_eq!(negotiate("text/plain"), prometheus);
+        assert_eq!(negotiate("application/openmetrics-text"), openmetrics);
+        assert_eq!(
+            negotiate("Application/OpenMetrics-Text; Version=\"1.0.0\"; charset=utf-8"),
+            openmetrics
+        );
+        // Unsupported versions are skipped
+        assert_eq!(
+            negotiate("application/openmetrics-text;version=3.0.0,text/plain;q=0.1"),
+            prometheus
+        );
+        // The 2.0 draft is only chosen when asked for explicitly
+        assert_eq!(
+            negotiate("application/openmetrics-text;version=2.0.0,text/plain;q=0.1"),
+            Format::Text(TextFormat::OpenMetrics2)
+        );
+        // The highest quality wins, then the first in order
+        assert_eq!(
+            negotiate(
+                "text/plain;version=0.0.4;q=0.3,application/openmetrics-text;version=1.0.0;q=0.5"
+            ),
+            openmetrics
+        );
+        assert_eq!(
+            negotiate("text/plain;q=0.5,application/openmetrics-text;q=0.5"),
+            prometheus
+        );
+        // q=0 means not acceptable
+        assert_eq!(
+            negotiate("application/openmetrics-text;q=0,text/plain;q=0.1"),
+            prometheus
+        );
+        assert_eq!(
+            negotiate("application/openmetrics-text;q=invalid"),
+            prometheus
+        );
+    }
+
+    #[test]
+    fn test_negotiate_among() {
+        let openmetrics = Format::Text(TextFormat::OpenMetrics);
+        let prometheus = Format::Text(TextFormat::Prometheus);
+        let both = [openmetrics, prometheus];
+
+        assert_eq!(negotiate_among("", &both), Some(openmetrics));
+        assert_eq!(negotiate_among("*/*", &both), Some(openmetrics));
+        assert_eq!(negotiate_among("text/*", &both), Some(prometheus));
+        assert_eq!(negotiate_among("application/*", &[prometheus]), None);
+        assert_eq!(
+            negotiate_among("text/plain;version=0.0.4,*/*;q=0.1", &both),
+            Some(prometheus)
+        );
+        assert_eq!(
+            negotiate_among("text/plain;version=0.0.4,*/*;q=0.1", &[openmetrics]),
+            Some(openmetrics)
+        );
+        assert_eq!(negotiate_among("text/plain,*/*;q=0", &[openmetrics]), None);
+        assert_eq!(negotiate_among("text/plain", &[]), None);
+
+        // Wildcards prefer the first available format, so that the 2.0 draft can be offered after 1.0
+        let openmetrics_2 = Format::Text(TextFormat::OpenMetrics2);
+        let all = [openmetrics, prometheus, openmetrics_2];
+        assert_eq!(negotiate_among("application/*", &all), Some(openmetrics));
+        assert_eq!(
+            negotiate_among(
+                "application/openmetrics-text;version=2.0.0,application/openmetrics-text;version=1.0.0;q=0.5",
+                &all
+            ),
+            Some(openmetrics_2)
+        );
+        assert_eq!(
+            negotiate_among("application/openmetrics-text;version=2.0.0", &both),
+            None
+        );
+    }
+
+    #[test]
+    fn test_negotiate_escaping() {
+        assert_eq!(negotiate_escaping(""), NameEscaping::Underscores);
+        assert_eq!(
+            negotiate_escaping("application/openmetrics-text;escaping=allow-utf-8"),
+            NameEscaping::AllowUtf8
+        );
+        assert_eq!(
+            negotiate_escaping("text/plain;version=0.0.4;escaping=dots"),
+            NameEscaping::Dots
+        );
+        assert_eq!(
+            negotiate_escaping("text/plain;escaping=values;q=0.5,application/openmetrics-text"),
+            NameEscaping::Underscores
+        );
+        // Unsupported media ranges and escaping schemes are ignored
+        assert_eq!(
+            negotiate_escaping("application/json;escaping=dots,text/plain;escaping=values;q=0.5"),
+            NameEscaping::Values
+        );
+        assert_eq!(
+            negotiate_escaping("text/plain;escaping=unknown"),
+            NameEscaping::Underscores
+        );
+    }
+
+    #[test]
+    fn test_negotiate_with_escaping() {
+        let openmetrics = Format::Text(TextFormat::OpenMetrics);
+        let prometheus = Format::Text(TextFormat::Prometheus);
+        let both = [openmetrics, prometheus];
+
+        assert_eq!(
+            negotiate_with_escaping("", &both),
+            Some((openmetrics, None))
+        );
+        assert_eq!(
+            negotiate_with_escaping(
+                "application/openmetrics-text;version=1.0.0;escaping=allow-utf-8,text/plain;escaping=dots;q=0.5",
+                &both
+            ),
+            Some((openmetrics, Some(NameEscaping::AllowUtf8)))
+        );
+        // The escaping of the chosen media range counts, even of a wildcard
+        assert_eq!(
+            negotiate_with_escaping(
+                "application/openmetrics-text;escaping=allow-utf-8,*/*;escaping=values;q=0.1",
+                &[prometheus]
+            ),
+            Some((prometheus, Some(NameEscaping::Values)))
+        );
+        assert_eq!(
+            negotiate_with_escaping("text/plain;escaping=unknown", &both),
+            Some((prometheus, None))
+        );
+        assert_eq!(
+            negotiate_with_escaping("application/json;escaping=dots", &both),
+            None
+        );
+        #[cfg(any(feature = "server", feature = "tower"))]
+        assert_eq!(
+            openmetrics.content_type_with_escaping(NameEscaping::AllowUtf8),
+            "application/openmetrics-text; version=1.0.0; charset=utf-8; escaping=allow-utf-8"
+        );
+    }
+
+    #[cfg(feature = "protobuf")]
+    #[test]
+    fn test_negotiate_protobuf() {
+        assert_eq!(
+            negotiate(
+                "application/vnd.google.protobuf;proto=io.prometheus.client.MetricFamily;encoding=delimited;q=0.7,\
+                 application/openmetrics-text;version=1.0.0;q=0.5,*/*;q=0.1"
+            ),
+            Format::Protobuf
+        );
+        assert_eq!(
+            negotiate("application/vnd.google.protobuf;proto=io.prometheus.client.MetricFamily"),
+            Format::Text(TextFormat::Prometheus)
+        );
+        assert_eq!(
+            Format::Protobuf.content_type(),
+            crate::convert::PROTOBUF_MIME_TYPE
+        );
+    }
+
+    #[test]
+    fn test_write_as_format() {
+        let metrics = ottotom_testsupport::resource_metrics::make_test_metrics();
+        let options = ConvertOptions::default();
+
+        let mut buf = Vec::new();
+        let format = Format::Text(TextFormat::Prometheus);
+        metrics.write_as_format(format, &mut buf, &options).unwrap();
+        let text = String::from_utf8(buf).unwrap();
+        assert!(text.contains("# TYPE u64_counter_seconds_total counter\n"));
+        assert!(!text.contains("# EOF"));
+
+        let mut buf = Vec::new();
+        metrics
+            .write_as_format(Format::default(), &mut buf, &options)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            metrics.to_openmetrics_string().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_content_type() {
+        assert_eq!(
+            Format::Text(TextFormat::OpenMetrics).content_type(),
+            crate::convert::MIME_TYPE
+        );
+        assert_eq!(
+            Format::Text(TextFormat::Prometheus).content_type(),
+            crate::convert::PROMETHEUS_MIME_TYPE
+        );
+        assert_eq!(
+            Format::Text(TextFormat::OpenMetrics2).content_type(),
+            crate::convert::OPENMETRICS_2_MIME_TYPE
+        );
+    }
+}
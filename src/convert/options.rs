@@ -0,0 +1,274 @@
+use super::exponential::ExponentialBuckets;
+use super::unit::UnitMapping;
+use super::{MIME_TYPE, OPENMETRICS_2_MIME_TYPE, PROMETHEUS_MIME_TYPE};
+
+/// The text format which metrics are written in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TextFormat {
+    /// The [OpenMetrics 1.0](https://github.com/prometheus/OpenMetrics/blob/main/specification/OpenMetrics.md)
+    /// text format.
+    #[default]
+    OpenMetrics,
+    /// The classic Prometheus
+    /// [text format 0.0.4](https://prometheus.io/docs/instrumenting/exposition_formats/#text-based-format).
+    ///
+    /// It has no `# EOF` marker, no `# UNIT` metadata and no `_created` samples. Timestamps are written in
+    /// integer milliseconds, info metrics as gauges and types unknown to the format as `untyped`.
+    Prometheus,
+    /// The text format of the
+    /// [OpenMetrics 2.0 draft](https://github.com/prometheus/OpenMetrics/blob/main/specification/OpenMetrics.md).
+    ///
+    /// It is written like [`TextFormat::OpenMetrics`], but with the native histograms of
+    /// [`ConvertOptions::with_native_histograms`], which OpenMetrics 1.0 parsers reject. It has a content type of its
+    /// own, so that it is only served to scrapers asking for it.
+    OpenMetrics2,
+}
+
+impl TextFormat {
+    /// The mime type of the text written in this format.
+    pub const fn mime_type(self) -> &'static str {
+        match self {
+            TextFormat::OpenMetrics => MIME_TYPE,
+            TextFormat::Prometheus => PROMETHEUS_MIME_TYPE,
+            TextFormat::OpenMetrics2 => OPENMETRICS_2_MIME_TYPE,
+        }
+    }
+
+    /// Whether this is one of the OpenMetrics formats, which only differ in native histograms.
+    pub(crate) const fn is_openmetrics(self) -> bool {
+        !matches!(self, TextFormat::Prometheus)
+    }
+}
+
+/// How the timestamps of samples are written, see [`ConvertOptions::with_timestamps`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Timestamps {
+    /// In the unit of the text format: float seconds for [`TextFormat::OpenMetrics`], integer milliseconds for
+    /// [`TextFormat::Prometheus`] and the protobuf format.
+    #[default]
+    Native,
+    /// Without timestamps, so that the scraper uses the time of the scrape.
+    ///
+    /// Recommended by Prometheus for directly scraped targets, as explicit timestamps interfere with staleness
+    /// handling.
+    Omit,
+    /// In float seconds, regardless of the text format.
+    Seconds,
+    /// In integer milliseconds, regardless of the text format.
+    Milliseconds,
+}
+
+/// How metric and label names with characters outside of the legacy Prometheus character set are written, see
+/// [`negotiate_escaping`](super::negotiate_escaping).
+///
+/// The schemes correspond to the `escaping` parameter of the Prometheus content negotiation.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NameEscaping {
+    /// Replaces illegal characters with underscores as the OpenTelemetry specification requires, runs of them by a
+    /// single one in metric names, e.g. `http.server.duration` becomes `http_server_duration`.
+    #[default]
+    Underscores,
+    /// Keeps the names as they are, quoting those with illegal characters like `{"http.server.duration"}`.
+    ///
+    /// The quoted syntax is understood by Prometheus 3 and later.
+    AllowUtf8,
+    /// Replaces `.` with `_dot_`, `_` with `__` and any other illegal character with `__`, so that
+    /// `http.server.duration` becomes `http_dot_server_dot_duration`.
+    Dots,
+    /// Prefixes names with illegal characters with `U__` and replaces `_` with `__` and any other illegal character
+    /// with its code point in hex between underscores, so that `http.server` becomes `U__http_2e_server`.
+    Values,
+}
+
+/// Options controlling how metrics are converted to OpenMetrics.
+///
+/// The cargo features `otel_scope_info` and `experimental-histogram-min-max` only set the defaults of
+/// [`ConvertOptions::with_scope_info`], [`ConvertOptions::with_scope_labels`] and
+/// [`ConvertOptions::with_histogram_min_max`], so that they can be overridden where the features are unified across
+/// a workspace.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConvertOptions {
+    pub(crate) exponential_buckets: ExponentialBuckets,
+    pub(crate) native_histograms: bool,
+    pub(crate) start_timestamps: bool,
+    pub(crate) scope_info: bool,
+    pub(crate) scope_labels: bool,
+    pub(crate) histogram_min_max: bool,
+    pub(crate) exemplars: bool,
+    pub(crate) promoted_resource_attributes: Vec<String>,
+    pub(crate) lenient: bool,
+    pub(crate) text_format: TextFormat,
+    pub(crate) timestamps: Timestamps,
+    pub(crate) name_escaping: NameEscaping,
+    pub(crate) unit_suffixes: bool,
+    pub(crate) type_suffixes: bool,
+    pub(crate) unit_mapping: UnitMapping,
+}
+
+/// The options used by [`super::WriteOpenMetrics::write_as_openmetrics`].
+pub(crate) static DEFAULT_OPTIONS: ConvertOptions = ConvertOptions::new();
+
+impl ConvertOptions {
+    /// Creates the default options.
+    pub const fn new() -> Self {
+        Self {
+            exponential_buckets: ExponentialBuckets::DEFAULT,
+            native_histograms: false,
+            start_timestamps: false,
+            scope_info: cfg!(feature = "otel_scope_info"),
+            scope_labels: cfg!(feature = "otel_scope_info"),
+            histogram_min_max: cfg!(feature = "experimental-histogram-min-max"),
+            exemplars: true,
+            promoted_resource_attributes: Vec::new(),
+            lenient: true,
+            text_format: TextFormat::OpenMetrics,
+            timestamps: Timestamps::Native,
+            name_escaping: NameEscaping::Underscores,
+            unit_suffixes: true,
+            type_suffixes: true,
+            unit_mapping: UnitMapping::new(),
+        }
+    }
+
+    /// Sets the buckets which exponential histograms are converted into.
+    pub fn with_exponential_buckets(mut self, buckets: ExponentialBuckets) -> Self {
+        self.exponential_buckets = buckets.normalized();
+        self
+    }
+
+    /// Writes exponential histograms as Prometheus native histograms instead of converting their buckets.
+    ///
+    /// The native histogram syntax is not part of OpenMetrics 1.0, so it is only written in
+    /// [`TextFormat::OpenMetrics2`] and the protobuf format. Histograms with a scale below -4, and all histograms
+    /// written in the other text formats, are still converted into explicit buckets.
+    pub fn with_native_histograms(mut self, enabled: bool) -> Self {
+        self.native_histograms = enabled;
+        self
+    }
+
+    /// Writes the start of the interval of counters and histograms as `st@` start timestamps after the timestamp of
+    /// each of their samples, instead of as `_created` samples.
+    ///
+    /// The start timestamp syntax is not part of OpenMetrics 1.0 and only understood by scrapers which support the
+    /// OpenMetrics 2.0 draft, so it is only written in [`TextFormat::OpenMetrics2`]. [`TextFormat::OpenMetrics`] keeps
+    /// the `_created` samples, and [`TextFormat::Prometheus`] has neither.
+    pub fn with_start_timestamps(mut self, enabled: bool) -> Self {
+        self.start_timestamps = enabled;
+        self
+    }
+
+    /// Sets whether the resource is written as a `target_info` metric and the instrumentation scopes as an
+    /// `otel_scope_info` metric. Enabled by default with the `otel_scope_info` feature.
+    pub fn with_scope_info(mut self, enabled: bool) -> Self {
+        self.scope_info = enabled;
+        self
+    }
+
+    /// Sets whether all samples are labelled with the name of their instrumentation scope as `otel_scope_name`.
+    /// Enabled by default with the `otel_scope_info` feature.
+    ///
+    /// Without it, only metrics reported by several scopes are labelled, to keep their series apart.
+    pub fn with_scope_labels(mut self, enabled: bool) -> Self {
+        self.scope_labels = enabled;
+        self
+    }
+
+    /// Sets whether the recorded minimum and maximum of histograms are written as gauge families of their own,
+    /// named like the histogram with `_min` and `_max` before the unit suffix, e.g. `http_server_duration_min_seconds`
+    /// for `http_server_duration_seconds`. Enabled by default with the `experimental-histogram-min-max` feature.
+    ///
+    /// Their series have the same labels as those of the histogram. They are not written in the protobuf format.
+    pub fn with_histogram_min_max(mut self, enabled: bool) -> Self {
+        self.histogram_min_max = enabled;
+        self
+    }
+
+    /// Sets whether the exemplars of counters and histograms are written after their `_total` and `_bucket`
+    /// samples, with the IDs of the trace and span they were recorded in. Enabled by default.
+    ///
+    /// Only the most recent exemplar of each sample is written. Exemplars are not part of
+    /// [`TextFormat::Prometheus`], so they are only written in the OpenMetrics formats.
+    pub fn with_exemplars(mut self, enabled: bool) -> Self {
+        self.exemplars = enabled;
+        self
+    }
+
+    /// Sets the resource attributes which are copied onto every sample as labels, like `service.name` or
+    /// `k8s.pod.name`, so that they can be queried without joining `target_info`. None by default.
+    ///
+    /// An entry ending with `*` selects all attributes starting with the rest of it, like `k8s.*`. Attributes of
+    /// the data points and the scope labels take precedence over promoted resource attributes with the same label
+    /// name. The promoted attributes are still written in `target_info`.
+    pub fn with_promoted_resource_attributes(
+        mut self,
+        keys: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        self.promoted_resource_attributes = keys.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets whether metrics which cannot be converted, like delta sums without a
+    /// [`DeltaAccumulator`](super::DeltaAccumulator), colliding names or negative counters, are skipped instead of
+    /// failing the conversion. Enabled by default, disable it for a strict conversion.
+    ///
+    /// The errors of the skipped metrics are returned as diagnostics alongside the output, and logged with the
+    /// `tracing` feature.
+    pub fn with_lenient(mut self, enabled: bool) -> Self {
+        self.lenient = enabled;
+        self
+    }
+
+    /// Sets the text format which metrics are written in, [`TextFormat::OpenMetrics`] by default.
+    pub fn with_text_format(mut self, format: TextFormat) -> Self {
+        self.text_format = format;
+        self
+    }
+
+    /// Sets how the timestamps of samples are written, [`Timestamps::Native`] by default.
+    ///
+    /// OpenMetrics specifies timestamps in seconds and the Prometheus text format in milliseconds, so only use the
+    /// other unit for consumers expecting it. The protobuf format is always written in milliseconds, unless
+    /// timestamps are omitted.
+    pub fn with_timestamps(mut self, timestamps: Timestamps) -> Self {
+        self.timestamps = timestamps;
+        self
+    }
+
+    /// Sets how names with characters outside of the legacy Prometheus character set are written,
+    /// [`NameEscaping::Underscores`] by default.
+    pub fn with_name_escaping(mut self, escaping: NameEscaping) -> Self {
+        self.name_escaping = escaping;
+        self
+    }
+
+    /// Sets whether metric names are suffixed with their unit, like `http_server_duration_seconds`. Enabled by
+    /// default.
+    ///
+    /// Names already ending with their unit are never suffixed twice. Without the suffix, the `# UNIT` metadata is
+    /// omitted, as OpenMetrics requires the unit to be part of the name.
+    pub fn with_unit_suffixes(mut self, enabled: bool) -> Self {
+        self.unit_suffixes = enabled;
+        self
+    }
+
+    /// Sets the overrides of the unit suffixes which the units of metrics are translated into.
+    pub fn with_unit_mapping(mut self, mapping: UnitMapping) -> Self {
+        self.unit_mapping = mapping;
+        self
+    }
+
+    /// Sets whether counter samples are suffixed with `_total`. Enabled by default.
+    ///
+    /// Names already ending with `_total` are never suffixed twice. The suffix is only dropped in
+    /// [`TextFormat::Prometheus`] and the protobuf format, as OpenMetrics requires it for counters.
+    pub fn with_type_suffixes(mut self, enabled: bool) -> Self {
+        self.type_suffixes = enabled;
+        self
+    }
+}
+
+impl Default for ConvertOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
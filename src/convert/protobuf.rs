@@ -0,0 +1,873 @@
+//! Encoding of metrics in the Prometheus protobuf exposition format.
+//!
+//! Every metric family is written as a length-delimited `io.prometheus.client.MetricFamily` message as defined in
+//! [metrics.proto](https://github.com/prometheus/client_model/blob/master/io/prometheus/client/metrics.proto).
+//! The few messages involved are encoded by hand, so no protobuf library is needed. Metric names, unit suffixes
+//! and labels are the same as in the OpenMetrics text format.
+
+use std::time::SystemTime;
+
+use opentelemetry::{InstrumentationScope, Key, KeyValue, Value};
+use opentelemetry_sdk::metrics::data::{
+    AggregatedMetrics, ExponentialHistogram, Gauge, MetricData, ResourceMetrics,
+};
+
+use super::cumulative::{AccumulatedData, CumulativeData};
+use super::data::{HistogramLike, HistogramPoint, NumberPoint, SumLike, ToF64};
+use super::exponential::RebucketedHistogram;
+use super::native::{BucketSpan, NativeHistogram};
+use super::options::DEFAULT_OPTIONS;
+use super::{
+    Accumulated, ConvertOptions, DeltaAccumulator, MetricEntry, NameEscaping, Timestamps,
+    collect_families, collect_scopes, hash_attrs, make_scope_attrs, millis_since_epoch,
+    promote_resource_attrs, sanitize_labels, series_attrs,
+};
+use crate::error::{Diagnostics, Error};
+
+/// The mime type of the protobuf exposition format produced by [`WriteProtobuf`].
+pub const PROTOBUF_MIME_TYPE: &str =
+    "application/vnd.google.protobuf; proto=io.prometheus.client.MetricFamily; encoding=delimited";
+
+/// Trait to write the metrics data in the Prometheus protobuf exposition format.
+pub trait WriteProtobuf {
+    /// Appends the metrics to `buf` as length-delimited `MetricFamily` messages.
+    fn write_as_protobuf(&self, buf: &mut Vec<u8>) -> Result<(), Error> {
+        self.write_as_protobuf_with(buf, &DEFAULT_OPTIONS)?;
+        Ok(())
+    }
+    /// Appends the metrics to `buf` as length-delimited `MetricFamily` messages, converted according to `options`.
+    ///
+    /// Returns the errors of the metrics which were skipped in lenient mode, see [`ConvertOptions::with_lenient`].
+    fn write_as_protobuf_with(
+        &self,
+        buf: &mut Vec<u8>,
+        options: &ConvertOptions,
+    ) -> Result<Vec<Error>, Error>;
+    /// Creates and returns the metrics data encoded as length-delimited `MetricFamily` messages.
+    fn to_protobuf_bytes(&self) -> Result<Vec<u8>, Error> {
+        let mut buf = Vec::new();
+        self.write_as_protobuf(&mut buf)?;
+        Ok(buf)
+    }
+}
+
+impl WriteProtobuf for ResourceMetrics {
+    /// Appends the metrics to `buf` as length-delimited `MetricFamily` messages, converted according to `options`.
+    ///
+    /// Sums and histograms with delta temporality are skipped, use a [`DeltaAccumulator`] to write those.
+    fn write_as_protobuf_with(
+        &self,
+        buf: &mut Vec<u8>,
+        options: &ConvertOptions,
+    ) -> Result<Vec<Error>, Error> {
+        encode_resource_metrics(buf, options, self, None)
+    }
+}
+
+impl WriteProtobuf for Accumulated<'_> {
+    fn write_as_protobuf_with(
+        &self,
+        buf: &mut Vec<u8>,
+        options: &ConvertOptions,
+    ) -> Result<Vec<Error>, Error> {
+        encode_resource_metrics(buf, options, self.metrics, Some(self.accumulator))
+    }
+}
+
+/// Field numbers of the messages in metrics.proto
+mod field {
+    pub const FAMILY_NAME: u32 = 1;
+    pub const FAMILY_HELP: u32 = 2;
+    pub const FAMILY_TYPE: u32 = 3;
+    pub const FAMILY_METRIC: u32 = 4;
+    pub const FAMILY_UNIT: u32 = 5;
+
+    pub const METRIC_LABEL: u32 = 1;
+    pub const METRIC_GAUGE: u32 = 2;
+    pub const METRIC_COUNTER: u32 = 3;
+    pub const METRIC_TIMESTAMP_MS: u32 = 6;
+    pub const METRIC_HISTOGRAM: u32 = 7;
+
+    pub const LABEL_NAME: u32 = 1;
+    pub const LABEL_VALUE: u32 = 2;
+
+    pub const VALUE: u32 = 1;
+    pub const COUNTER_CREATED_TIMESTAMP: u32 = 3;
+
+    pub const HISTOGRAM_SAMPLE_COUNT: u32 = 1;
+    pub const HISTOGRAM_SAMPLE_SUM: u32 = 2;
+    pub const HISTOGRAM_BUCKET: u32 = 3;
+    pub const HISTOGRAM_SCHEMA: u32 = 5;
+    pub const HISTOGRAM_ZERO_THRESHOLD: u32 = 6;
+    pub const HISTOGRAM_ZERO_COUNT: u32 = 7;
+    pub const HISTOGRAM_NEGATIVE_SPAN: u32 = 9;
+    pub const HISTOGRAM_NEGATIVE_DELTA: u32 = 10;
+    pub const HISTOGRAM_POSITIVE_SPAN: u32 = 12;
+    pub const HISTOGRAM_POSITIVE_DELTA: u32 = 13;
+    pub const HISTOGRAM_CREATED_TIMESTAMP: u32 = 15;
+
+    pub const BUCKET_CUMULATIVE_COUNT: u32 = 1;
+    pub const BUCKET_UPPER_BOUND: u32 = 2;
+
+    pub const SPAN_OFFSET: u32 = 1;
+    pub const SPAN_LENGTH: u32 = 2;
+
+    pub const TIMESTAMP_SECONDS: u32 = 1;
+    pub const TIMESTAMP_NANOS: u32 = 2;
+}
+
+/// The `io.prometheus.client.MetricType` enum
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum MetricType {
+    Counter = 0,
+    Gauge = 1,
+    Histogram = 4,
+}
+
+impl MetricType {
+    /// Maps an OpenMetrics type as returned by [`get_entry_type`] onto the protobuf enum.
+    fn from_openmetrics(typ: &str) -> Self {
+        match typ {
+            "counter" => MetricType::Counter,
+            "histogram" => MetricType::Histogram,
+            _ => MetricType::Gauge,
+        }
+    }
+}
+
+/// Encoding context for common variables needed during conversion, like [`super::Context`].
+struct Encoder<'f> {
+    /// the output buffer
+    buf: &'f mut Vec<u8>,
+    /// the sanitized name of the current metric
+    name: String,
+    /// the options for the conversion
+    options: &'f ConvertOptions,
+}
+
+fn encode_resource_metrics(
+    buf: &mut Vec<u8>,
+    options: &ConvertOptions,
+    resource_metrics: &ResourceMetrics,
+    accumulator: Option<&DeltaAccumulator>,
+) -> Result<Vec<Error>, Error> {
+    let mut enc = Encoder {
+        buf,
+        name: String::with_capacity(64),
+        options,
+    };
+    let mut diagnostics = Diagnostics::new(options.lenient);
+    let scopes = collect_scopes(resource_metrics, accumulator);
+    let families = collect_families(&scopes, options, &mut diagnostics)?;
+
+    if options.scope_info {
+        encode_target_info(&mut enc, resource_metrics.resource());
+    }
+
+    let resource_attrs = promote_resource_attrs(resource_metrics.resource(), options);
+
+    if options.scope_info {
+        encode_otel_scope_info(&mut enc, scopes.iter().map(|(scope, _)| *scope));
+    }
+
+    // The extremes of histograms are only written in the text formats
+    for family in families
+        .into_iter()
+        .filter(|family| family.extreme.is_none())
+    {
+        let typ = MetricType::from_openmetrics(family.typ);
+        let label_scopes = enc.options.scope_labels || family.spans_scopes();
+        let label_versions = family.has_ambiguous_scopes();
+        enc.name.clear();
+        enc.name.push_str(&family.name);
+        if typ == MetricType::Counter && family.total_suffix {
+            enc.name.push_str("_total");
+        }
+
+        let mut encoded = Vec::new();
+        put_string(&mut encoded, field::FAMILY_NAME, &enc.name);
+        put_string(&mut encoded, field::FAMILY_HELP, family.description());
+        put_key(&mut encoded, field::FAMILY_TYPE, WireType::Varint);
+        put_varint(&mut encoded, typ as u64);
+        let unit = family.unit.as_deref().filter(|_| family.has_unit_suffix);
+        put_string(&mut encoded, field::FAMILY_UNIT, unit.unwrap_or(""));
+        let mut metrics = MetricEncoder {
+            family: encoded,
+            scope_name: "",
+            scope_version: None,
+            resource_attrs: &resource_attrs,
+            options: enc.options,
+        };
+        for (scope, metric) in family.metrics {
+            metrics.scope_name = if label_scopes { scope.name() } else { "" };
+            metrics.scope_version = label_versions.then(|| scope.version().unwrap_or_default());
+            match metric {
+                MetricEntry::Reported(metric) => encode_values(&mut metrics, metric.data()),
+                MetricEntry::Accumulated(_, metric) => {
+                    encode_accumulated_values(&mut metrics, &metric.data)
+                }
+            }
+        }
+        put_length_delimited(enc.buf, &metrics.family);
+    }
+    Ok(diagnostics.into_errors())
+}
+
+/// Encodes the resource attributes as a `target_info` gauge, since protobuf has no info type.
+fn encode_target_info(enc: &mut Encoder, resource: &opentelemetry_sdk::Resource) {
+    let mut family = Vec::new();
+    put_string(&mut family, field::FAMILY_NAME, "target_info");
+    put_key(&mut family, field::FAMILY_TYPE, WireType::Varint);
+    put_varint(&mut family, MetricType::Gauge as u64);
+    put_message(&mut family, field::FAMILY_METRIC, |metric| {
+        put_labels(metric, resource.iter(), enc.options.name_escaping);
+        put_message(metric, field::METRIC_GAUGE, |gauge| {
+            put_double(gauge, field::VALUE, 1.0)
+        });
+    });
+    put_length_delimited(enc.buf, &family);
+}
+
+/// Encodes an `otel_scope_info` gauge for all `scopes`, see [`super::write_otel_scope_info`].
+fn encode_otel_scope_info<'a>(
+    enc: &mut Encoder,
+    scopes: impl Iterator<Item = &'a InstrumentationScope>,
+) {
+    let mut family = Vec::new();
+    put_string(&mut family, field::FAMILY_NAME, "otel_scope_info");
+    put_key(&mut family, field::FAMILY_TYPE, WireType::Varint);
+    put_varint(&mut family, MetricType::Gauge as u64);
+    for scope in scopes {
+        let otel_attrs = &[
+            KeyValue::new("otel_scope_name", scope.name().to_owned()),
+            KeyValue::new(
+                "otel_scope_version",
+                scope.version().unwrap_or_default().to_owned(),
+            ),
+        ];
+        put_message(&mut family, field::FAMILY_METRIC, |metric| {
+            let attrs = otel_attrs.iter().chain(scope.attributes());
+            let attrs = attrs.map(|kv| (&kv.key, &kv.value));
+            put_labels(metric, attrs, enc.options.name_escaping);
+            put_message(metric, field::METRIC_GAUGE, |gauge| {
+                put_double(gauge, field::VALUE, 1.0)
+            });
+        });
+    }
+    put_length_delimited(enc.buf, &family);
+}
+
+/// The state for encoding the `Metric` messages of a single `MetricFamily`.
+struct MetricEncoder<'f> {
+    /// the encoded `MetricFamily` message
+    family: Vec<u8>,
+    /// the name of the current scope, empty if the series are not labelled with their scope
+    scope_name: &'f str,
+    /// the version of the current scope, if the series are labelled with it
+    scope_version: Option<&'f str>,
+    /// the resource attributes copied onto every series
+    resource_attrs: &'f [KeyValue],
+    /// the options for the conversion
+    options: &'f ConvertOptions,
+}
+
+impl MetricEncoder<'_> {
+    /// Appends a `Metric` message with the labels of `attributes`, the current scope and the promoted resource
+    /// attributes.
+    fn put_metric<'a>(
+        &mut self,
+        attributes: impl Iterator<Item = &'a KeyValue>,
+        time: SystemTime,
+        value: impl FnOnce(&mut Vec<u8>),
+    ) {
+        let scope_name_attrs = make_scope_attrs(self.scope_name, self.scope_version);
+        let attributes: Vec<&KeyValue> = attributes.collect();
+        let attrs = series_attrs(
+            || attributes.iter().copied(),
+            &scope_name_attrs,
+            self.resource_attrs,
+            self.options.name_escaping,
+        );
+        put_message(&mut self.family, field::FAMILY_METRIC, |metric| {
+            let attrs = attrs.map(|kv| (&kv.key, &kv.value));
+            put_labels(metric, attrs, self.options.name_escaping);
+            value(metric);
+            if self.options.timestamps != Timestamps::Omit {
+                put_key(metric, field::METRIC_TIMESTAMP_MS, WireType::Varint);
+                put_varint(metric, millis_since_epoch(time) as u64);
+            }
+        });
+    }
+}
+
+/// Encode all data points for this metric
+fn encode_values(enc: &mut MetricEncoder, metric: &AggregatedMetrics) {
+    match metric {
+        AggregatedMetrics::F64(metric_data) => match metric_data {
+            MetricData::Gauge(gauge) => encode_gauge(enc, gauge),
+            MetricData::Sum(sum) => encode_counter(enc, sum),
+            MetricData::Histogram(histogram) => encode_histogram(enc, histogram),
+            MetricData::ExponentialHistogram(histogram) => {
+                encode_exponential_histogram(enc, histogram)
+            }
+        },
+        AggregatedMetrics::U64(metric_data) => match metric_data {
+            MetricData::Gauge(gauge) => encode_gauge(enc, gauge),
+            MetricData::Sum(sum) => encode_counter(enc, sum),
+            MetricData::Histogram(histogram) => encode_histogram(enc, histogram),
+            MetricData::ExponentialHistogram(histogram) => {
+                encode_exponential_histogram(enc, histogram)
+            }
+        },
+        AggregatedMetrics::I64(metric_data) => match metric_data {
+            MetricData::Gauge(gauge) => encode_gauge(enc, gauge),
+            MetricData::Sum(sum) => encode_counter(enc, sum),
+            MetricData::Histogram(histogram) => encode_histogram(enc, histogram),
+            MetricData::ExponentialHistogram(histogram) => {
+                encode_exponential_histogram(enc, histogram)
+            }
+        },
+    }
+}
+
+/// Encode all data points of an accumulated metric
+fn encode_accumulated_values(enc: &mut MetricEncoder, metric: &AccumulatedData) {
+    match metric {
+        AccumulatedData::F64(CumulativeData::Sum(sum)) => encode_counter(enc, sum),
+        AccumulatedData::F64(CumulativeData::Histogram(histogram)) => {
+            encode_histogram(enc, histogram)
+        }
+        AccumulatedData::U64(CumulativeData::Sum(sum)) => encode_counter(enc, sum),
+        AccumulatedData::U64(CumulativeData::Histogram(histogram)) => {
+            encode_histogram(enc, histogram)
+        }
+        AccumulatedData::I64(CumulativeData::Sum(sum)) => encode_counter(enc, sum),
+        AccumulatedData::I64(CumulativeData::Histogram(histogram)) => {
+            encode_histogram(enc, histogram)
+        }
+    }
+}
+
+fn encode_gauge<T: ToF64>(enc: &mut MetricEncoder, gauge: &Gauge<T>) {
+    let mut points: Vec<_> = gauge.data_points().collect();
+    points.sort_by_cached_key(|p| hash_attrs(p.attributes()));
+    for point in points {
+        enc.put_metric(point.attributes(), gauge.time(), |metric| {
+            put_message(metric, field::METRIC_GAUGE, |gauge| {
+                put_double(gauge, field::VALUE, point.value().to_f64())
+            })
+        });
+    }
+}
+
+fn encode_counter<T: ToF64>(enc: &mut MetricEncoder, sum: &impl SumLike<T>) {
+    let value_field = if sum.is_monotonic() {
+        field::METRIC_COUNTER
+    } else {
+        field::METRIC_GAUGE
+    };
+
+    let mut points: Vec<_> = sum.data_points().collect();
+    points.sort_by_cached_key(|p| hash_attrs(p.attributes()));
+    for point in points {
+        enc.put_metric(point.attributes(), sum.time(), |metric| {
+            put_message(metric, value_field, |value| {
+                put_double(value, field::VALUE, point.value().to_f64());
+                if sum.is_monotonic() {
+                    put_timestamp(
+                        value,
+                        field::COUNTER_CREATED_TIMESTAMP,
+                        sum.start_time(point),
+                    );
+                }
+            })
+        });
+    }
+}
+
+/// Encodes a histogram with explicit buckets. Unlike the text format, the sum is kept for negative buckets.
+fn encode_histogram<T: ToF64>(enc: &mut MetricEncoder, histogram: &impl HistogramLike<T>) {
+    let mut points: Vec<_> = histogram.data_points().collect();
+    points.sort_by_cached_key(|p| hash_attrs(p.attributes()));
+    for point in points {
+        enc.put_metric(point.attributes(), histogram.time(), |metric| {
+            put_message(metric, field::METRIC_HISTOGRAM, |hist| {
+                put_uint(hist, field::HISTOGRAM_SAMPLE_COUNT, point.count());
+                put_double(hist, field::HISTOGRAM_SAMPLE_SUM, point.sum().to_f64());
+                // The +Inf bucket is implied by the sample count
+                let mut cumulative_count = 0;
+                for (bound, count) in std::iter::zip(point.bounds(), point.bucket_counts()) {
+                    cumulative_count += count;
+                    put_message(hist, field::HISTOGRAM_BUCKET, |bucket| {
+                        put_uint(bucket, field::BUCKET_CUMULATIVE_COUNT, cumulative_count);
+                        put_double(bucket, field::BUCKET_UPPER_BOUND, bound);
+                    });
+                }
+                put_timestamp(
+                    hist,
+                    field::HISTOGRAM_CREATED_TIMESTAMP,
+                    histogram.start_time(point),
+                );
+            })
+        });
+    }
+}
+
+/// Encodes an exponential histogram as a native histogram if enabled in the [`ConvertOptions`], otherwise with the
+/// buckets configured there.
+fn encode_exponential_histogram<T: ToF64 + 'static>(
+    enc: &mut MetricEncoder,
+    histogram: &ExponentialHistogram<T>,
+) {
+    if enc.options.native_histograms {
+        let native: Option<Vec<_>> = histogram
+            .data_points()
+            .map(|point| NativeHistogram::new(point).map(|native| (point, native)))
+            .collect();
+        if let Some(mut points) = native {
+            points.sort_by_cached_key(|(p, _)| hash_attrs(p.attributes()));
+            for (point, native) in points {
+                enc.put_metric(point.attributes(), histogram.time(), |metric| {
+                    put_message(metric, field::METRIC_HISTOGRAM, |hist| {
+                        put_uint(hist, field::HISTOGRAM_SAMPLE_COUNT, point.count() as u64);
+                        put_double(hist, field::HISTOGRAM_SAMPLE_SUM, point.sum().to_f64());
+                        put_native_buckets(hist, &native);
+                        put_timestamp(
+                            hist,
+                            field::HISTOGRAM_CREATED_TIMESTAMP,
+                            histogram.start_time(),
+                        );
+                    })
+                });
+            }
+            return;
+        }
+    }
+    let histogram = RebucketedHistogram::new(histogram, &enc.options.exponential_buckets);
+    encode_histogram(enc, &histogram)
+}
+
+fn put_native_buckets(hist: &mut Vec<u8>, native: &NativeHistogram) {
+    put_sint(hist, field::HISTOGRAM_SCHEMA, i64::from(native.schema));
+    put_double(hist, field::HISTOGRAM_ZERO_THRESHOLD, native.zero_threshold);
+    put_uint(hist, field::HISTOGRAM_ZERO_COUNT, native.zero_count);
+    put_spans(hist, field::HISTOGRAM_NEGATIVE_SPAN, &native.negative_spans);
+    put_packed_sint(
+        hist,
+        field::HISTOGRAM_NEGATIVE_DELTA,
+        &native.negative_deltas,
+    );
+    if native.negative_spans.is_empty() && native.positive_spans.is_empty() {
+        // Without any span an empty histogram could not be told apart from a classic one
+        put_spans(
+            hist,
+            field::HISTOGRAM_POSITIVE_SPAN,
+            &[BucketSpan {
+                offset: 0,
+                length: 0,
+            }],
+        );
+    } else {
+        put_spans(hist, field::HISTOGRAM_POSITIVE_SPAN, &native.positive_spans);
+    }
+    put_packed_sint(
+        hist,
+        field::HISTOGRAM_POSITIVE_DELTA,
+        &native.positive_deltas,
+    );
+}
+
+fn put_spans(buf: &mut Vec<u8>, field: u32, spans: &[BucketSpan]) {
+    for span in spans {
+        put_message(buf, field, |message| {
+            put_sint(message, field::SPAN_OFFSET, i64::from(span.offset));
+            put_uint(message, field::SPAN_LENGTH, u64::from(span.length));
+        });
+    }
+}
+
+/// Appends a `LabelPair` message for each label of the attributes, see [`super::sanitize_labels`].
+///
+/// Names are never quoted, as protobuf allows any UTF-8 name.
+fn put_labels<'a>(
+    buf: &mut Vec<u8>,
+    attrs: impl Iterator<Item = (&'a Key, &'a Value)>,
+    escaping: NameEscaping,
+) {
+    for (name, value) in sanitize_labels(attrs, escaping) {
+        put_message(buf, field::METRIC_LABEL, |label| {
+            put_string(label, field::LABEL_NAME, &name);
+            put_string(label, field::LABEL_VALUE, &value);
+        });
+    }
+}
+
+/// Appends a `google.protobuf.Timestamp` message.
+fn put_timestamp(buf: &mut Vec<u8>, field: u32, time: SystemTime) {
+    let (seconds, nanos) = to_seconds_and_nanos(time);
+    put_message(buf, field, |timestamp| {
+        // int64 fields encode negative values as their two's complement
+        put_uint(timestamp, field::TIMESTAMP_SECONDS, seconds as u64);
+        put_uint(timestamp, field::TIMESTAMP_NANOS, u64::from(nanos));
+    });
+}
+
+/// Splits [`SystemTime`] into the seconds and nanoseconds of a `google.protobuf.Timestamp`, where the nanoseconds
+/// count forward even for times before the epoch.
+fn to_seconds_and_nanos(time: SystemTime) -> (i64, u32) {
+    match time.duration_since(SystemTime::UNIX_EPOCH) {
+        Ok(since_epoch) => (
+            since_epoch.as_secs().try_into().unwrap_or(i64::MAX),
+            since_epoch.subsec_nanos(),
+        ),
+        Err(before_epoch) => {
+            let before_epoch = before_epoch.duration();
+            let seconds = i64::try_from(before_epoch.as_secs()).unwrap_or(i64::MAX);
+            match before_epoch.subsec_nanos() {
+                0 => (-seconds, 0),
+                nanos => (-seconds - 1, 1_000_000_000 - nanos),
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum WireType {
+    Varint = 0,
+    Fixed64 = 1,
+    LengthDelimited = 2,
+}
+
+fn put_key(buf: &mut Vec<u8>, field: u32, wire_type: WireType) {
+    put_varint(buf, u64::from(field << 3 | wire_type as u32));
+}
+
+fn put_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+/// Maps signed integers onto unsigned ones so that small magnitudes have short varints.
+fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+// Fields with default values are omitted, like protobuf encoders do.
+
+fn put_uint(buf: &mut Vec<u8>, field: u32, value: u64) {
+    if value != 0 {
+        put_key(buf, field, WireType::Varint);
+        put_varint(buf, value);
+    }
+}
+
+fn put_sint(buf: &mut Vec<u8>, field: u32, value: i64) {
+    if value != 0 {
+        put_key(buf, field, WireType::Varint);
+        put_varint(buf, zigzag(value));
+    }
+}
+
+fn put_double(buf: &mut Vec<u8>, field: u32, value: f64) {
+    if value.to_bits() != 0 {
+        put_key(buf, field, WireType::Fixed64);
+        buf.extend_from_slice(&value.to_le_bytes());
+    }
+}
+
+fn put_string(buf: &mut Vec<u8>, field: u32, value: &str) {
+    if !value.is_empty() {
+        put_key(buf, field, WireType::LengthDelimited);
+        put_length_delimited(buf, value.as_bytes());
+    }
+}
+
+fn put_packed_sint(buf: &mut Vec<u8>, field: u32, values: &[i64]) {
+    if !values.is_empty() {
+        put_message(buf, field, |packed| {
+            for &value in values {
+                put_varint(packed, zigzag(value));
+            }
+        });
+    }
+}
+
+/// Appends an embedded message, which is encoded by `encode` directly into `buf`.
+/// Messages are always written, even if all their fields have default values.
+fn put_message(buf: &mut Vec<u8>, field: u32, encode: impl FnOnce(&mut Vec<u8>)) {
+    put_key(buf, field, WireType::LengthDelimited);
+    let start = buf.len();
+    encode(buf);
+    // The length is only known now, so its varint has to be inserted in front of the message
+    let mut length = Vec::with_capacity(10);
+    put_varint(&mut length, (buf.len() - start) as u64);
+    buf.splice(start..start, length);
+}
+
+fn put_length_delimited(buf: &mut Vec<u8>, bytes: &[u8]) {
+    put_varint(buf, bytes.len() as u64);
+    buf.extend_from_slice(bytes);
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use ottotom_testsupport::resource_metrics::make_test_metrics;
+
+    use super::*;
+
+    /// A decoded field value, only as far as needed by the tests.
+    #[derive(Debug, PartialEq)]
+    enum Decoded<'a> {
+        Varint(u64),
+        Fixed64(f64),
+        Bytes(&'a [u8]),
+    }
+
+    fn read_varint(bytes: &mut &[u8]) -> u64 {
+        let mut value = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = bytes[0];
+            *bytes = &bytes[1..];
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte < 0x80 {
+                break;
+            }
+        }
+        value
+    }
+
+    fn read_length_delimited<'a>(bytes: &mut &'a [u8]) -> &'a [u8] {
+        let len = read_varint(bytes) as usize;
+        let (message, rest) = bytes.split_at(len);
+        *bytes = rest;
+        message
+    }
+
+    fn decode(mut bytes: &[u8]) -> Vec<(u32, Decoded<'_>)> {
+        let mut fields = Vec::new();
+        while !bytes.is_empty() {
+            let key = read_varint(&mut bytes);
+            let value = match key & 0b111 {
+                0 => Decoded::Varint(read_varint(&mut bytes)),
+                1 => {
+                    let (value, rest) = bytes.split_at(8);
+                    bytes = rest;
+                    Decoded::Fixed64(f64::from_le_bytes(value.try_into().unwrap()))
+                }
+                2 => Decoded::Bytes(read_length_delimited(&mut bytes)),
+                wire_type => panic!("unexpected wire type {wire_type}"),
+            };
+            fields.push(((key >> 3) as u32, value));
+        }
+        fields
+    }
+
+    fn get<'a>(fields: &'a [(u32, Decoded<'a>)], field: u32) -> Vec<&'a Decoded<'a>> {
+        fields
+            .iter()
+            .filter(|(number, _)| *number == field)
+            .map(|(_, value)| value)
+            .collect()
+    }
+
+    fn get_str<'a>(fields: &'a [(u32, Decoded<'a>)], field: u32) -> &'a str {
+        match get(fields, field).first() {
+            Some(Decoded::Bytes(bytes)) => std::str::from_utf8(bytes).unwrap(),
+            _ => "",
+        }
+    }
+
+    fn get_message<'a>(fields: &'a [(u32, Decoded<'a>)], field: u32) -> Vec<(u32, Decoded<'a>)> {
+        match get(fields, field).first() {
+            Some(Decoded::Bytes(bytes)) => decode(bytes),
+            other => panic!("expected message in field {field}, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_put_varint() {
+        let mut buf = Vec::new();
+        put_varint(&mut buf, 1);
+        put_varint(&mut buf, 300);
+        put_varint(&mut buf, u64::MAX);
+        assert_eq!(
+            buf,
+            [
+                1, 0xac, 0x02, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01
+            ]
+        );
+    }
+
+    #[test]
+    fn test_zigzag() {
+        assert_eq!(zigzag(0), 0);
+        assert_eq!(zigzag(-1), 1);
+        assert_eq!(zigzag(1), 2);
+        assert_eq!(zigzag(-2), 3);
+        assert_eq!(zigzag(i64::MIN), u64::MAX);
+    }
+
+    #[test]
+    fn test_to_seconds_and_nanos() {
+        let epoch = SystemTime::UNIX_EPOCH;
+        assert_eq!(
+            to_seconds_and_nanos(epoch + Duration::from_millis(1500)),
+            (1, 500_000_000)
+        );
+        assert_eq!(
+            to_seconds_and_nanos(epoch - Duration::from_secs(2)),
+            (-2, 0)
+        );
+        assert_eq!(
+            to_seconds_and_nanos(epoch - Duration::from_millis(1500)),
+            (-2, 500_000_000)
+        );
+    }
+
+    #[test]
+    fn test_put_message() {
+        let mut buf = Vec::new();
+        put_message(&mut buf, 4, |message| {
+            put_string(message, 1, "abc");
+            put_message(message, 2, |_| {});
+        });
+        assert_eq!(
+            buf,
+            [
+                4 << 3 | 2,
+                7,
+                1 << 3 | 2,
+                3,
+                b'a',
+                b'b',
+                b'c',
+                2 << 3 | 2,
+                0
+            ]
+        );
+    }
+
+    #[test]
+    fn test_write_as_protobuf() {
+        let bytes = make_test_metrics().to_protobuf_bytes().unwrap();
+        let mut rest = bytes.as_slice();
+        let mut families = Vec::new();
+        while !rest.is_empty() {
+            families.push(decode(read_length_delimited(&mut rest)));
+        }
+
+        let names: Vec<_> = families
+            .iter()
+            .map(|family| get_str(family, field::FAMILY_NAME))
+            .collect();
+        let mut expected = vec![
+            "f64_gauge",
+            "histo",
+            "histo_exp",
+            "u64_counter_seconds_total",
+        ];
+        if cfg!(feature = "otel_scope_info") {
+            expected.splice(0..0, ["target_info", "otel_scope_info"]);
+        }
+        assert_eq!(names, expected);
+
+        let counter = families.last().unwrap();
+        assert_eq!(
+            get(counter, field::FAMILY_TYPE),
+            [&Decoded::Varint(MetricType::Counter as u64)]
+        );
+        assert_eq!(get_str(counter, field::FAMILY_UNIT), "seconds");
+        let metric = get_message(counter, field::FAMILY_METRIC);
+        let value = get_message(&metric, field::METRIC_COUNTER);
+        assert_eq!(value[0], (field::VALUE, Decoded::Fixed64(125.0)));
+        assert_eq!(get(&value, field::COUNTER_CREATED_TIMESTAMP).len(), 1);
+        if cfg!(feature = "otel_scope_info") {
+            let label = get_message(&metric, field::METRIC_LABEL);
+            assert_eq!(get_str(&label, field::LABEL_NAME), "otel_scope_name");
+            assert_eq!(get_str(&label, field::LABEL_VALUE), "meter.1");
+        }
+
+        let gauge = families
+            .iter()
+            .find(|family| get_str(family, field::FAMILY_NAME) == "f64_gauge")
+            .unwrap();
+        assert_eq!(
+            get_str(gauge, field::FAMILY_HELP),
+            "A \"gauge\"\nFor testing"
+        );
+        assert_eq!(get(gauge, field::FAMILY_METRIC).len(), 2);
+
+        let histogram = families
+            .iter()
+            .find(|family| get_str(family, field::FAMILY_NAME) == "histo")
+            .unwrap();
+        let metric = get_message(histogram, field::FAMILY_METRIC);
+        let histogram = get_message(&metric, field::METRIC_HISTOGRAM);
+        assert_eq!(
+            get(&histogram, field::HISTOGRAM_SAMPLE_COUNT),
+            [&Decoded::Varint(4)]
+        );
+        assert_eq!(
+            get(&histogram, field::HISTOGRAM_SAMPLE_SUM),
+            [&Decoded::Fixed64(15.7)]
+        );
+        assert_eq!(get(&histogram, field::HISTOGRAM_BUCKET).len(), 15);
+        assert_eq!(get(&histogram, field::HISTOGRAM_CREATED_TIMESTAMP).len(), 1);
+    }
+
+    #[test]
+    fn test_write_native_histogram_as_protobuf() {
+        let options = ConvertOptions::default().with_native_histograms(true);
+        let mut bytes = Vec::new();
+        make_test_metrics()
+            .write_as_protobuf_with(&mut bytes, &options)
+            .unwrap();
+        let mut rest = bytes.as_slice();
+        let family = std::iter::from_fn(|| {
+            (!rest.is_empty()).then(|| decode(read_length_delimited(&mut rest)))
+        })
+        .find(|family| get_str(family, field::FAMILY_NAME) == "histo_exp")
+        .unwrap();
+
+        let metric = get_message(&family, field::FAMILY_METRIC);
+        let histogram = get_message(&metric, field::METRIC_HISTOGRAM);
+        assert!(get(&histogram, field::HISTOGRAM_BUCKET).is_empty());
+        assert_eq!(
+            get(&histogram, field::HISTOGRAM_ZERO_COUNT),
+            [&Decoded::Varint(1)]
+        );
+        assert!(get(&histogram, field::HISTOGRAM_NEGATIVE_SPAN).is_empty());
+        assert!(!get(&histogram, field::HISTOGRAM_POSITIVE_SPAN).is_empty());
+    }
+
+    #[test]
+    fn test_write_promoted_resource_attributes_as_protobuf() {
+        let options = ConvertOptions::default()
+            .with_scope_labels(false)
+            .with_promoted_resource_attributes(["service.name"]);
+        let mut bytes = Vec::new();
+        make_test_metrics()
+            .write_as_protobuf_with(&mut bytes, &options)
+            .unwrap();
+        let mut rest = bytes.as_slice();
+        let family = std::iter::from_fn(|| {
+            (!rest.is_empty()).then(|| decode(read_length_delimited(&mut rest)))
+        })
+        .find(|family| get_str(family, field::FAMILY_NAME) == "u64_counter_seconds_total")
+        .unwrap();
+
+        let metric = get_message(&family, field::FAMILY_METRIC);
+        let labels = get(&metric, field::METRIC_LABEL);
+        assert_eq!(labels.len(), 1);
+        let Decoded::Bytes(label) = labels[0] else {
+            panic!("label is not a message");
+        };
+        let label = decode(label);
+        assert_eq!(get_str(&label, field::LABEL_NAME), "service_name");
+    }
+}
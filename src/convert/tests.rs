@@ -1,11 +1,18 @@
 use std::time::UNIX_EPOCH;
 
+use cumulative::{CumulativeHistogram, CumulativeSum};
+use data::test::{TestExemplar, TestHistogram, TestHistogramPoint, TestPoint, TestSum, at};
 use insta::assert_snapshot;
 use opentelemetry::KeyValue;
+use opentelemetry::metrics::MeterProvider;
+use opentelemetry_sdk::metrics::SdkMeterProvider;
 use opentelemetry_sdk::metrics::data::ScopeMetrics;
+use opentelemetry_sdk::metrics::reader::MetricReader;
 use ottotom_testsupport::metric_data::{
-    make_f64_gauge_metric, make_f64_histogram_metric, make_u64_counter_metric,
+    make_f64_exponential_histogram_metric, make_f64_gauge_metric, make_f64_histogram_metric,
+    make_u64_counter_metric,
 };
+use ottotom_testsupport::reader::TestMetricsReader;
 use ottotom_testsupport::resource_metrics::make_test_metrics;
 use ufmt::uwrite;
 
@@ -51,6 +58,38 @@ fn test_write_escaped() {
     assert_eq!(output, "Simple string");
 }
 
+#[test]
+fn test_write_escaped_help() {
+    let mut output = String::new();
+    write_escaped_help(&mut output, "Line 1\nWindows \"quoted\" \\ BS").unwrap();
+    assert_eq!(output, "Line 1\\nWindows \"quoted\" \\\\ BS");
+}
+
+#[test]
+fn test_write_prometheus_header() {
+    let mut output = String::new();
+    let options = ConvertOptions::default().with_text_format(TextFormat::Prometheus);
+    let mut ctx = Context {
+        name: "requests".to_owned(),
+        unit: Some(Cow::Borrowed("seconds")),
+        typ: "counter",
+        ..Context::with_options(&mut output, &options)
+    };
+    write_header(&mut ctx, "Handled \"requests\"").unwrap();
+    ctx.typ = "info";
+    write_header(&mut ctx, "").unwrap();
+    ctx.typ = "stateset";
+    write_header(&mut ctx, "").unwrap();
+
+    assert_eq!(
+        output,
+        "# HELP requests_total Handled \"requests\"\n\
+         # TYPE requests_total counter\n\
+         # TYPE requests gauge\n\
+         # TYPE requests untyped\n"
+    );
+}
+
 #[test]
 fn test_hash_attrs() {
     let attrs = [
@@ -87,7 +126,7 @@ fn test_write_attrs() {
         KeyValue::new("key2", "value2"),
     ];
 
-    write_attrs(&mut output, attrs.iter()).unwrap();
+    write_attrs(&mut output, attrs.iter(), NameEscaping::Underscores).unwrap();
     assert_eq!(output, "key1=\"value1\",key2=\"value2\"");
 
     // Test with attributes containing characters that need escaping
@@ -97,27 +136,99 @@ fn test_write_attrs() {
         KeyValue::new("key2", "value\"with\"quotes"),
     ];
 
-    write_attrs(&mut output, attrs_with_escapes.iter()).unwrap();
+    write_attrs(
+        &mut output,
+        attrs_with_escapes.iter(),
+        NameEscaping::Underscores,
+    )
+    .unwrap();
     assert_eq!(
         output,
         "key1=\"value\\nwith\\nnewlines\",key2=\"value\\\"with\\\"quotes\""
     );
+
+    // Keys which sanitize to the same label are merged in the order of the original keys
+    output.clear();
+    let colliding_attrs = [
+        KeyValue::new("http_method", "b"),
+        KeyValue::new("z", 1),
+        KeyValue::new("http.method", "a\"1"),
+        KeyValue::new("http/method", "c"),
+    ];
+    write_attrs(
+        &mut output,
+        colliding_attrs.iter(),
+        NameEscaping::Underscores,
+    )
+    .unwrap();
+    assert_eq!(output, "http_method=\"a\\\"1;c;b\",z=\"1\"");
+
+    // Names outside of the legacy character set are quoted, or escaped according to the scheme
+    let utf8_attrs = [KeyValue::new("http.method", "GET"), KeyValue::new("z", 1)];
+    output.clear();
+    write_attrs(&mut output, utf8_attrs.iter(), NameEscaping::AllowUtf8).unwrap();
+    assert_eq!(output, "\"http.method\"=\"GET\",z=\"1\"");
+    output.clear();
+    write_attrs(&mut output, utf8_attrs.iter(), NameEscaping::Dots).unwrap();
+    assert_eq!(output, "http_dot_method=\"GET\",z=\"1\"");
+    output.clear();
+    write_attrs(&mut output, utf8_attrs.iter(), NameEscaping::Values).unwrap();
+    assert_eq!(output, "U__http_2e_method=\"GET\",z=\"1\"");
+}
+
+#[test]
+fn test_sanitize_label_name() {
+    assert_eq!(
+        sanitize_label_name("valid_Label1", NameEscaping::Underscores),
+        "valid_Label1"
+    );
+    assert_eq!(
+        sanitize_label_name("http.request..method", NameEscaping::Underscores),
+        "http_request__method"
+    );
+    assert_eq!(
+        sanitize_label_name("scope:name", NameEscaping::Underscores),
+        "scope_name"
+    );
+    assert_eq!(
+        sanitize_label_name("a__b", NameEscaping::Underscores),
+        "a__b"
+    );
+    assert_eq!(
+        sanitize_label_name("2xx", NameEscaping::Underscores),
+        "key_2xx"
+    );
+    assert_eq!(
+        sanitize_label_name("__name__", NameEscaping::Underscores),
+        "key__name__"
+    );
+    assert_eq!(
+        sanitize_label_name("_private", NameEscaping::Underscores),
+        "key_private"
+    );
+    assert_eq!(
+        sanitize_label_name(".hidden", NameEscaping::Underscores),
+        "key_hidden"
+    );
+    assert_eq!(sanitize_label_name("ä", NameEscaping::Underscores), "key_");
+    assert_eq!(sanitize_label_name("", NameEscaping::Underscores), "key");
 }
 
 #[test]
-fn test_make_scope_name_attrs() {
-    let scope_name = "test_scope";
-    let attr = make_scope_name_attrs(scope_name);
+fn test_make_scope_attrs() {
+    let attrs = make_scope_attrs("test_scope", None);
+    assert_eq!(attrs, vec![KeyValue::new("otel_scope_name", "test_scope")]);
 
-    if cfg!(feature = "otel_scope_info") {
-        assert!(attr.is_some());
-        if let Some(kv) = attr {
-            assert_eq!(kv.key.as_str(), "otel_scope_name");
-            assert_eq!(kv.value.as_str(), "test_scope");
-        }
-    } else {
-        assert!(attr.is_none());
-    }
+    let attrs = make_scope_attrs("test_scope", Some("1.0"));
+    assert_eq!(
+        attrs,
+        vec![
+            KeyValue::new("otel_scope_name", "test_scope"),
+            KeyValue::new("otel_scope_version", "1.0"),
+        ]
+    );
+
+    assert!(make_scope_attrs("", Some("1.0")).is_empty());
 }
 
 #[test]
@@ -126,20 +237,364 @@ fn test_to_timestamp() {
 
     // Test with a known timestamp
     let time = UNIX_EPOCH + Duration::from_secs(1625097600);
-    let timestamp = to_timestamp(time);
+    let timestamp = to_timestamp(time, TextFormat::OpenMetrics);
     let mut output = String::new();
     uwrite!(output, "{}", timestamp).unwrap();
     assert_eq!(output, "1625097600");
+
+    let time = time + Duration::from_micros(1500);
+    output.clear();
+    uwrite!(output, "{}", to_timestamp(time, TextFormat::OpenMetrics)).unwrap();
+    assert_eq!(output, "1625097600.0015");
+    output.clear();
+    uwrite!(output, "{}", to_timestamp(time, TextFormat::Prometheus)).unwrap();
+    assert_eq!(output, "1625097600001");
+}
+
+#[test]
+fn test_to_timestamp_before_epoch() {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    let time = UNIX_EPOCH - Duration::from_millis(1500);
+    let mut output = String::new();
+    uwrite!(output, "{}", to_timestamp(time, TextFormat::OpenMetrics)).unwrap();
+    assert_eq!(output, "-1.5");
+    output.clear();
+    uwrite!(output, "{}", to_timestamp(time, TextFormat::Prometheus)).unwrap();
+    assert_eq!(output, "-1500");
+}
+
+#[test]
+fn test_sample_timestamp() {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    let time = UNIX_EPOCH + Duration::from_millis(1625097600500);
+    let show = |options: ConvertOptions| {
+        let mut output = String::new();
+        uwrite!(output, "{}", SampleTimestamp::new(time, &options)).unwrap();
+        output
+    };
+
+    assert_eq!(show(ConvertOptions::default()), " 1625097600.5");
+    assert_eq!(
+        show(ConvertOptions::default().with_text_format(TextFormat::Prometheus)),
+        " 1625097600500"
+    );
+    assert_eq!(
+        show(ConvertOptions::default().with_timestamps(Timestamps::Omit)),
+        ""
+    );
+    assert_eq!(
+        show(ConvertOptions::default().with_timestamps(Timestamps::Milliseconds)),
+        " 1625097600500"
+    );
+    assert_eq!(
+        show(
+            ConvertOptions::default()
+                .with_text_format(TextFormat::Prometheus)
+                .with_timestamps(Timestamps::Seconds)
+        ),
+        " 1625097600.5"
+    );
+}
+
+#[test]
+fn test_omit_timestamps() {
+    let reader = TestMetricsReader::default();
+    let meter_provider = SdkMeterProvider::builder()
+        .with_reader(reader.clone())
+        .build();
+    meter_provider
+        .meter("meter")
+        .u64_counter("requests")
+        .build()
+        .add(125, &[KeyValue::new("kk", "v1")]);
+    let mut metrics = ResourceMetrics::default();
+    reader.collect(&mut metrics).unwrap();
+
+    let options = ConvertOptions::default()
+        .with_scope_labels(true)
+        .with_timestamps(Timestamps::Omit);
+    let mut output = String::new();
+    metrics
+        .write_as_openmetrics_with(&mut output, &options)
+        .unwrap();
+    assert!(output.contains("requests_total{kk=\"v1\",otel_scope_name=\"meter\"} 125\n"));
+    // The value of `_created` is still written, as it is not a timestamp of the sample
+    assert!(sample_value(&output, "requests_created", "kk=\"v1\"").is_some());
+    openmetrics_parser::openmetrics::parse_openmetrics(&output).unwrap();
+}
+
+#[test]
+fn test_runtime_switches() {
+    let reader = TestMetricsReader::default();
+    let meter_provider = SdkMeterProvider::builder()
+        .with_reader(reader.clone())
+        .build();
+    meter_provider
+        .meter("meter")
+        .f64_histogram("latency")
+        .with_boundaries(vec![1.0])
+        .build()
+        .record(0.5, &[]);
+    let mut metrics = ResourceMetrics::default();
+    reader.collect(&mut metrics).unwrap();
+
+    let options = ConvertOptions::default()
+        .with_scope_info(false)
+        .with_scope_labels(false)
+        .with_histogram_min_max(false);
+    let mut output = String::new();
+    metrics
+        .write_as_openmetrics_with(&mut output, &options)
+        .unwrap();
+    assert!(!output.contains("target_info"));
+    assert!(!output.contains("otel_scope"));
+    assert!(!output.contains("latency_min"));
+
+    let options = options
+        .with_scope_info(true)
+        .with_scope_labels(true)
+        .with_histogram_min_max(true);
+    let mut output = String::new();
+    metrics
+        .write_as_openmetrics_with(&mut output, &options)
+        .unwrap();
+    assert!(output.contains("# TYPE target info\n"));
+    assert!(output.contains("# TYPE otel_scope info\n"));
+    assert_eq!(
+        sample_value(&output, "latency_count", "otel_scope_name=\"meter\""),
+        Some("1")
+    );
+    assert_eq!(
+        sample_value(&output, "latency_min", "otel_scope_name=\"meter\""),
+        Some("0.5")
+    );
+}
+
+#[test]
+fn test_histogram_min_max() {
+    let reader = TestMetricsReader::default();
+    let meter_provider = SdkMeterProvider::builder()
+        .with_reader(reader.clone())
+        .build();
+    let histogram = meter_provider
+        .meter("meter")
+        .f64_histogram("http.server.duration")
+        .with_unit("s")
+        .with_description("Duration")
+        .with_boundaries(vec![1.0])
+        .build();
+    histogram.record(0.5, &[KeyValue::new("kk", "v1")]);
+    histogram.record(2.5, &[KeyValue::new("kk", "v1")]);
+    histogram.record(1.5, &[KeyValue::new("kk", "v2")]);
+    let mut metrics = ResourceMetrics::default();
+    reader.collect(&mut metrics).unwrap();
+
+    let options = ConvertOptions::default().with_histogram_min_max(true);
+    let mut output = String::new();
+    metrics
+        .write_as_openmetrics_with(&mut output, &options)
+        .unwrap();
+    assert!(output.contains(
+        "# TYPE http_server_duration_min_seconds gauge\n\
+         # UNIT http_server_duration_min_seconds seconds\n\
+         # HELP http_server_duration_min_seconds Duration\n"
+    ));
+    assert!(output.contains("# TYPE http_server_duration_max_seconds gauge\n"));
+    assert_eq!(
+        sample_value(&output, "http_server_duration_min_seconds", "kk=\"v1\""),
+        Some("0.5")
+    );
+    assert_eq!(
+        sample_value(&output, "http_server_duration_max_seconds", "kk=\"v1\""),
+        Some("2.5")
+    );
+    assert_eq!(
+        sample_value(&output, "http_server_duration_max_seconds", "kk=\"v2\""),
+        Some("1.5")
+    );
+    // The histogram itself has no `_min` or `_max` samples
+    assert!(!output.contains("http_server_duration_seconds_min"));
+
+    // The parser rejects histograms with a unit, so check the families without unit suffixes
+    let mut output = String::new();
+    metrics
+        .write_as_openmetrics_with(&mut output, &options.with_unit_suffixes(false))
+        .unwrap();
+    assert!(output.contains("# TYPE http_server_duration_min gauge\n"));
+    openmetrics_parser::openmetrics::parse_openmetrics(&output).unwrap();
+}
+
+#[test]
+fn test_histogram_min_max_collisions() {
+    let reader = TestMetricsReader::default();
+    let meter_provider = SdkMeterProvider::builder()
+        .with_reader(reader.clone())
+        .build();
+    let meter = meter_provider.meter("meter.a");
+    meter.f64_gauge("queue_max").build().record(3.0, &[]);
+    let meter = meter_provider.meter("meter.b");
+    meter.f64_histogram("latency").build().record(1.0, &[]);
+    meter.f64_histogram("queue").build().record(1.0, &[]);
+    let meter = meter_provider.meter("meter.c");
+    meter.f64_gauge("latency_min").build().record(2.0, &[]);
+
+    let mut metrics = ResourceMetrics::default();
+    reader.collect(&mut metrics).unwrap();
+    let options = ConvertOptions::default()
+        .with_scope_info(true)
+        .with_histogram_min_max(true);
+    let mut output = String::new();
+    let diagnostics = metrics
+        .write_as_openmetrics_with(&mut output, &options)
+        .unwrap();
+    let collision = |scope: &str, metric: &str, family: &str| Error::NameCollision {
+        scope: scope.to_owned(),
+        metric: metric.to_owned(),
+        family: family.to_owned(),
+    };
+    assert_eq!(
+        diagnostics,
+        [
+            collision("meter.b", "queue", "queue_max"),
+            collision("meter.c", "latency_min", "latency_min"),
+        ]
+    );
+    let types: Vec<_> = output
+        .lines()
+        .filter(|line| line.starts_with("# TYPE"))
+        .collect();
+    assert_eq!(
+        types,
+        [
+            "# TYPE target info",
+            "# TYPE otel_scope info",
+            "# TYPE latency histogram",
+            "# TYPE latency_max gauge",
+            "# TYPE latency_min gauge",
+            "# TYPE queue_max gauge",
+        ]
+    );
+    assert_eq!(sample_value(&output, "latency_min", ""), Some("1"));
+    assert_eq!(sample_value(&output, "queue_max", ""), Some("3"));
+    openmetrics_parser::openmetrics::parse_openmetrics(&output).unwrap();
+}
+
+#[test]
+fn test_special_values() {
+    let reader = TestMetricsReader::default();
+    let meter_provider = SdkMeterProvider::builder()
+        .with_reader(reader.clone())
+        .build();
+    let meter = meter_provider.meter("meter");
+    let gauge = meter.f64_gauge("temperature").build();
+    gauge.record(f64::INFINITY, &[KeyValue::new("kk", "v1")]);
+    gauge.record(f64::NEG_INFINITY, &[KeyValue::new("kk", "v2")]);
+    gauge.record(f64::NAN, &[KeyValue::new("kk", "v3")]);
+    meter
+        .f64_histogram("latency")
+        .with_boundaries(vec![0.5, 1.0, 2.5])
+        .build()
+        .record(f64::INFINITY, &[]);
+    let mut metrics = ResourceMetrics::default();
+    reader.collect(&mut metrics).unwrap();
+
+    let mut output = String::new();
+    metrics.write_as_openmetrics(&mut output).unwrap();
+    assert_eq!(
+        sample_value(&output, "temperature", "kk=\"v1\""),
+        Some("+Inf")
+    );
+    assert_eq!(
+        sample_value(&output, "temperature", "kk=\"v2\""),
+        Some("-Inf")
+    );
+    assert_eq!(
+        sample_value(&output, "temperature", "kk=\"v3\""),
+        Some("NaN")
+    );
+    assert_eq!(sample_value(&output, "latency_sum", ""), Some("+Inf"));
+    // Bounds are written in their canonical form
+    assert_eq!(
+        sample_value(&output, "latency_bucket", "le=\"0.5\""),
+        Some("0")
+    );
+    assert_eq!(
+        sample_value(&output, "latency_bucket", "le=\"1.0\""),
+        Some("0")
+    );
+    assert_eq!(
+        sample_value(&output, "latency_bucket", "le=\"+Inf\""),
+        Some("1")
+    );
+    openmetrics_parser::openmetrics::parse_openmetrics(&output).unwrap();
+}
+
+#[test]
+fn test_promoted_resource_attributes() {
+    let reader = TestMetricsReader::default();
+    let resource = opentelemetry_sdk::Resource::builder_empty()
+        .with_attributes([
+            KeyValue::new("service.name", "checkout"),
+            KeyValue::new("k8s.pod.name", "checkout-1"),
+            KeyValue::new("k8s.node.name", "node-1"),
+            KeyValue::new("host.name", "host-1"),
+        ])
+        .build();
+    let meter_provider = SdkMeterProvider::builder()
+        .with_resource(resource)
+        .with_reader(reader.clone())
+        .build();
+    let counter = meter_provider
+        .meter("meter")
+        .u64_counter("requests")
+        .build();
+    counter.add(1, &[KeyValue::new("kk", "v1")]);
+    // The attribute of the data point wins over the resource attribute with the same label name
+    counter.add(
+        2,
+        &[
+            KeyValue::new("kk", "v2"),
+            KeyValue::new("service_name", "override"),
+        ],
+    );
+    let mut metrics = ResourceMetrics::default();
+    reader.collect(&mut metrics).unwrap();
+
+    let options = ConvertOptions::default()
+        .with_scope_info(true)
+        .with_promoted_resource_attributes(["service.name", "k8s.*"]);
+    let mut output = String::new();
+    metrics
+        .write_as_openmetrics_with(&mut output, &options)
+        .unwrap();
+    let line = output
+        .lines()
+        .find(|line| line.starts_with("requests_total{") && line.contains("kk=\"v1\""))
+        .unwrap();
+    assert!(line.contains("k8s_node_name=\"node-1\""));
+    assert!(line.contains("k8s_pod_name=\"checkout-1\""));
+    assert!(line.contains("service_name=\"checkout\""));
+    assert!(!line.contains("host_name"));
+    let line = output
+        .lines()
+        .find(|line| line.starts_with("requests_total{") && line.contains("kk=\"v2\""))
+        .unwrap();
+    assert!(line.contains("service_name=\"override\""));
+    assert!(!line.contains("checkout\""));
+    // All resource attributes stay in `target_info`
+    assert!(output.contains("host_name=\"host-1\""));
+    openmetrics_parser::openmetrics::parse_openmetrics(&output).unwrap();
 }
 
-#[cfg(feature = "otel_scope_info")]
 #[test]
 fn test_write_otel_scope_info() {
     let resource_metrics = make_test_metrics();
-    let scopes: Vec<&ScopeMetrics> = resource_metrics.scope_metrics().collect();
+    let scopes = resource_metrics.scope_metrics().map(|s| s.scope());
 
     let mut output = String::new();
-    write_otel_scope_info(&mut output, &scopes).unwrap();
+    write_otel_scope_info(&mut output, scopes, &ConvertOptions::default()).unwrap();
 
     assert!(output.contains("# TYPE otel_scope info"));
     assert!(output.contains("otel_scope_info{"));
@@ -228,6 +683,12 @@ fn test_write_counter() {
         .unwrap()
         .as_secs_f64()
         .to_string();
+    let start_ts = metric
+        .start_time()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs_f64()
+        .to_string();
 
     let mut output = String::new();
 
@@ -240,6 +701,7 @@ fn test_write_counter() {
     write_counter(&mut ctx, &metric).unwrap();
 
     let output = output.replace(&ts, "<TIMESTAMP>");
+    let output = output.replace(&start_ts, "<START_TIMESTAMP>");
     assert_snapshot!(output);
 }
 
@@ -266,12 +728,12 @@ fn test_write_histogram() {
         .to_string();
 
     let mut output = String::new();
-
+    let options = ConvertOptions::default().with_histogram_min_max(false);
     let mut ctx = Context {
         attr_buffer: String::from("staledata"),
         name: "myhistogram".to_owned(),
         scope_name: "myscope",
-        ..Context::with_output(&mut output)
+        ..Context::with_options(&mut output, &options)
     };
     write_histogram(&mut ctx, &metric).unwrap();
     let output = output.replace(&ts, "<TIMESTAMP>");
@@ -279,3 +741,966 @@ fn test_write_histogram() {
 
     assert_snapshot!(output);
 }
+
+/// Returns the value of the sample of metric `name` which has the `label`.
+fn sample_value<'a>(output: &'a str, name: &str, label: &str) -> Option<&'a str> {
+    output
+        .lines()
+        .filter(|line| line.split_once('{').is_some_and(|(n, _)| n == name))
+        .find(|line| line.contains(label))
+        .and_then(|line| line.split(' ').nth(1))
+}
+
+#[test]
+fn test_merge_families_across_scopes() {
+    let reader = TestMetricsReader::default();
+    let meter_provider = SdkMeterProvider::builder()
+        .with_reader(reader.clone())
+        .build();
+    let attrs = [KeyValue::new("kk", "v1")];
+    meter_provider
+        .meter("meter.two")
+        .u64_counter("requests")
+        .with_description("Requests of meter two")
+        .build()
+        .add(2, &attrs);
+    meter_provider
+        .meter("meter.one")
+        .u64_counter("requests")
+        .build()
+        .add(1, &attrs);
+    let scope = InstrumentationScope::builder("meter.one")
+        .with_version("2.0")
+        .build();
+    meter_provider
+        .meter_with_scope(scope)
+        .u64_counter("requests")
+        .build()
+        .add(3, &attrs);
+    // Conflicts with the type of the family and is dropped
+    meter_provider
+        .meter("meter.three")
+        .f64_gauge("requests")
+        .build()
+        .record(42.0, &attrs);
+
+    let mut metrics = ResourceMetrics::default();
+    reader.collect(&mut metrics).unwrap();
+    let output = metrics.to_openmetrics_string().unwrap();
+
+    assert_eq!(output.matches("# TYPE requests").count(), 1);
+    assert!(output.contains("# HELP requests Requests of meter two\n"));
+    assert_eq!(
+        sample_value(
+            &output,
+            "requests_total",
+            "otel_scope_name=\"meter.one\",otel_scope_version=\"\"}"
+        ),
+        Some("1")
+    );
+    assert_eq!(
+        sample_value(&output, "requests_total", "otel_scope_version=\"2.0\""),
+        Some("3")
+    );
+    assert_eq!(
+        sample_value(&output, "requests_total", "otel_scope_name=\"meter.two\""),
+        Some("2")
+    );
+    assert!(!output.contains(" 42 "));
+    openmetrics_parser::openmetrics::parse_openmetrics(&output).unwrap();
+}
+
+#[test]
+fn test_sample_name_collisions() {
+    let reader = TestMetricsReader::default();
+    let meter_provider = SdkMeterProvider::builder()
+        .with_reader(reader.clone())
+        .build();
+    let meter = meter_provider.meter("meter.a");
+    meter.u64_gauge("latency_bucket").build().record(1, &[]);
+    let meter = meter_provider.meter("meter.b");
+    meter.u64_counter("requests").build().add(1, &[]);
+    meter.f64_histogram("latency").build().record(1.0, &[]);
+    meter.f64_histogram("duration").build().record(1.0, &[]);
+    let meter = meter_provider.meter("meter.c");
+    meter.u64_gauge("requests_total").build().record(1, &[]);
+    meter.u64_gauge("duration_created").build().record(1, &[]);
+
+    let mut metrics = ResourceMetrics::default();
+    reader.collect(&mut metrics).unwrap();
+    let options = ConvertOptions::default().with_histogram_min_max(false);
+    let mut output = String::new();
+    let mut diagnostics = metrics
+        .write_as_openmetrics_with(&mut output, &options)
+        .unwrap();
+    diagnostics.sort_by(|a, b| a.metric().cmp(&b.metric()));
+    let collision = |scope: &str, metric: &str| Error::NameCollision {
+        scope: scope.to_owned(),
+        metric: metric.to_owned(),
+        family: metric.to_owned(),
+    };
+    assert_eq!(
+        diagnostics,
+        [
+            collision("meter.c", "duration_created"),
+            collision("meter.b", "latency"),
+            collision("meter.c", "requests_total"),
+        ]
+    );
+    assert!(output.contains("# TYPE latency_bucket gauge\n"));
+    assert!(!output.contains("# TYPE latency histogram\n"));
+    assert!(output.contains("# TYPE requests counter\n"));
+    assert!(!output.contains("# TYPE requests_total"));
+    openmetrics_parser::openmetrics::parse_openmetrics(&output).unwrap();
+
+    // Without the `_total` suffix in Prometheus, the counter and gauge don't collide
+    let options = options
+        .with_text_format(TextFormat::Prometheus)
+        .with_type_suffixes(false);
+    let diagnostics = metrics
+        .write_as_openmetrics_with(&mut String::new(), &options)
+        .unwrap();
+    assert_eq!(diagnostics, [collision("meter.b", "latency")]);
+}
+
+#[test]
+fn test_name_escaping() {
+    let reader = TestMetricsReader::default();
+    let meter_provider = SdkMeterProvider::builder()
+        .with_reader(reader.clone())
+        .build();
+    let meter = meter_provider.meter("meter");
+    meter
+        .f64_histogram("http.server.duration")
+        .with_description("Duration")
+        .build()
+        .record(1.5, &[]);
+    meter
+        .u64_counter("requests")
+        .build()
+        .add(1, &[KeyValue::new("http.method", "GET")]);
+    meter
+        .u64_gauge("system.memory")
+        .with_unit("By")
+        .build()
+        .record(1, &[]);
+    meter
+        .u64_gauge("network.io")
+        .with_unit("By/s")
+        .build()
+        .record(1, &[]);
+
+    let mut metrics = ResourceMetrics::default();
+    reader.collect(&mut metrics).unwrap();
+    let write = |escaping| {
+        let options = ConvertOptions::default()
+            .with_scope_labels(true)
+            .with_histogram_min_max(false)
+            .with_name_escaping(escaping);
+        let mut output = String::new();
+        metrics
+            .write_as_openmetrics_with(&mut output, &options)
+            .unwrap();
+        output
+    };
+
+    let output = write(NameEscaping::AllowUtf8);
+    assert!(output.contains("# TYPE \"http.server.duration\" histogram\n"));
+    assert!(output.contains("# UNIT \"system.memory_bytes\" bytes\n"));
+    assert!(output.contains("# HELP \"http.server.duration\" Duration\n"));
+    assert!(output.contains("{\"http.server.duration_count\",otel_scope_name=\"meter\"}"));
+    assert!(
+        output.contains("{\"http.server.duration_bucket\",otel_scope_name=\"meter\",le=\"0.0\"}")
+    );
+    // Legacy names stay unquoted
+    assert!(output.contains("# TYPE requests counter\n"));
+    assert!(output.contains("\nrequests_total{\"http.method\"=\"GET\","));
+
+    let output = write(NameEscaping::Dots);
+    assert!(output.contains("# TYPE http_dot_server_dot_duration histogram\n"));
+    assert!(output.contains("# TYPE system_dot_memory__bytes gauge\n"));
+    assert!(output.contains("# UNIT system_dot_memory__bytes bytes\n"));
+    // A unit which the escaped name no longer ends with is not written
+    assert!(output.contains("# TYPE network_dot_io__bytes__per__second gauge\n"));
+    assert!(!output.contains("# UNIT network_dot_io"));
+    assert!(output.contains("\nrequests_total{http_dot_method=\"GET\","));
+    openmetrics_parser::openmetrics::parse_openmetrics(&output).unwrap();
+
+    let output = write(NameEscaping::Values);
+    assert!(output.contains("# TYPE U__http_2e_server_2e_duration histogram\n"));
+    assert!(output.contains("# TYPE U__system_2e_memory__bytes gauge\n"));
+    assert!(output.contains("# UNIT U__system_2e_memory__bytes bytes\n"));
+    assert!(!output.contains("# UNIT U__network_2e_io"));
+    assert!(output.contains("\nrequests_total{U__http_2e_method=\"GET\","));
+    openmetrics_parser::openmetrics::parse_openmetrics(&output).unwrap();
+
+    let output = write(NameEscaping::Underscores);
+    assert!(output.contains("# TYPE http_server_duration histogram\n"));
+    assert!(output.contains("\nrequests_total{http_method=\"GET\","));
+}
+
+#[test]
+fn test_metric_name_suffixes() {
+    let reader = TestMetricsReader::default();
+    let meter_provider = SdkMeterProvider::builder()
+        .with_reader(reader.clone())
+        .build();
+    let meter = meter_provider.meter("meter");
+    meter.u64_counter("requests").build().add(1, &[]);
+    meter.u64_counter("errors_total").build().add(2, &[]);
+    meter
+        .f64_gauge("request_duration_seconds")
+        .with_unit("s")
+        .build()
+        .record(1.5, &[]);
+    meter
+        .u64_gauge("memory")
+        .with_unit("By")
+        .build()
+        .record(3, &[]);
+
+    let mut metrics = ResourceMetrics::default();
+    reader.collect(&mut metrics).unwrap();
+    let write = |options: ConvertOptions| {
+        let mut output = String::new();
+        metrics
+            .write_as_openmetrics_with(&mut output, &options)
+            .unwrap();
+        output
+    };
+
+    let output = write(ConvertOptions::default());
+    assert!(output.contains("# TYPE errors counter\n"));
+    assert!(output.contains("\nerrors_total{"));
+    assert!(output.contains("# TYPE request_duration_seconds gauge\n"));
+    assert!(output.contains("# UNIT request_duration_seconds seconds\n"));
+    assert!(output.contains("# UNIT memory_bytes bytes\n"));
+    assert!(!output.contains("_total_total"));
+    assert!(!output.contains("seconds_seconds"));
+    openmetrics_parser::openmetrics::parse_openmetrics(&output).unwrap();
+
+    // The unit is only written if it is part of the name
+    let output = write(ConvertOptions::default().with_unit_suffixes(false));
+    assert!(output.contains("# TYPE memory gauge\n"));
+    assert!(!output.contains("# UNIT memory"));
+    assert!(output.contains("# UNIT request_duration_seconds seconds\n"));
+    openmetrics_parser::openmetrics::parse_openmetrics(&output).unwrap();
+
+    // OpenMetrics requires the `_total` suffix of counters
+    let output = write(ConvertOptions::default().with_type_suffixes(false));
+    assert!(output.contains("\nrequests_total{"));
+    let output = write(
+        ConvertOptions::default()
+            .with_text_format(TextFormat::Prometheus)
+            .with_type_suffixes(false),
+    );
+    assert!(output.contains("# TYPE requests counter\n"));
+    assert!(output.contains("\nrequests{"));
+    assert!(output.contains("# TYPE errors_total counter\n"));
+    assert!(output.contains("\nerrors_total{"));
+}
+
+#[test]
+fn test_unit_mapping() {
+    let reader = TestMetricsReader::default();
+    let meter_provider = SdkMeterProvider::builder()
+        .with_reader(reader.clone())
+        .build();
+    let meter = meter_provider.meter("meter");
+    meter
+        .u64_counter("http.server.requests")
+        .with_unit("{request}")
+        .build()
+        .add(1, &[]);
+    meter
+        .f64_gauge("cpu.utilization")
+        .with_unit("1")
+        .build()
+        .record(0.5, &[]);
+
+    let mut metrics = ResourceMetrics::default();
+    reader.collect(&mut metrics).unwrap();
+    let mapping = UnitMapping::new()
+        .with_unit("{request}", Some("requests"))
+        .with_metric("cpu.utilization", None);
+    let options = ConvertOptions::default().with_unit_mapping(mapping);
+    let mut output = String::new();
+    metrics
+        .write_as_openmetrics_with(&mut output, &options)
+        .unwrap();
+
+    assert!(output.contains("# TYPE http_server_requests counter\n"));
+    assert!(output.contains("# UNIT http_server_requests requests\n"));
+    assert!(output.contains("# TYPE cpu_utilization gauge\n"));
+    assert!(!output.contains("# UNIT cpu_utilization"));
+    openmetrics_parser::openmetrics::parse_openmetrics(&output).unwrap();
+}
+
+#[test]
+fn test_has_name_suffix() {
+    assert!(has_name_suffix("requests_total", "total"));
+    assert!(has_name_suffix("duration_seconds", "seconds"));
+    assert!(!has_name_suffix("duration_milliseconds", "seconds"));
+    assert!(!has_name_suffix("durationseconds", "seconds"));
+    assert!(!has_name_suffix("_seconds", "seconds"));
+    assert!(!has_name_suffix("seconds", "seconds"));
+}
+
+#[test]
+fn test_delta_metrics_are_skipped_without_accumulator() {
+    let reader = TestMetricsReader::with_temporality(Temporality::Delta);
+    let meter_provider = SdkMeterProvider::builder()
+        .with_reader(reader.clone())
+        .build();
+    let meter = meter_provider.meter("meter");
+    meter.u64_counter("requests").build().add(3, &[]);
+    meter.f64_histogram("latency").build().record(1.5, &[]);
+
+    let mut metrics = ResourceMetrics::default();
+    reader.collect(&mut metrics).unwrap();
+
+    let output = metrics.to_openmetrics_string().unwrap();
+    assert!(!output.contains("requests"));
+    assert!(!output.contains("latency"));
+}
+
+#[test]
+fn test_invalid_values() {
+    let reader = TestMetricsReader::default();
+    let meter_provider = SdkMeterProvider::builder()
+        .with_reader(reader.clone())
+        .build();
+    let meter = meter_provider.meter("meter");
+    meter.f64_counter("requests").build().add(-1.0, &[]);
+    meter.f64_gauge("temperature").build().record(21.5, &[]);
+
+    let mut metrics = ResourceMetrics::default();
+    reader.collect(&mut metrics).unwrap();
+
+    let invalid = Error::InvalidValue {
+        scope: "meter".to_owned(),
+        metric: "requests".to_owned(),
+        reason: "counter total is negative or NaN",
+    };
+    // A strict conversion fails
+    let options = ConvertOptions::default().with_lenient(false);
+    let result = metrics.write_as_openmetrics_with(&mut String::new(), &options);
+    assert_eq!(result, Err(invalid.clone()));
+
+    let mut output = String::new();
+    let diagnostics = metrics
+        .write_as_openmetrics_with(&mut output, &ConvertOptions::default())
+        .unwrap();
+    assert_eq!(diagnostics, [invalid]);
+    assert!(!output.contains("requests"));
+    assert_eq!(sample_value(&output, "temperature", ""), Some("21.5"));
+}
+
+#[test]
+fn test_accumulate_delta_counter() {
+    let reader = TestMetricsReader::with_temporality(Temporality::Delta);
+    let meter_provider = SdkMeterProvider::builder()
+        .with_reader(reader.clone())
+        .build();
+    let counter = meter_provider
+        .meter("meter")
+        .u64_counter("requests")
+        .build();
+    let mut accumulator = DeltaAccumulator::default();
+    let mut metrics = ResourceMetrics::default();
+
+    counter.add(3, &[KeyValue::new("kk", "v1")]);
+    reader.collect(&mut metrics).unwrap();
+    accumulator.accumulate(&metrics);
+
+    counter.add(4, &[KeyValue::new("kk", "v1")]);
+    counter.add(1, &[KeyValue::new("kk", "v2")]);
+    reader.collect(&mut metrics).unwrap();
+    let output = accumulator
+        .accumulate(&metrics)
+        .to_openmetrics_string()
+        .unwrap();
+    assert!(output.contains("# TYPE requests counter\n"));
+    assert_eq!(
+        sample_value(&output, "requests_total", "kk=\"v1\""),
+        Some("7")
+    );
+    assert_eq!(
+        sample_value(&output, "requests_total", "kk=\"v2\""),
+        Some("1")
+    );
+    // Each series is created at the start of the interval it first appeared in
+    let created = |label| {
+        sample_value(&output, "requests_created", label)
+            .unwrap()
+            .parse::<f64>()
+            .unwrap()
+    };
+    assert!(created("kk=\"v1\"") < created("kk=\"v2\""));
+
+    // Accumulating the same export again does not count it twice
+    let output = accumulator
+        .accumulate(&metrics)
+        .to_openmetrics_string()
+        .unwrap();
+    assert_eq!(
+        sample_value(&output, "requests_total", "kk=\"v1\""),
+        Some("7")
+    );
+
+    // Series without updates in the last interval keep their value
+    counter.add(2, &[KeyValue::new("kk", "v2")]);
+    reader.collect(&mut metrics).unwrap();
+    let output = accumulator
+        .accumulate(&metrics)
+        .to_openmetrics_string()
+        .unwrap();
+    assert_eq!(
+        sample_value(&output, "requests_total", "kk=\"v1\""),
+        Some("7")
+    );
+    assert_eq!(
+        sample_value(&output, "requests_total", "kk=\"v2\""),
+        Some("3")
+    );
+
+    // Metrics without any updates are still written
+    reader.collect(&mut metrics).unwrap();
+    let output = accumulator
+        .accumulate(&metrics)
+        .to_openmetrics_string()
+        .unwrap();
+    assert_eq!(
+        sample_value(&output, "requests_total", "kk=\"v1\""),
+        Some("7")
+    );
+}
+
+#[test]
+fn test_accumulate_delta_histogram() {
+    let reader = TestMetricsReader::with_temporality(Temporality::Delta);
+    let meter_provider = SdkMeterProvider::builder()
+        .with_reader(reader.clone())
+        .build();
+    let histogram = meter_provider
+        .meter("meter")
+        .f64_histogram("latency")
+        .with_boundaries(vec![1.0, 10.0])
+        .build();
+    let mut accumulator = DeltaAccumulator::default();
+    let mut metrics = ResourceMetrics::default();
+
+    histogram.record(0.5, &[]);
+    histogram.record(5.0, &[]);
+    reader.collect(&mut metrics).unwrap();
+    accumulator.accumulate(&metrics);
+
+    histogram.record(20.0, &[]);
+    reader.collect(&mut metrics).unwrap();
+    let output = accumulator
+        .accumulate(&metrics)
+        .to_openmetrics_string()
+        .unwrap();
+    assert!(output.contains("# TYPE latency histogram\n"));
+    assert_eq!(sample_value(&output, "latency_count", ""), Some("3"));
+    assert_eq!(sample_value(&output, "latency_sum", ""), Some("25.5"));
+    assert_eq!(
+        sample_value(&output, "latency_bucket", "le=\"1.0\"}"),
+        Some("1")
+    );
+    assert_eq!(
+        sample_value(&output, "latency_bucket", "le=\"10.0\"}"),
+        Some("2")
+    );
+    assert_eq!(
+        sample_value(&output, "latency_bucket", "le=\"+Inf\"}"),
+        Some("3")
+    );
+}
+
+#[test]
+fn test_accumulate_overflow() {
+    let reader = TestMetricsReader::with_temporality(Temporality::Delta);
+    let meter_provider = SdkMeterProvider::builder()
+        .with_reader(reader.clone())
+        .build();
+    let meter = meter_provider.meter("meter");
+    let requests = meter.u64_counter("requests").build();
+    let size = meter.u64_histogram("size").build();
+    let mut accumulator = DeltaAccumulator::default();
+    let mut metrics = ResourceMetrics::default();
+
+    requests.add(u64::MAX, &[]);
+    size.record(u64::MAX, &[]);
+    reader.collect(&mut metrics).unwrap();
+    accumulator.accumulate(&metrics);
+
+    requests.add(2, &[]);
+    size.record(4, &[]);
+    reader.collect(&mut metrics).unwrap();
+    let accumulated = accumulator.accumulate(&metrics);
+    let overflow = |metric: &str| Error::Overflow {
+        scope: "meter".to_owned(),
+        metric: metric.to_owned(),
+    };
+    // A strict conversion fails
+    let options = ConvertOptions::default().with_lenient(false);
+    let result = accumulated.write_as_openmetrics_with(&mut String::new(), &options);
+    assert!(matches!(result, Err(Error::Overflow { .. })));
+
+    // The overflowing series start over with the latest delta
+    let mut output = String::new();
+    let mut diagnostics = accumulated
+        .write_as_openmetrics_with(&mut output, &ConvertOptions::default())
+        .unwrap();
+    diagnostics.sort_by(|a, b| a.metric().cmp(&b.metric()));
+    assert_eq!(diagnostics, [overflow("requests"), overflow("size")]);
+    assert_eq!(sample_value(&output, "requests_total", ""), Some("2"));
+    assert_eq!(sample_value(&output, "size_count", ""), Some("1"));
+    assert_eq!(sample_value(&output, "size_sum", ""), Some("4"));
+    openmetrics_parser::openmetrics::parse_openmetrics(&output).unwrap();
+
+    // Later deltas add up again
+    requests.add(5, &[]);
+    reader.collect(&mut metrics).unwrap();
+    let mut output = String::new();
+    let diagnostics = accumulator
+        .accumulate(&metrics)
+        .write_as_openmetrics_with(&mut output, &ConvertOptions::default())
+        .unwrap();
+    assert_eq!(diagnostics, []);
+    assert_eq!(sample_value(&output, "requests_total", ""), Some("7"));
+}
+
+/// Writes the counter `requests` and the histogram `latency` like a whole exposition.
+fn write_counter_and_histogram(
+    sum: &impl SumLike<f64>,
+    histogram: &impl HistogramLike<f64>,
+    options: &ConvertOptions,
+) -> String {
+    let mut output = String::new();
+    let mut ctx = Context::with_options(&mut output, options);
+    ctx.name.push_str("requests");
+    ctx.typ = "counter";
+    write_header(&mut ctx, "").unwrap();
+    write_counter(&mut ctx, sum).unwrap();
+    ctx.name.clear();
+    ctx.name.push_str("latency");
+    ctx.typ = "histogram";
+    write_header(&mut ctx, "").unwrap();
+    write_histogram(&mut ctx, histogram).unwrap();
+    if options.text_format.is_openmetrics() {
+        output.push_str("# EOF\n");
+    }
+    output
+}
+
+/// A sum with a single point of `value` with the `exemplars`, over the interval between `start` and `end`.
+fn test_sum(start: u64, end: u64, value: f64, exemplars: Vec<TestExemplar>) -> TestSum {
+    TestSum {
+        start_time: at(start),
+        time: at(end),
+        points: vec![TestPoint {
+            attributes: vec![KeyValue::new("method", "GET")],
+            value,
+            exemplars,
+        }],
+    }
+}
+
+/// A histogram with a single point with buckets up to 1 and 10, over the interval between `start` and `end`.
+fn test_histogram(
+    start: u64,
+    end: u64,
+    bucket_counts: [u64; 3],
+    sum: f64,
+    exemplars: Vec<TestExemplar>,
+) -> TestHistogram {
+    TestHistogram {
+        start_time: at(start),
+        time: at(end),
+        points: vec![TestHistogramPoint {
+            attributes: Vec::new(),
+            sum,
+            bounds: vec![1.0, 10.0],
+            bucket_counts: bucket_counts.to_vec(),
+            exemplars,
+        }],
+    }
+}
+
+#[test]
+fn test_write_exemplars() {
+    let sum = test_sum(
+        0,
+        30,
+        3.0,
+        vec![
+            TestExemplar::in_trace(1.0, 10, 0x01),
+            TestExemplar::in_trace(2.0, 20, 0x02),
+        ],
+    );
+    let histogram = test_histogram(
+        0,
+        30,
+        [1, 1, 1],
+        25.5,
+        vec![
+            TestExemplar::in_trace(0.5, 10, 0x01),
+            TestExemplar::in_trace(20.0, 20, 0x03),
+        ],
+    );
+    let options = ConvertOptions::default();
+    let output = write_counter_and_histogram(&sum, &histogram, &options);
+
+    // The latest exemplar goes onto the `_total` sample, and each on the bucket it falls into
+    let trace = |byte: &str| {
+        format!(
+            "# {{trace_id=\"{}\",span_id=\"abababababababab\"}}",
+            byte.repeat(16)
+        )
+    };
+    assert!(output.contains(&format!(
+        "requests_total{{method=\"GET\"}} 3 30 {} 2 20\n",
+        trace("02")
+    )));
+    assert!(output.contains(&format!(
+        "latency_bucket{{le=\"1.0\"}} 1 30 {} 0.5 10\n",
+        trace("01")
+    )));
+    assert!(output.contains("latency_bucket{le=\"10.0\"} 2 30\n"));
+    assert!(output.contains(&format!(
+        "latency_bucket{{le=\"+Inf\"}} 3 30 {} 20 20\n",
+        trace("03")
+    )));
+    assert_eq!(output.matches(" # {").count(), 3);
+    openmetrics_parser::openmetrics::parse_openmetrics(&output).unwrap();
+
+    let options = ConvertOptions::default().with_exemplars(false);
+    let output = write_counter_and_histogram(&sum, &histogram, &options);
+    assert!(!output.contains(" # {"));
+
+    // Prometheus has no exemplars
+    let options = ConvertOptions::default().with_text_format(TextFormat::Prometheus);
+    let output = write_counter_and_histogram(&sum, &histogram, &options);
+    assert!(!output.contains(" # {"));
+}
+
+#[test]
+fn test_accumulate_exemplars() {
+    let mut sum = CumulativeSum::new(true);
+    let mut histogram = CumulativeHistogram::new();
+    sum.update(&test_sum(
+        0,
+        10,
+        2.0,
+        vec![TestExemplar::in_trace(2.0, 5, 0x01)],
+    ));
+    histogram.update(&test_histogram(
+        0,
+        10,
+        [0, 1, 1],
+        25.0,
+        vec![TestExemplar::in_trace(20.0, 5, 0x01)],
+    ));
+    // Deltas without exemplars keep those of the previous ones
+    sum.update(&test_sum(10, 20, 1.0, Vec::new()));
+    histogram.update(&test_histogram(10, 20, [1, 0, 0], 0.5, Vec::new()));
+
+    let options = ConvertOptions::default();
+    let output = write_counter_and_histogram(&sum, &histogram, &options);
+    assert!(output.contains(
+        "requests_total{method=\"GET\"} 3 20 # {trace_id=\"01010101010101010101010101010101\",\
+         span_id=\"abababababababab\"} 2 5\n"
+    ));
+    assert!(output.contains("latency_bucket{le=\"1.0\"} 1 20\n"));
+    assert!(output.contains(
+        "latency_bucket{le=\"+Inf\"} 3 20 # {trace_id=\"01010101010101010101010101010101\",\
+         span_id=\"abababababababab\"} 20 5\n"
+    ));
+    openmetrics_parser::openmetrics::parse_openmetrics(&output).unwrap();
+
+    // Deltas with exemplars replace them
+    sum.update(&test_sum(
+        20,
+        30,
+        1.0,
+        vec![TestExemplar::in_trace(1.0, 25, 0x02)],
+    ));
+    histogram.update(&test_histogram(
+        20,
+        30,
+        [1, 0, 0],
+        0.5,
+        vec![TestExemplar::in_trace(0.5, 25, 0x02)],
+    ));
+    let output = write_counter_and_histogram(&sum, &histogram, &options);
+    assert_eq!(output.matches(" # {trace_id=\"0202").count(), 2);
+    assert!(!output.contains("0101"));
+    assert!(output.contains("latency_bucket{le=\"1.0\"} 2 30 # {"));
+    openmetrics_parser::openmetrics::parse_openmetrics(&output).unwrap();
+}
+
+#[test]
+fn test_start_timestamps() {
+    let reader = TestMetricsReader::default();
+    let meter_provider = SdkMeterProvider::builder()
+        .with_reader(reader.clone())
+        .build();
+    let meter = meter_provider.meter("meter");
+    meter
+        .u64_counter("requests")
+        .build()
+        .add(1, &[KeyValue::new("kk", "v1")]);
+    let histogram = meter
+        .f64_histogram("latency")
+        .with_boundaries(vec![1.0])
+        .build();
+    histogram.record(0.5, &[KeyValue::new("kk", "v1")]);
+    histogram.record(0.5, &[KeyValue::new("kk", "v2")]);
+
+    let mut metrics = ResourceMetrics::default();
+    reader.collect(&mut metrics).unwrap();
+
+    // Each histogram series has its own `_created` sample with its labels
+    let options = ConvertOptions::default().with_histogram_min_max(false);
+    let mut output = String::new();
+    metrics
+        .write_as_openmetrics_with(&mut output, &options)
+        .unwrap();
+    assert!(sample_value(&output, "latency_created", "kk=\"v1\"").is_some());
+    assert!(sample_value(&output, "latency_created", "kk=\"v2\"").is_some());
+    assert!(sample_value(&output, "requests_created", "kk=\"v1\"").is_some());
+    openmetrics_parser::openmetrics::parse_openmetrics(&output).unwrap();
+
+    // OpenMetrics 1.0 keeps the `_created` samples
+    let options = options.with_start_timestamps(true);
+    let mut output = String::new();
+    metrics
+        .write_as_openmetrics_with(&mut output, &options)
+        .unwrap();
+    assert!(!output.contains("st@"));
+    assert!(sample_value(&output, "requests_created", "kk=\"v1\"").is_some());
+    openmetrics_parser::openmetrics::parse_openmetrics(&output).unwrap();
+
+    let options = options.with_text_format(TextFormat::OpenMetrics2);
+    let mut output = String::new();
+    metrics
+        .write_as_openmetrics_with(&mut output, &options)
+        .unwrap();
+    assert!(!output.contains("_created"));
+    for line in output
+        .lines()
+        .filter(|line| line.starts_with("requests_total") || line.starts_with("latency_"))
+    {
+        let (_, start) = line.split_once(" st@").unwrap();
+        start.parse::<f64>().unwrap();
+    }
+
+    // The Prometheus text format has neither
+    let options = options.with_text_format(TextFormat::Prometheus);
+    let mut output = String::new();
+    metrics
+        .write_as_openmetrics_with(&mut output, &options)
+        .unwrap();
+    assert!(!output.contains("_created"));
+    assert!(!output.contains("st@"));
+}
+
+#[test]
+fn test_write_exponential_histogram() {
+    let metric = make_f64_exponential_histogram_metric(
+        vec![
+            (-3.0, vec![]),
+            (0.0, vec![]),
+            (1.5, vec![]),
+            (3.0, vec![]),
+            (5.0, vec![]),
+            (1000.0, vec![]),
+        ],
+        20,
+    );
+    let ts = metric
+        .time()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs_f64()
+        .to_string();
+    let start_ts = metric
+        .start_time()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs_f64()
+        .to_string();
+
+    let mut output = String::new();
+    let options = ConvertOptions::default()
+        .with_exponential_buckets(ExponentialBuckets::Scale { max_buckets: 8 })
+        .with_histogram_min_max(false);
+    let mut ctx = Context {
+        name: "myhistogram".to_owned(),
+        scope_name: "myscope",
+        ..Context::with_options(&mut output, &options)
+    };
+    write_exponential_histogram(&mut ctx, &metric).unwrap();
+    let output = output.replace(&ts, "<TIMESTAMP>");
+    let output = output.replace(&start_ts, "<START_TIMESTAMP>");
+
+    assert_snapshot!(output);
+}
+
+#[test]
+fn test_write_exponential_histogram_with_bounds() {
+    let metric = make_f64_exponential_histogram_metric(
+        vec![
+            (-3.0, vec![]),
+            (0.0, vec![]),
+            (1.5, vec![]),
+            (3.0, vec![]),
+            (5.0, vec![]),
+            (1000.0, vec![]),
+        ],
+        20,
+    );
+
+    let mut output = String::new();
+    let options = ConvertOptions::default()
+        .with_exponential_buckets(ExponentialBuckets::Bounds(vec![10.0, 0.0, 1.0]));
+    let mut ctx = Context {
+        name: "myhistogram".to_owned(),
+        ..Context::with_options(&mut output, &options)
+    };
+    write_exponential_histogram(&mut ctx, &metric).unwrap();
+
+    assert_eq!(sample_value(&output, "myhistogram_count", ""), Some("6"));
+    assert_eq!(sample_value(&output, "myhistogram_sum", ""), Some("1006.5"));
+    assert_eq!(
+        sample_value(&output, "myhistogram_bucket", "le=\"0.0\""),
+        Some("2")
+    );
+    assert_eq!(
+        sample_value(&output, "myhistogram_bucket", "le=\"1.0\""),
+        Some("2")
+    );
+    assert_eq!(
+        sample_value(&output, "myhistogram_bucket", "le=\"10.0\""),
+        Some("5")
+    );
+    assert_eq!(
+        sample_value(&output, "myhistogram_bucket", "le=\"+Inf\""),
+        Some("6")
+    );
+}
+
+#[test]
+fn test_write_exponential_histogram_with_overflowing_bound() {
+    // At scale 20, the upper boundary of the bucket of f64::MAX is beyond the range of f64
+    let metric = make_f64_exponential_histogram_metric(vec![(f64::MAX, vec![])], 160);
+
+    let mut output = String::new();
+    let options = ConvertOptions::default().with_histogram_min_max(false);
+    let mut ctx = Context {
+        name: "myhistogram".to_owned(),
+        ..Context::with_options(&mut output, &options)
+    };
+    write_exponential_histogram(&mut ctx, &metric).unwrap();
+
+    assert_eq!(output.matches("le=\"+Inf\"").count(), 1);
+    assert_eq!(
+        sample_value(
+            &output,
+            "myhistogram_bucket",
+            "le=\"1.7976931348623157e308\""
+        ),
+        Some("1")
+    );
+}
+
+#[test]
+fn test_histogram_with_negative_bounds() {
+    let reader = TestMetricsReader::default();
+    let meter_provider = SdkMeterProvider::builder()
+        .with_reader(reader.clone())
+        .build();
+    let histogram = meter_provider
+        .meter("meter")
+        .f64_histogram("temperature")
+        .with_boundaries(vec![-10.0, 0.0, 10.0])
+        .build();
+    histogram.record(-15.0, &[]);
+    histogram.record(5.0, &[]);
+    let mut metrics = ResourceMetrics::default();
+    reader.collect(&mut metrics).unwrap();
+
+    let output = metrics.to_openmetrics_string().unwrap();
+    // OpenMetrics forbids the sum of histograms with negative buckets, but not their count
+    assert_eq!(sample_value(&output, "temperature_count", ""), Some("2"));
+    assert_eq!(sample_value(&output, "temperature_sum", ""), None);
+    assert_eq!(
+        sample_value(&output, "temperature_bucket", "le=\"-10.0\""),
+        Some("1")
+    );
+
+    let options = ConvertOptions::default().with_text_format(TextFormat::Prometheus);
+    let mut output = String::new();
+    metrics
+        .write_as_openmetrics_with(&mut output, &options)
+        .unwrap();
+    assert_eq!(sample_value(&output, "temperature_sum", ""), Some("-10"));
+}
+
+#[test]
+fn test_write_native_histogram() {
+    let metric = make_f64_exponential_histogram_metric(
+        vec![
+            (-3.0, vec![]),
+            (0.0, vec![]),
+            (1.5, vec![]),
+            (3.0, vec![]),
+            (5.0, vec![]),
+            (1000.0, vec![]),
+        ],
+        20,
+    );
+    let ts = metric
+        .time()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs_f64()
+        .to_string();
+    let start_ts = metric
+        .start_time()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs_f64()
+        .to_string();
+
+    let mut output = String::new();
+    let options = ConvertOptions::default()
+        .with_native_histograms(true)
+        .with_text_format(TextFormat::OpenMetrics2);
+    let mut ctx = Context {
+        name: "myhistogram".to_owned(),
+        scope_name: "myscope",
+        ..Context::with_options(&mut output, &options)
+    };
+    write_exponential_histogram(&mut ctx, &metric).unwrap();
+    let output = output.replace(&ts, "<TIMESTAMP>");
+    let output = output.replace(&start_ts, "<START_TIMESTAMP>");
+
+    assert_snapshot!(output);
+
+    // OpenMetrics 1.0 has no native histograms, so their buckets are converted
+    let mut output = String::new();
+    let options = options.with_text_format(TextFormat::OpenMetrics);
+    let mut ctx = Context {
+        name: "myhistogram".to_owned(),
+        ..Context::with_options(&mut output, &options)
+    };
+    write_exponential_histogram(&mut ctx, &metric).unwrap();
+    assert!(!output.contains("schema:"));
+    assert_eq!(
+        sample_value(&output, "myhistogram_bucket", "le=\"+Inf\""),
+        Some("6")
+    );
+}
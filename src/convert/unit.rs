@@ -8,6 +8,8 @@
 
 use std::borrow::Cow;
 
+use super::write_sanitized_name;
+
 const NON_APPLICABLE_ON_PER_UNIT: [&str; 8] = ["1", "d", "h", "min", "s", "ms", "us", "ns"];
 
 pub(crate) fn get_unit_suffixes(unit: &str) -> Option<Cow<'static, str>> {
@@ -21,6 +23,15 @@ pub(crate) fn get_unit_suffixes(unit: &str) -> Option<Cow<'static, str>> {
         return Some(Cow::Borrowed(matched));
     }
 
+    // parsing compound UCUM units
+    // e.g.
+    // "kW.h" => "kilowatt_hours"
+    // "Mibit/s" => "mebibits_per_second"
+    // "{packets}/s" => "per_second"
+    if let Some(parsed) = parse_ucum(unit) {
+        return Some(Cow::Owned(parsed));
+    }
+
     // converting foo/bar to foo_per_bar
     // split the string by the first '/'
     // if the first part is empty, we just return the second part if it's a match with known per unit
@@ -100,10 +111,335 @@ fn get_prom_per_unit(unit: &str) -> Option<&'static str> {
     }
 }
 
+/// Overrides of the unit suffixes which the units of metrics are translated into, see
+/// [`ConvertOptions::with_unit_mapping`](super::ConvertOptions::with_unit_mapping).
+///
+/// An override for the metric takes precedence over one for its unit, and units without override are translated as
+/// usual. Overriding with `None` drops the unit, so that neither a suffix nor `# UNIT` metadata is written.
+///
+/// ```
+/// use ottotom::convert::{ConvertOptions, UnitMapping};
+///
+/// let mapping = UnitMapping::new()
+///     .with_unit("{request}", Some("requests"))
+///     .with_unit("K", Some("kelvin"))
+///     .with_metric("process.cpu.utilization", None);
+/// let options = ConvertOptions::default().with_unit_mapping(mapping);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct UnitMapping {
+    /// the suffixes by unit
+    units: Vec<(String, Option<String>)>,
+    /// the suffixes by metric name
+    metrics: Vec<(String, Option<String>)>,
+}
+
+impl UnitMapping {
+    /// Creates a mapping without overrides.
+    pub const fn new() -> Self {
+        Self {
+            units: Vec::new(),
+            metrics: Vec::new(),
+        }
+    }
+
+    /// Translates the UCUM `unit` of any metric into `suffix`, or drops it if `None`.
+    pub fn with_unit(mut self, unit: impl Into<String>, suffix: Option<&str>) -> Self {
+        insert_override(&mut self.units, unit.into(), suffix);
+        self
+    }
+
+    /// Translates the unit of the metric named `name` into `suffix`, or drops it if `None`.
+    pub fn with_metric(mut self, name: impl Into<String>, suffix: Option<&str>) -> Self {
+        insert_override(&mut self.metrics, name.into(), suffix);
+        self
+    }
+
+    /// Gets the unit suffix of the metric named `name` with the UCUM `unit`.
+    pub(crate) fn get_unit_suffixes(&self, name: &str, unit: &str) -> Option<Cow<'static, str>> {
+        let find = |overrides: &[(String, Option<String>)], key: &str| {
+            overrides
+                .iter()
+                .find(|(known, _)| known == key)
+                .map(|(_, suffix)| suffix.clone())
+        };
+        match find(&self.metrics, name).or_else(|| find(&self.units, unit)) {
+            Some(suffix) => suffix.map(Cow::Owned),
+            None => get_unit_suffixes(unit),
+        }
+    }
+}
+
+/// Inserts or replaces the override of `key`, sanitizing the `suffix` so that it can be part of a metric name.
+fn insert_override(
+    overrides: &mut Vec<(String, Option<String>)>,
+    key: String,
+    suffix: Option<&str>,
+) {
+    let suffix = suffix.map(|suffix| {
+        let mut sanitized = String::with_capacity(suffix.len());
+        let Ok(()) = write_sanitized_name(&mut sanitized, suffix);
+        sanitized
+    });
+    match overrides.iter_mut().find(|(known, _)| *known == key) {
+        Some((_, known)) => *known = suffix,
+        None => overrides.push((key, suffix)),
+    }
+}
+
+/// A prefix, atom and exponent of a [UCUM](https://ucum.org/ucum) unit.
+struct Component {
+    prefix: &'static str,
+    atom: &'static Atom,
+    exponent: i32,
+}
+
+impl Component {
+    fn name(&self, plural: bool) -> String {
+        let atom = if plural {
+            self.atom.plural
+        } else {
+            self.atom.singular
+        };
+        match self.exponent.abs() {
+            1 => format!("{}{atom}", self.prefix),
+            2 => format!("square_{}{atom}", self.prefix),
+            3 => format!("cubic_{}{atom}", self.prefix),
+            exponent => format!("{}{atom}_pow{exponent}", self.prefix),
+        }
+    }
+}
+
+/// A UCUM unit atom and its long names.
+struct Atom {
+    symbol: &'static str,
+    singular: &'static str,
+    plural: &'static str,
+    /// whether the atom accepts SI and binary prefixes
+    metric: bool,
+}
+
+const fn atom(
+    symbol: &'static str,
+    singular: &'static str,
+    plural: &'static str,
+    metric: bool,
+) -> Atom {
+    Atom {
+        symbol,
+        singular,
+        plural,
+        metric,
+    }
+}
+
+const ATOMS: &[Atom] = &[
+    // Time
+    atom("s", "second", "seconds", true),
+    atom("min", "minute", "minutes", false),
+    atom("h", "hour", "hours", false),
+    atom("d", "day", "days", false),
+    atom("wk", "week", "weeks", false),
+    atom("mo", "month", "months", false),
+    atom("a", "year", "years", false),
+    // Information
+    atom("By", "byte", "bytes", true),
+    atom("bit", "bit", "bits", true),
+    atom("Bd", "baud", "baud", true),
+    // SI base and derived units
+    atom("m", "meter", "meters", true),
+    atom("g", "gram", "grams", true),
+    atom("t", "tonne", "tonnes", true),
+    atom("l", "liter", "liters", true),
+    atom("L", "liter", "liters", true),
+    atom("A", "ampere", "amperes", true),
+    atom("K", "kelvin", "kelvins", true),
+    atom("Cel", "celsius", "celsius", true),
+    atom("mol", "mole", "moles", true),
+    atom("cd", "candela", "candelas", true),
+    atom("rad", "radian", "radians", true),
+    atom("sr", "steradian", "steradians", true),
+    atom("Hz", "hertz", "hertz", true),
+    atom("N", "newton", "newtons", true),
+    atom("Pa", "pascal", "pascals", true),
+    atom("J", "joule", "joules", true),
+    atom("W", "watt", "watts", true),
+    atom("C", "coulomb", "coulombs", true),
+    atom("V", "volt", "volts", true),
+    atom("F", "farad", "farads", true),
+    atom("Ohm", "ohm", "ohms", true),
+    atom("S", "siemens", "siemens", true),
+    atom("Wb", "weber", "webers", true),
+    atom("T", "tesla", "teslas", true),
+    atom("H", "henry", "henries", true),
+    atom("lm", "lumen", "lumens", true),
+    atom("lx", "lux", "lux", true),
+    atom("Bq", "becquerel", "becquerels", true),
+    atom("Gy", "gray", "grays", true),
+    atom("Sv", "sievert", "sieverts", true),
+    atom("eV", "electronvolt", "electronvolts", true),
+    atom("bar", "bar", "bars", true),
+    // Dimensionless
+    atom("%", "percent", "percent", false),
+];
+
+/// UCUM prefixes, the two letter ones first so that they are matched first.
+const PREFIXES: &[(&str, &str)] = &[
+    ("da", "deka"),
+    ("Ki", "kibi"),
+    ("Mi", "mebi"),
+    ("Gi", "gibi"),
+    ("Ti", "tebi"),
+    ("Y", "yotta"),
+    ("Z", "zetta"),
+    ("E", "exa"),
+    ("P", "peta"),
+    ("T", "tera"),
+    ("G", "giga"),
+    ("M", "mega"),
+    ("k", "kilo"),
+    ("h", "hecto"),
+    ("d", "deci"),
+    ("c", "centi"),
+    ("m", "milli"),
+    ("u", "micro"),
+    ("n", "nano"),
+    ("p", "pico"),
+    ("f", "femto"),
+    ("a", "atto"),
+    ("z", "zepto"),
+    ("y", "yocto"),
+];
+
+/// Parses a [UCUM](https://ucum.org/ucum) unit term of components joined by `.` and `/` into a long unit name like
+/// `kilowatt_hours` or `mebibits_per_second`.
+///
+/// A `/` divides by the component directly after it only, like in `N.m/mol.K`. Components may have prefixes, integer
+/// exponents and `{annotations}`, which are ignored. Returns `None` if any component is unknown, or if there is no unit
+/// besides annotations and the factor `1`.
+fn parse_ucum(unit: &str) -> Option<String> {
+    let mut numerator: Vec<Component> = Vec::new();
+    let mut denominator: Vec<Component> = Vec::new();
+    let mut divide = false;
+    let mut start = 0;
+    let mut depth = 0;
+    for (i, c) in unit.char_indices().chain([(unit.len(), '.')]) {
+        match c {
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            '.' | '/' if depth == 0 => {
+                let component = &unit[start..i];
+                // A term may start with `/`, e.g. `/s`
+                if !(component.is_empty() && i == 0 && c == '/')
+                    && let Some(component) = parse_component(component)?
+                {
+                    if divide == (component.exponent < 0) {
+                        numerator.push(component);
+                    } else {
+                        denominator.push(component);
+                    }
+                }
+                divide = c == '/';
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    if depth != 0 {
+        return None;
+    }
+
+    let mut parts: Vec<String> = Vec::with_capacity(numerator.len() + denominator.len() + 1);
+    let last = numerator.len().saturating_sub(1);
+    for (i, component) in numerator.iter().enumerate() {
+        parts.push(component.name(i == last));
+    }
+    if !denominator.is_empty() {
+        parts.push("per".to_owned());
+        parts.extend(denominator.iter().map(|component| component.name(false)));
+    }
+    if parts.is_empty() {
+        None
+    } else {
+        Some(parts.join("_"))
+    }
+}
+
+/// Parses a single UCUM component. Returns `Some(None)` for annotations and the factor `1`, which have no name.
+fn parse_component(component: &str) -> Option<Option<Component>> {
+    let component = match component.split_once('{') {
+        Some((unit, annotation)) if annotation.ends_with('}') => unit,
+        Some(_) => return None,
+        None => component,
+    };
+    if component.is_empty() || component == "1" {
+        return Some(None);
+    }
+
+    let unit = component.trim_end_matches(|c: char| c.is_ascii_digit());
+    let unit = unit
+        .strip_suffix(['-', '+'])
+        .filter(|_| unit.len() < component.len())
+        .unwrap_or(unit);
+    let exponent = match &component[unit.len()..] {
+        "" => 1,
+        exponent => exponent.parse().ok().filter(|&exponent| exponent != 0)?,
+    };
+
+    if let Some(atom) = ATOMS.iter().find(|atom| atom.symbol == unit) {
+        return Some(Some(Component {
+            prefix: "",
+            atom,
+            exponent,
+        }));
+    }
+    PREFIXES.iter().find_map(|&(symbol, prefix)| {
+        let unit = unit.strip_prefix(symbol)?;
+        // Binary prefixes only apply to units of information
+        let binary = matches!(symbol, "Ki" | "Mi" | "Gi" | "Ti");
+        let atom = ATOMS.iter().find(|atom| {
+            atom.metric && atom.symbol == unit && (!binary || matches!(unit, "By" | "bit"))
+        })?;
+        Some(Some(Component {
+            prefix,
+            atom,
+            exponent,
+        }))
+    })
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
 
+    #[test]
+    fn test_unit_mapping() {
+        let mapping = UnitMapping::new()
+            .with_unit("{request}", Some("requests"))
+            .with_unit("K", Some("kelvin"))
+            .with_unit("K", Some("kelvin degrees"))
+            .with_unit("1", None)
+            .with_metric("temperature", Some("celsius"));
+
+        assert_eq!(
+            mapping.get_unit_suffixes("http.requests", "{request}"),
+            Some(Cow::Owned("requests".to_owned()))
+        );
+        assert_eq!(
+            mapping.get_unit_suffixes("temperature.core", "K"),
+            Some(Cow::Owned("kelvin_degrees".to_owned()))
+        );
+        assert_eq!(
+            mapping.get_unit_suffixes("temperature", "K"),
+            Some(Cow::Owned("celsius".to_owned()))
+        );
+        assert_eq!(mapping.get_unit_suffixes("utilization", "1"), None);
+        assert_eq!(
+            mapping.get_unit_suffixes("memory", "By"),
+            Some(Cow::Borrowed("bytes"))
+        );
+    }
+
     #[test]
     fn test_get_unit_suffixes() {
         let test_cases = vec![
@@ -120,6 +456,48 @@ mod test {
             ("", None),
             // annotations
             ("{request}", None),
+            // UCUM
+            ("kW", Some(Cow::Owned("kilowatts".to_owned()))),
+            ("mA", Some(Cow::Owned("milliamperes".to_owned()))),
+            ("cm", Some(Cow::Owned("centimeters".to_owned()))),
+            ("kPa", Some(Cow::Owned("kilopascals".to_owned()))),
+            ("daL", Some(Cow::Owned("dekaliters".to_owned()))),
+            (
+                "Mibit/s",
+                Some(Cow::Owned("mebibits_per_second".to_owned())),
+            ),
+            ("{packets}/s", Some(Cow::Owned("per_second".to_owned()))),
+            ("/min", Some(Cow::Owned("per_minute".to_owned()))),
+            (
+                "By{compressed}/s",
+                Some(Cow::Owned("bytes_per_second".to_owned())),
+            ),
+            ("kW.h", Some(Cow::Owned("kilowatt_hours".to_owned()))),
+            ("m2", Some(Cow::Owned("square_meters".to_owned()))),
+            (
+                "m/s2",
+                Some(Cow::Owned("meters_per_square_second".to_owned())),
+            ),
+            ("s-1", Some(Cow::Owned("per_second".to_owned()))),
+            ("m4", Some(Cow::Owned("meters_pow4".to_owned()))),
+            ("J/(kg)", None),
+            (
+                "N.m/mol.K",
+                Some(Cow::Owned("newton_meter_kelvins_per_mole".to_owned())),
+            ),
+            (
+                "m/s/s",
+                Some(Cow::Owned("meters_per_second_second".to_owned())),
+            ),
+            (
+                "KiBy/s",
+                Some(Cow::Owned("kibibytes_per_second".to_owned())),
+            ),
+            ("Kim", None),
+            ("Mis", None),
+            ("{a.b/c}", None),
+            ("kmin", None),
+            ("kB{", None),
         ];
         for (unit, expected_suffix) in test_cases {
             assert_eq!(get_unit_suffixes(unit), expected_suffix);
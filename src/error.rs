@@ -0,0 +1,174 @@
+use std::fmt::{self, Display, Formatter};
+
+use opentelemetry_sdk::metrics::Temporality;
+
+/// An error converting metrics, carrying the instrumentation scope and the name of the metric at fault.
+///
+/// In lenient mode, the default, the errors of the skipped or reset metrics are returned as diagnostics instead, and only
+/// [`Error::Write`] fails the conversion. A strict conversion, see
+/// [`ConvertOptions::with_lenient`](crate::convert::ConvertOptions::with_lenient), fails on the first error.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum Error {
+    /// Writing to the output failed, while writing the metric if there is one.
+    Write {
+        scope: Option<String>,
+        metric: Option<String>,
+    },
+    /// The aggregation of a metric cannot be converted, like an exponential histogram with delta temporality.
+    UnsupportedAggregation {
+        scope: String,
+        metric: String,
+        aggregation: &'static str,
+    },
+    /// The temporality of a metric cannot be converted, like a delta sum written without a
+    /// [`DeltaAccumulator`](crate::convert::DeltaAccumulator).
+    UnsupportedTemporality {
+        scope: String,
+        metric: String,
+        temporality: Temporality,
+    },
+    /// The name of a metric collides with a metric family of another type or unit, or with the samples of another
+    /// family.
+    NameCollision {
+        scope: String,
+        metric: String,
+        family: String,
+    },
+    /// A metric has a value which OpenMetrics does not allow, like a negative counter.
+    InvalidValue {
+        scope: String,
+        metric: String,
+        reason: &'static str,
+    },
+    /// A series accumulated by a [`DeltaAccumulator`](crate::convert::DeltaAccumulator) exceeded the range of its
+    /// number type, and was started over with the latest delta.
+    Overflow { scope: String, metric: String },
+}
+
+impl Error {
+    /// The name of the instrumentation scope of the metric at fault, if the error concerns a metric.
+    pub fn scope(&self) -> Option<&str> {
+        match self {
+            Error::Write { scope, .. } => scope.as_deref(),
+            Error::UnsupportedAggregation { scope, .. }
+            | Error::UnsupportedTemporality { scope, .. }
+            | Error::NameCollision { scope, .. }
+            | Error::InvalidValue { scope, .. }
+            | Error::Overflow { scope, .. } => Some(scope),
+        }
+    }
+
+    /// The name of the metric at fault, if the error concerns a metric.
+    pub fn metric(&self) -> Option<&str> {
+        match self {
+            Error::Write { metric, .. } => metric.as_deref(),
+            Error::UnsupportedAggregation { metric, .. }
+            | Error::UnsupportedTemporality { metric, .. }
+            | Error::NameCollision { metric, .. }
+            | Error::InvalidValue { metric, .. }
+            | Error::Overflow { metric, .. } => Some(metric),
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Write { .. } => f.write_str("failed to write to the output")?,
+            Error::UnsupportedAggregation { aggregation, .. } => {
+                write!(f, "unsupported aggregation {aggregation}")?
+            }
+            Error::UnsupportedTemporality { temporality, .. } => {
+                write!(f, "unsupported temporality {temporality:?}")?
+            }
+            Error::NameCollision { family, .. } => write!(
+                f,
+                "the metric family {family} collides with another family or its samples"
+            )?,
+            Error::InvalidValue { reason, .. } => write!(f, "invalid value: {reason}")?,
+            Error::Overflow { .. } => {
+                f.write_str("the accumulated value overflowed and was reset")?
+            }
+        }
+        if let Some(metric) = self.metric() {
+            write!(f, " in metric {metric}")?;
+        }
+        if let Some(scope) = self.scope() {
+            write!(f, " of scope {scope}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Collects the errors of the metrics skipped or reset in lenient mode.
+#[derive(Debug)]
+pub(crate) struct Diagnostics {
+    lenient: bool,
+    errors: Vec<Error>,
+}
+
+impl Diagnostics {
+    pub(crate) fn new(lenient: bool) -> Self {
+        Diagnostics {
+            lenient,
+            errors: Vec::new(),
+        }
+    }
+
+    /// Records the `error` of a metric which is skipped or reset in lenient mode, or returns it otherwise.
+    pub(crate) fn report(&mut self, error: Error) -> Result<(), Error> {
+        if !self.lenient {
+            return Err(error);
+        }
+        #[cfg(feature = "tracing")]
+        tracing::warn!("Converting metrics: {error}");
+        self.errors.push(error);
+        Ok(())
+    }
+
+    pub(crate) fn into_errors(self) -> Vec<Error> {
+        self.errors
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_display() {
+        let error = Error::NameCollision {
+            scope: "meter".to_owned(),
+            metric: "requests".to_owned(),
+            family: "requests_seconds".to_owned(),
+        };
+        assert_eq!(
+            error.to_string(),
+            "the metric family requests_seconds collides with another family or its samples in metric requests of \
+             scope meter"
+        );
+        let error = Error::Write {
+            scope: None,
+            metric: None,
+        };
+        assert_eq!(error.to_string(), "failed to write to the output");
+        assert_eq!(error.metric(), None);
+    }
+
+    #[test]
+    fn test_diagnostics() {
+        let error = Error::InvalidValue {
+            scope: "meter".to_owned(),
+            metric: "requests".to_owned(),
+            reason: "negative counter",
+        };
+        let mut strict = Diagnostics::new(false);
+        assert_eq!(strict.report(error.clone()), Err(error.clone()));
+        let mut lenient = Diagnostics::new(true);
+        assert_eq!(lenient.report(error.clone()), Ok(()));
+        assert_eq!(lenient.into_errors(), [error]);
+    }
+}
@@ -1,3 +1,4 @@
+use std::borrow::Cow;
 use std::sync::Arc;
 use std::time::Duration;
 
@@ -7,20 +8,41 @@ use opentelemetry_sdk::metrics::data::ResourceMetrics;
 use opentelemetry_sdk::metrics::exporter::PushMetricExporter;
 use tokio::sync::{Mutex, RwLock};
 
-use crate::convert::WriteOpenMetrics;
+use crate::convert::{ConvertOptions, DeltaAccumulator, Format, NameEscaping, WriteFormat};
+#[cfg(any(feature = "server", feature = "tower"))]
+use crate::convert::{TextFormat, negotiate_among, negotiate_with_escaping};
+use crate::error::Error;
 
 /// A [`PushMetricExporter`] which writes metrics into an internal buffer in OpenMetrics text format.
+///
+/// Sums and histograms exported with delta temporality are accumulated into cumulative series.
+///
+/// With the `server` or `tower` feature, the metrics are also written in the other formats and name escapings which
+/// the servers negotiate with scrapers, from the first export after a scraper asked for them.
 #[derive(Debug, Clone)]
 pub struct OpenMetricsExporter {
-    buffer: Arc<RwLock<String>>,
-    backbuffer: Arc<Mutex<String>>,
+    buffer: Arc<RwLock<Rendered>>,
+    backbuffer: Arc<Mutex<Rendered>>,
+    accumulator: Arc<Mutex<DeltaAccumulator>>,
+    /// the formats and name escapings other than those of the options which scrapers asked for
+    requested: Arc<Mutex<Vec<(Format, NameEscaping)>>>,
+    temporality: Temporality,
+    options: ConvertOptions,
+    #[cfg(feature = "server")]
+    server_shutdown: Arc<crate::server::Shutdown>,
 }
 
 impl Default for OpenMetricsExporter {
     fn default() -> Self {
         Self {
-            buffer: Arc::new(RwLock::new(String::new())),
-            backbuffer: Arc::new(Mutex::new(String::new())),
+            buffer: Arc::default(),
+            backbuffer: Arc::default(),
+            accumulator: Arc::new(Mutex::new(DeltaAccumulator::default())),
+            requested: Arc::default(),
+            temporality: Temporality::Cumulative,
+            options: ConvertOptions::default(),
+            #[cfg(feature = "server")]
+            server_shutdown: Arc::default(),
         }
     }
 }
@@ -31,9 +53,110 @@ impl OpenMetricsExporter {
         Self::default()
     }
 
+    /// Creates a builder to configure an exporter at runtime.
+    pub fn builder() -> OpenMetricsExporterBuilder {
+        OpenMetricsExporterBuilder::default()
+    }
+
+    /// Sets the temporality requested from the SDK, [`Temporality::Cumulative`] by default.
+    ///
+    /// Delta sums and histograms are accumulated by the exporter before being written.
+    pub fn with_temporality(mut self, temporality: Temporality) -> Self {
+        self.temporality = temporality;
+        self
+    }
+
+    /// Sets the options used to convert the exported metrics.
+    pub fn with_options(mut self, options: ConvertOptions) -> Self {
+        self.options = options;
+        self
+    }
+
     /// Get a clone of the last-exported OpenMetrics text.
     pub async fn text(&self) -> String {
-        self.buffer.read().await.as_str().to_owned()
+        let format = Format::Text(self.options.text_format);
+        let buffer = self.buffer.read().await;
+        let text = buffer.get(format, self.options.name_escaping);
+        String::from_utf8_lossy(text.unwrap_or_default()).into_owned()
+    }
+
+    /// Get the errors of the metrics which were skipped in the last export, see [`ConvertOptions::with_lenient`].
+    pub async fn diagnostics(&self) -> Vec<Error> {
+        self.buffer.read().await.diagnostics.clone()
+    }
+
+    /// The formats the servers offer, the text format of the options first.
+    #[cfg(any(feature = "server", feature = "tower"))]
+    pub(crate) fn formats(&self) -> Vec<Format> {
+        let mut formats = vec![Format::Text(self.options.text_format)];
+        let mut offered = vec![
+            Format::Text(TextFormat::OpenMetrics),
+            Format::Text(TextFormat::Prometheus),
+        ];
+        if self.options.native_histograms {
+            offered.push(Format::Text(TextFormat::OpenMetrics2));
+        }
+        #[cfg(feature = "protobuf")]
+        offered.push(Format::Protobuf);
+        for format in offered {
+            if !formats.contains(&format) {
+                formats.push(format);
+            }
+        }
+        formats
+    }
+
+    /// Negotiates the format and name escaping of the response to a request with the `Accept` header `accept`
+    /// among the [`formats`](Self::formats), and gets a clone of the metrics of the last export written in them.
+    ///
+    /// The escaping is taken from the `escaping` parameter of the chosen media range, the one of the options if there
+    /// is none. Formats and escapings other than those of the options are only written from the next export on once
+    /// they were asked for. Until then, the request gets another acceptable format or escaping which was written, or
+    /// an empty body. Returns `None` if none of the formats is acceptable.
+    #[cfg(any(feature = "server", feature = "tower"))]
+    pub(crate) async fn negotiate(&self, accept: &str) -> Option<(Format, NameEscaping, Vec<u8>)> {
+        let (format, escaping) = negotiate_with_escaping(accept, &self.formats())?;
+        let escaping = escaping.unwrap_or(self.options.name_escaping);
+        let buffer = self.buffer.read().await;
+        if let Some(body) = buffer.get(format, escaping) {
+            return Some((format, escaping, body.to_vec()));
+        }
+        let mut requested = self.requested.lock().await;
+        if !requested.contains(&(format, escaping)) {
+            requested.push((format, escaping));
+        }
+        let written: Vec<Format> = buffer.formats.iter().map(|(format, ..)| *format).collect();
+        let fallback = negotiate_among(accept, &written).and_then(|written| {
+            // Another escaping of the chosen format is preferred over another format
+            buffer
+                .formats
+                .iter()
+                .filter(|(format, ..)| *format == written)
+                .min_by_key(|(_, written, _)| *written != escaping)
+        });
+        match fallback {
+            Some((format, escaping, body)) => Some((*format, *escaping, body.clone())),
+            None => Some((format, escaping, Vec::new())),
+        }
+    }
+
+    /// The formats and name escapings the metrics are written in on export, those of the options first.
+    async fn written_formats(&self) -> Vec<(Format, NameEscaping)> {
+        let mut formats = vec![(
+            Format::Text(self.options.text_format),
+            self.options.name_escaping,
+        )];
+        for &variant in self.requested.lock().await.iter() {
+            if !formats.contains(&variant) {
+                formats.push(variant);
+            }
+        }
+        formats
+    }
+
+    #[cfg(feature = "server")]
+    pub(crate) fn server_shutdown(&self) -> &Arc<crate::server::Shutdown> {
+        &self.server_shutdown
     }
 }
 
@@ -42,12 +165,27 @@ impl PushMetricExporter for OpenMetricsExporter {
         #[cfg(feature = "tracing")]
         tracing::debug!("Exporting metrics");
         let mut backbuffer = self.backbuffer.lock().await;
+        let mut accumulator = self.accumulator.lock().await;
+        let accumulated = accumulator.accumulate(metrics);
         backbuffer.clear();
-        metrics
-            .write_as_openmetrics(&mut *backbuffer)
-            .map_err(|err| {
-                OTelSdkError::InternalFailure(format!("Failed to write to buffer: {err}"))
-            })?;
+        for (format, escaping) in self.written_formats().await {
+            let mut buf = backbuffer.take_buffer();
+            let options = if escaping == self.options.name_escaping {
+                Cow::Borrowed(&self.options)
+            } else {
+                Cow::Owned(self.options.clone().with_name_escaping(escaping))
+            };
+            let diagnostics = accumulated
+                .write_as_format(format, &mut buf, &options)
+                .map_err(|err| {
+                    OTelSdkError::InternalFailure(format!("Failed to convert metrics: {err}"))
+                })?;
+            // All formats skip the same metrics
+            if backbuffer.formats.is_empty() {
+                backbuffer.diagnostics = diagnostics;
+            }
+            backbuffer.formats.push((format, escaping, buf));
+        }
 
         let mut frontbuffer = self.buffer.write().await;
         std::mem::swap(&mut *frontbuffer, &mut *backbuffer);
@@ -59,11 +197,90 @@ impl PushMetricExporter for OpenMetricsExporter {
         Ok(())
     }
 
+    /// Stops the servers started with [`MetricsServer`](crate::server::MetricsServer), waiting at most `timeout`
+    /// for them to answer their pending requests.
+    ///
+    /// When called on a thread running a tokio runtime, the servers are stopped without waiting for them, see
+    /// [`ServerHandle::stopped`](crate::server::ServerHandle::stopped).
+    #[cfg(feature = "server")]
+    fn shutdown_with_timeout(&self, timeout: Duration) -> OTelSdkResult {
+        self.server_shutdown.shutdown(timeout)
+    }
+
+    #[cfg(not(feature = "server"))]
     fn shutdown_with_timeout(&self, _timeout: Duration) -> OTelSdkResult {
         Ok(())
     }
 
     fn temporality(&self) -> Temporality {
-        Temporality::Cumulative
+        self.temporality
+    }
+}
+
+/// The body of the `406 Not Acceptable` response to a request accepting none of the `formats`.
+#[cfg(any(feature = "server", feature = "tower"))]
+pub(crate) fn not_acceptable(formats: &[Format]) -> String {
+    let content_types: Vec<_> = formats.iter().map(|format| format.content_type()).collect();
+    format!("Metrics are available as {}\n", content_types.join(", "))
+}
+
+/// The metrics of an export, written in each of the formats and name escapings which were configured or asked for.
+#[derive(Debug, Default)]
+struct Rendered {
+    formats: Vec<(Format, NameEscaping, Vec<u8>)>,
+    /// the errors of the metrics which were skipped
+    diagnostics: Vec<Error>,
+    /// the buffers of the previous export, kept to reuse their allocations
+    spare: Vec<Vec<u8>>,
+}
+
+impl Rendered {
+    fn get(&self, format: Format, escaping: NameEscaping) -> Option<&[u8]> {
+        self.formats
+            .iter()
+            .find(|(rendered, escaped, _)| *rendered == format && *escaped == escaping)
+            .map(|(.., buf)| buf.as_slice())
+    }
+
+    fn clear(&mut self) {
+        for (.., mut buf) in self.formats.drain(..) {
+            buf.clear();
+            self.spare.push(buf);
+        }
+    }
+
+    fn take_buffer(&mut self) -> Vec<u8> {
+        self.spare.pop().unwrap_or_default()
+    }
+}
+
+/// A builder for an [`OpenMetricsExporter`], see [`OpenMetricsExporter::builder`].
+///
+/// The switches of the conversion, like scope info, scope labels, histogram min/max, timestamps and suffixes, are
+/// set at runtime on the [`ConvertOptions`]. The cargo features only set their defaults.
+#[derive(Debug, Clone, Default)]
+pub struct OpenMetricsExporterBuilder {
+    temporality: Temporality,
+    options: ConvertOptions,
+}
+
+impl OpenMetricsExporterBuilder {
+    /// Sets the temporality requested from the SDK, see [`OpenMetricsExporter::with_temporality`].
+    pub fn with_temporality(mut self, temporality: Temporality) -> Self {
+        self.temporality = temporality;
+        self
+    }
+
+    /// Sets the options used to convert the exported metrics.
+    pub fn with_options(mut self, options: ConvertOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Creates the configured exporter.
+    pub fn build(self) -> OpenMetricsExporter {
+        OpenMetricsExporter::default()
+            .with_temporality(self.temporality)
+            .with_options(self.options)
     }
 }
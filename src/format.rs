@@ -4,33 +4,50 @@ pub trait FastDisplay {
     fn fast_display(&self) -> impl uDisplay + Copy + use<Self>;
 }
 
+/// Shows an `f64` like its `Display`, but with the special values `NaN`, `+Inf` and `-Inf` of OpenMetrics.
 #[derive(Copy, Clone)]
-struct RyuDisplay<N: ryu::Float>(N);
+struct RyuDisplay {
+    value: f64,
+    /// whether integral values keep their `.0`, as in the canonical form of floats in label values
+    canonical: bool,
+}
 
-impl<N: ryu::Float> uDisplay for RyuDisplay<N> {
+impl uDisplay for RyuDisplay {
     fn fmt<W>(&self, f: &mut ufmt::Formatter<'_, W>) -> Result<(), W::Error>
     where
         W: ufmt::uWrite + ?Sized,
     {
+        if self.value.is_nan() {
+            return f.write_str("NaN");
+        } else if self.value.is_infinite() {
+            return f.write_str(if self.value > 0.0 { "+Inf" } else { "-Inf" });
+        }
         let mut buffer = ryu::Buffer::new();
-        let mut formatted = buffer.format(self.0);
-
-        // Remove trailing .0 to match f64 Display
-        let formatted_bytes = formatted.as_bytes();
-        if formatted_bytes.ends_with(b".0") {
-            formatted = unsafe {
-                std::str::from_utf8_unchecked(&formatted_bytes[..formatted_bytes.len() - 2])
-            }
+        let formatted = buffer.format_finite(self.value);
+        if self.canonical {
+            return f.write_str(formatted);
         }
-
-        f.write_str(formatted)
+        // Remove trailing .0 to match f64 Display
+        f.write_str(formatted.strip_suffix(".0").unwrap_or(formatted))
     }
 }
 
 impl FastDisplay for f64 {
     #[inline]
     fn fast_display(&self) -> impl uDisplay + Copy + use<> {
-        RyuDisplay(*self)
+        RyuDisplay {
+            value: *self,
+            canonical: false,
+        }
+    }
+}
+
+/// Shows `value` in the canonical form of floats in label values like `le`, which keeps the `.0` of integral
+/// values, e.g. `1.0`.
+pub fn canonical_float(value: f64) -> impl uDisplay + Copy {
+    RyuDisplay {
+        value,
+        canonical: true,
     }
 }
 
@@ -86,3 +103,32 @@ mod fast_impl_without {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn show(value: impl uDisplay) -> String {
+        let mut output = String::new();
+        ufmt::uwrite!(output, "{}", value).unwrap();
+        output
+    }
+
+    #[test]
+    fn test_special_values() {
+        assert_eq!(show(f64::NAN.fast_display()), "NaN");
+        assert_eq!(show(f64::INFINITY.fast_display()), "+Inf");
+        assert_eq!(show(f64::NEG_INFINITY.fast_display()), "-Inf");
+        assert_eq!(show(canonical_float(f64::INFINITY)), "+Inf");
+        assert_eq!(show((-0.0).fast_display()), "-0");
+    }
+
+    #[test]
+    fn test_canonical_float() {
+        assert_eq!(show(1.0.fast_display()), "1");
+        assert_eq!(show(canonical_float(1.0)), "1.0");
+        assert_eq!(show(canonical_float(0.25)), "0.25");
+        assert_eq!(show(canonical_float(-5.0)), "-5.0");
+        assert_eq!(show(canonical_float(1e21)), "1e21");
+    }
+}
@@ -0,0 +1,51 @@
+//! gzip compression of HTTP responses.
+
+use std::io::Write;
+
+use flate2::Compression;
+use flate2::write::GzEncoder;
+
+/// Whether the `Accept-Encoding` header `value` accepts gzip, by name or by the `*` wildcard unless gzip is listed.
+pub(crate) fn accepts_gzip(value: &str) -> bool {
+    let mut gzip = None;
+    let mut wildcard = None;
+    for coding in value.split(',') {
+        let mut params = coding.split(';');
+        let name = params.next().unwrap_or_default().trim();
+        let quality = params
+            .filter_map(|param| param.split_once('='))
+            .find(|(name, _)| name.trim().eq_ignore_ascii_case("q"))
+            .map_or(1.0, |(_, q)| q.trim().parse().unwrap_or(0.0));
+        if name.eq_ignore_ascii_case("gzip") {
+            gzip = Some(quality);
+        } else if name == "*" {
+            wildcard = Some(quality);
+        }
+    }
+    gzip.or(wildcard).is_some_and(|quality| quality > 0.0)
+}
+
+/// Compresses `body`, favouring speed over size since metrics are compressed on every request.
+pub(crate) fn gzip(body: &[u8]) -> Option<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::with_capacity(body.len() / 4), Compression::fast());
+    encoder.write_all(body).ok()?;
+    encoder.finish().ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_accepts_gzip() {
+        assert!(accepts_gzip("gzip"));
+        assert!(accepts_gzip("br;q=1.0, gzip;q=0.8"));
+        assert!(accepts_gzip("*"));
+        assert!(!accepts_gzip("gzip;q=0"));
+        // An explicit gzip takes precedence over the wildcard
+        assert!(!accepts_gzip("gzip;q=0, *"));
+        assert!(accepts_gzip("*;q=0, gzip"));
+        assert!(!accepts_gzip("deflate, br"));
+        assert!(!accepts_gzip(""));
+    }
+}
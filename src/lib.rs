@@ -2,8 +2,23 @@
 
 /// Implementation of the OpenMetrics text format conversion.
 pub mod convert;
+
 /// Contains the main interface of this crate, [`exporter::OpenMetricsExporter`].
 #[cfg(feature = "exporter")]
 pub mod exporter;
+/// Contains [`reader::OpenMetricsReader`], which collects metrics whenever they are requested.
+#[cfg(feature = "reader")]
+pub mod reader;
+/// Contains [`server::MetricsServer`], a standalone HTTP server exposing the metrics of an exporter.
+#[cfg(feature = "server")]
+pub mod server;
+/// Contains [`service::MetricsService`], a `tower` service answering requests with the metrics of an exporter.
+#[cfg(feature = "tower")]
+pub mod service;
 
+mod error;
 mod format;
+#[cfg(any(feature = "server", feature = "tower"))]
+mod gzip;
+
+pub use error::Error;
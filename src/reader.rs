@@ -0,0 +1,210 @@
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, Weak};
+use std::time::Duration;
+
+use opentelemetry_sdk::error::{OTelSdkError, OTelSdkResult};
+use opentelemetry_sdk::metrics::data::ResourceMetrics;
+use opentelemetry_sdk::metrics::reader::MetricReader;
+use opentelemetry_sdk::metrics::{InstrumentKind, ManualReader, Pipeline, Temporality};
+use tokio::sync::watch;
+
+use crate::convert::{ConvertOptions, DeltaAccumulator, WriteOpenMetrics};
+use crate::error::Error;
+
+/// The default timeout of a collection, see [`OpenMetricsReader::with_timeout`].
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The outcome of a collection, shared by all requests waiting for it.
+type Collection = Option<Result<Arc<str>, SharedError>>;
+
+/// A [`MetricReader`] which collects the metrics from the SDK when their OpenMetrics text is requested.
+///
+/// Unlike the [`OpenMetricsExporter`](crate::exporter::OpenMetricsExporter) behind a `PeriodicReader`, metrics are
+/// only collected while someone asks for them and are never an interval stale. Concurrent calls of
+/// [`text`](Self::text) share a single collection.
+///
+/// Sums and histograms collected with delta temporality are accumulated into cumulative series.
+#[derive(Debug, Clone)]
+pub struct OpenMetricsReader {
+    inner: Arc<ManualReader>,
+    state: Arc<State>,
+    temporality: Temporality,
+    options: ConvertOptions,
+    timeout: Duration,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    /// receives the outcome of the collection in progress, if there is one
+    in_flight: Mutex<Option<watch::Receiver<Collection>>>,
+    accumulator: Mutex<DeltaAccumulator>,
+    /// the errors of the metrics which were skipped in the last collection
+    diagnostics: Mutex<Vec<Error>>,
+}
+
+impl Default for OpenMetricsReader {
+    fn default() -> Self {
+        Self {
+            inner: Arc::new(ManualReader::default()),
+            state: Arc::default(),
+            temporality: Temporality::Cumulative,
+            options: ConvertOptions::default(),
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+}
+
+impl OpenMetricsReader {
+    /// Sets the temporality requested from the SDK, [`Temporality::Cumulative`] by default.
+    ///
+    /// Delta sums and histograms are accumulated by the reader before being written.
+    pub fn with_temporality(mut self, temporality: Temporality) -> Self {
+        self.inner = Arc::new(
+            ManualReader::builder()
+                .with_temporality(temporality)
+                .build(),
+        );
+        self.temporality = temporality;
+        self
+    }
+
+    /// Sets the options used to convert the collected metrics.
+    pub fn with_options(mut self, options: ConvertOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Sets how long [`text`](Self::text) waits for a collection, [`DEFAULT_TIMEOUT`] by default.
+    ///
+    /// The collection itself is not aborted on timeout, as the SDK offers no way to cancel it.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Collect the metrics and get them as OpenMetrics text.
+    ///
+    /// If a collection is already in progress, its result is returned instead of starting another one. A collection
+    /// which exceeds the timeout keeps running, and later calls wait for it rather than starting another one.
+    /// Must be called within a tokio runtime with time enabled.
+    pub async fn text(&self) -> Result<String, OTelSdkError> {
+        let mut receiver = self.join_or_start_collection();
+        let collection = tokio::time::timeout(self.timeout, receiver.wait_for(Option::is_some))
+            .await
+            .map_err(|_| OTelSdkError::Timeout(self.timeout))?
+            .map_err(|_| OTelSdkError::InternalFailure("Collection was aborted".to_owned()))?;
+        match collection
+            .as_ref()
+            .expect("waited for the collection to finish")
+        {
+            Ok(text) => Ok(text.to_string()),
+            Err(err) => Err(err.to_error()),
+        }
+    }
+
+    /// Get the errors of the metrics which were skipped in the last collection, see
+    /// [`ConvertOptions::with_lenient`].
+    pub fn diagnostics(&self) -> Vec<Error> {
+        lock(&self.state.diagnostics).clone()
+    }
+
+    fn join_or_start_collection(&self) -> watch::Receiver<Collection> {
+        let mut in_flight = lock(&self.state.in_flight);
+        // A closed channel means the collecting task was dropped with its runtime
+        if let Some(receiver) = &*in_flight
+            && receiver.has_changed().is_ok()
+        {
+            return receiver.clone();
+        }
+        let (sender, receiver) = watch::channel(None);
+        *in_flight = Some(receiver.clone());
+
+        // Collect in a task of its own, so that cancelled requests do not abort it for the others. It waits for the
+        // blocking collection even after the requests timed out, so that slow collections do not pile up.
+        let reader = self.clone();
+        tokio::spawn(async move {
+            let collection = tokio::task::spawn_blocking({
+                let reader = reader.clone();
+                move || reader.collect_text()
+            });
+            let result = match collection.await {
+                Ok(result) => result.map_err(|err| SharedError::from(&err)),
+                Err(err) => Err(SharedError::InternalFailure(format!(
+                    "Collection failed: {err}"
+                ))),
+            };
+            // Requests from now on start a new collection
+            *lock(&reader.state.in_flight) = None;
+            sender.send_replace(Some(result));
+        });
+        receiver
+    }
+
+    fn collect_text(&self) -> Result<Arc<str>, OTelSdkError> {
+        let mut metrics = ResourceMetrics::default();
+        self.inner.collect(&mut metrics)?;
+        let mut text = String::new();
+        let diagnostics = lock(&self.state.accumulator)
+            .accumulate(&metrics)
+            .write_as_openmetrics_with(&mut text, &self.options)
+            .map_err(|err| {
+                OTelSdkError::InternalFailure(format!("Failed to convert metrics: {err}"))
+            })?;
+        *lock(&self.state.diagnostics) = diagnostics;
+        Ok(text.into())
+    }
+}
+
+impl MetricReader for OpenMetricsReader {
+    fn register_pipeline(&self, pipeline: Weak<Pipeline>) {
+        self.inner.register_pipeline(pipeline);
+    }
+
+    fn collect(&self, rm: &mut ResourceMetrics) -> OTelSdkResult {
+        self.inner.collect(rm)
+    }
+
+    fn force_flush(&self) -> OTelSdkResult {
+        self.inner.force_flush()
+    }
+
+    fn shutdown_with_timeout(&self, timeout: Duration) -> OTelSdkResult {
+        self.inner.shutdown_with_timeout(timeout)
+    }
+
+    fn temporality(&self, _kind: InstrumentKind) -> Temporality {
+        self.temporality
+    }
+}
+
+/// A clonable copy of an [`OTelSdkError`].
+#[derive(Debug, Clone)]
+enum SharedError {
+    AlreadyShutdown,
+    Timeout(Duration),
+    InternalFailure(String),
+}
+
+impl From<&OTelSdkError> for SharedError {
+    fn from(err: &OTelSdkError) -> Self {
+        match err {
+            OTelSdkError::AlreadyShutdown => SharedError::AlreadyShutdown,
+            OTelSdkError::Timeout(timeout) => SharedError::Timeout(*timeout),
+            OTelSdkError::InternalFailure(message) => SharedError::InternalFailure(message.clone()),
+        }
+    }
+}
+
+impl SharedError {
+    fn to_error(&self) -> OTelSdkError {
+        match self {
+            SharedError::AlreadyShutdown => OTelSdkError::AlreadyShutdown,
+            SharedError::Timeout(timeout) => OTelSdkError::Timeout(*timeout),
+            SharedError::InternalFailure(message) => OTelSdkError::InternalFailure(message.clone()),
+        }
+    }
+}
+
+/// Locks `mutex`, ignoring panics of previous holders since the guarded state stays consistent.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
@@ -0,0 +1,470 @@
+//! A minimal standalone HTTP/1.1 server exposing the metrics of an
+//! [`OpenMetricsExporter`](crate::exporter::OpenMetricsExporter).
+//!
+//! Only what a scraper needs is implemented: `GET` and `HEAD` requests of the metrics and health paths, persistent
+//! connections, negotiation of the format and name escaping, and gzip compression of the response.
+
+use std::borrow::Cow;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::time::Duration;
+
+use opentelemetry_sdk::error::{OTelSdkError, OTelSdkResult};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::watch;
+use tokio::task::{JoinHandle, JoinSet};
+
+use crate::exporter::{OpenMetricsExporter, not_acceptable};
+use crate::gzip::{accepts_gzip, gzip};
+
+/// The default path the metrics are served on, see [`MetricsServer::with_metrics_path`].
+pub const DEFAULT_METRICS_PATH: &str = "/metrics";
+/// The default path of the health endpoint, see [`MetricsServer::with_health_path`].
+pub const DEFAULT_HEALTH_PATH: &str = "/health";
+
+/// The maximum size of a request line and its headers.
+const MAX_HEAD_SIZE: usize = 8192;
+
+/// Builder of a standalone HTTP server serving the metrics of an [`OpenMetricsExporter`].
+///
+/// The metrics are written in the format negotiated from the `Accept` header, like with
+/// [`MetricsService`](crate::service::MetricsService) of the `tower` feature.
+///
+/// The server runs on the tokio runtime it is bound in, until the exporter is shut down through
+/// [`PushMetricExporter::shutdown_with_timeout`](opentelemetry_sdk::metrics::exporter::PushMetricExporter::shutdown_with_timeout),
+/// e.g. when the `SdkMeterProvider` shuts down.
+///
+/// ```no_run
+/// # async fn example() -> std::io::Result<()> {
+/// use ottotom::exporter::OpenMetricsExporter;
+/// use ottotom::server::MetricsServer;
+///
+/// let exporter = OpenMetricsExporter::default();
+/// let server = MetricsServer::new(exporter.clone())
+///     .bind("127.0.0.1:9464".parse().unwrap())
+///     .await?;
+/// println!("Serving metrics on http://{}/metrics", server.local_addr());
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct MetricsServer {
+    exporter: OpenMetricsExporter,
+    metrics_path: String,
+    health_path: String,
+}
+
+impl MetricsServer {
+    /// Creates a server serving the metrics of `exporter`.
+    pub fn new(exporter: OpenMetricsExporter) -> Self {
+        Self {
+            exporter,
+            metrics_path: DEFAULT_METRICS_PATH.to_owned(),
+            health_path: DEFAULT_HEALTH_PATH.to_owned(),
+        }
+    }
+
+    /// Sets the path the metrics are served on, [`DEFAULT_METRICS_PATH`] by default.
+    pub fn with_metrics_path(mut self, path: impl Into<String>) -> Self {
+        self.metrics_path = path.into();
+        self
+    }
+
+    /// Sets the path of the health endpoint, [`DEFAULT_HEALTH_PATH`] by default.
+    ///
+    /// It answers `200 OK` as long as the server runs.
+    pub fn with_health_path(mut self, path: impl Into<String>) -> Self {
+        self.health_path = path.into();
+        self
+    }
+
+    /// Binds the server to `addr` and starts serving in a task on the current tokio runtime.
+    ///
+    /// Must be called within a tokio runtime with IO enabled. If the exporter is already shut down, the server stops
+    /// right away.
+    pub async fn bind(self, addr: SocketAddr) -> io::Result<ServerHandle> {
+        let listener = TcpListener::bind(addr).await?;
+        let local_addr = listener.local_addr()?;
+        let running = self.exporter.server_shutdown().clone().start();
+        let task = tokio::spawn(async move {
+            let _running = running;
+            self.serve(listener).await;
+        });
+        Ok(ServerHandle { local_addr, task })
+    }
+
+    async fn serve(self, listener: TcpListener) {
+        let server = Arc::new(self);
+        let mut shutdown = server.exporter.server_shutdown().subscribe();
+        let mut connections = JoinSet::new();
+        loop {
+            tokio::select! {
+                _ = shutdown.wait_for(|shutdown| *shutdown) => break,
+                accepted = listener.accept() => match accepted {
+                    Ok((stream, _)) => {
+                        let server = server.clone();
+                        let shutdown = server.exporter.server_shutdown().subscribe();
+                        connections.spawn(async move { server.handle_connection(stream, shutdown).await });
+                    }
+                    #[allow(unused_variables)]
+                    Err(err) => {
+                        #[cfg(feature = "tracing")]
+                        tracing::warn!("Failed to accept connection: {err}");
+                    }
+                },
+                // Reap finished connections so that they do not pile up
+                Some(_) = connections.join_next(), if !connections.is_empty() => {}
+            }
+        }
+        drop(listener);
+        // Let the connections finish the requests they are answering
+        while connections.join_next().await.is_some() {}
+    }
+
+    async fn handle_connection(&self, mut stream: TcpStream, mut shutdown: watch::Receiver<bool>) {
+        let mut buffer = Vec::new();
+        loop {
+            let request = tokio::select! {
+                _ = shutdown.wait_for(|shutdown| *shutdown) => return,
+                request = read_request(&mut stream, &mut buffer) => request,
+            };
+            let response = match request {
+                Ok(Some(request)) => self.respond(&request).await,
+                Ok(None) => return,
+                Err(RequestError::Io) => return,
+                Err(RequestError::TooLarge) => {
+                    Response::text("431 Request Header Fields Too Large", "").closing()
+                }
+                Err(RequestError::Malformed) => Response::text("400 Bad Request", "").closing(),
+            };
+            let close = response.close || *shutdown.borrow();
+            if stream.write_all(&response.encode(close)).await.is_err() || close {
+                return;
+            }
+        }
+    }
+
+    async fn respond(&self, request: &Request) -> Response {
+        let path = request
+            .target
+            .split_once('?')
+            .map_or(request.target.as_str(), |(path, _)| path);
+        let known_path = path == self.metrics_path || path == self.health_path;
+        let mut response = if !known_path {
+            Response::text("404 Not Found", "Not Found\n")
+        } else if !matches!(request.method.as_str(), "GET" | "HEAD") {
+            Response::text("405 Method Not Allowed", "").with_header("Allow", "GET, HEAD")
+        } else if path == self.metrics_path {
+            match self.exporter.negotiate(&request.accept).await {
+                Some((format, escaping, body)) => {
+                    let content_type = format.content_type_with_escaping(escaping);
+                    Response::new("200 OK", content_type, body)
+                        .with_header("Cache-Control", "no-store")
+                }
+                None => Response::text(
+                    "406 Not Acceptable",
+                    &not_acceptable(&self.exporter.formats()),
+                ),
+            }
+            .with_header("Vary", "Accept")
+        } else {
+            Response::text("200 OK", "OK\n")
+        };
+        if request.accepts_gzip {
+            response = response.gzipped();
+        }
+        if request.method == "HEAD" {
+            response.head = true;
+        }
+        response.close |= request.close;
+        response
+    }
+}
+
+/// Handle of a running [`MetricsServer`].
+#[derive(Debug)]
+pub struct ServerHandle {
+    local_addr: SocketAddr,
+    task: JoinHandle<()>,
+}
+
+impl ServerHandle {
+    /// The address the server is bound to, e.g. to find out the port picked for port `0`.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Waits until the server has stopped after the exporter was shut down.
+    pub async fn stopped(self) {
+        let _ = self.task.await;
+    }
+}
+
+/// Signals the servers of an exporter to shut down and waits for them to stop.
+#[derive(Debug)]
+pub(crate) struct Shutdown {
+    signal: watch::Sender<bool>,
+    /// the number of servers which have not stopped yet
+    running: Mutex<usize>,
+    stopped: Condvar,
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self {
+            signal: watch::Sender::new(false),
+            running: Mutex::new(0),
+            stopped: Condvar::new(),
+        }
+    }
+}
+
+impl Shutdown {
+    fn subscribe(&self) -> watch::Receiver<bool> {
+        self.signal.subscribe()
+    }
+
+    fn start(self: Arc<Self>) -> Running {
+        *lock(&self.running) += 1;
+        Running(self)
+    }
+
+    /// Stops all servers and blocks until they have answered their pending requests, at most for `timeout`.
+    ///
+    /// On a thread running a tokio runtime, the servers are only signalled: blocking it could keep their connections
+    /// from ever finishing, e.g. on a `current_thread` runtime.
+    pub(crate) fn shutdown(&self, timeout: Duration) -> OTelSdkResult {
+        self.signal.send_replace(true);
+        if tokio::runtime::Handle::try_current().is_ok() {
+            return Ok(());
+        }
+        let (_running, result) = self
+            .stopped
+            .wait_timeout_while(lock(&self.running), timeout, |running| *running > 0)
+            .unwrap_or_else(PoisonError::into_inner);
+        if result.timed_out() {
+            return Err(OTelSdkError::Timeout(timeout));
+        }
+        Ok(())
+    }
+}
+
+/// Marks a server as running until dropped.
+struct Running(Arc<Shutdown>);
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        *lock(&self.0.running) -= 1;
+        self.0.stopped.notify_all();
+    }
+}
+
+/// Locks `mutex`, ignoring panics of previous holders since the guarded counter stays consistent.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// The parts of a request the server cares about.
+#[derive(Debug)]
+struct Request {
+    method: String,
+    target: String,
+    /// the values of all `Accept` headers, joined by commas
+    accept: String,
+    accepts_gzip: bool,
+    /// whether the connection is to be closed after the response
+    close: bool,
+}
+
+#[derive(Debug)]
+enum RequestError {
+    /// the connection failed
+    Io,
+    TooLarge,
+    Malformed,
+}
+
+/// Reads the next request from `stream`, `None` if the client closed the connection.
+///
+/// Request bodies are not supported, requests announcing one close the connection after their response.
+async fn read_request(
+    stream: &mut TcpStream,
+    buffer: &mut Vec<u8>,
+) -> Result<Option<Request>, RequestError> {
+    loop {
+        if let Some(end) = buffer.windows(4).position(|window| window == b"\r\n\r\n") {
+            let request = parse_request(&buffer[..end]);
+            buffer.drain(..end + 4);
+            return request.map(Some);
+        }
+        if buffer.len() > MAX_HEAD_SIZE {
+            return Err(RequestError::TooLarge);
+        }
+        let mut chunk = [0; 1024];
+        let read = stream
+            .read(&mut chunk)
+            .await
+            .map_err(|_| RequestError::Io)?;
+        if read == 0 {
+            return if buffer.is_empty() {
+                Ok(None)
+            } else {
+                Err(RequestError::Malformed)
+            };
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+    }
+}
+
+fn parse_request(head: &[u8]) -> Result<Request, RequestError> {
+    let head = std::str::from_utf8(head).map_err(|_| RequestError::Malformed)?;
+    let mut lines = head.split("\r\n");
+    let mut request_line = lines.next().unwrap_or_default().split(' ');
+    let (Some(method), Some(target), Some(version), None) = (
+        request_line.next(),
+        request_line.next(),
+        request_line.next(),
+        request_line.next(),
+    ) else {
+        return Err(RequestError::Malformed);
+    };
+    let mut request = Request {
+        method: method.to_owned(),
+        target: target.to_owned(),
+        accept: String::new(),
+        accepts_gzip: false,
+        close: match version {
+            "HTTP/1.1" => false,
+            "HTTP/1.0" => true,
+            _ => return Err(RequestError::Malformed),
+        },
+    };
+    for line in lines {
+        let (name, value) = line.split_once(':').ok_or(RequestError::Malformed)?;
+        let value = value.trim();
+        if name.eq_ignore_ascii_case("accept") {
+            if !request.accept.is_empty() {
+                request.accept.push(',');
+            }
+            request.accept.push_str(value);
+        } else if name.eq_ignore_ascii_case("accept-encoding") {
+            request.accepts_gzip |= accepts_gzip(value);
+        } else if name.eq_ignore_ascii_case("connection") {
+            request.close = value
+                .split(',')
+                .any(|option| option.trim().eq_ignore_ascii_case("close"));
+        } else if name.eq_ignore_ascii_case("content-length") && value != "0"
+            || name.eq_ignore_ascii_case("transfer-encoding")
+        {
+            request.close = true;
+        }
+    }
+    Ok(request)
+}
+
+#[derive(Debug)]
+struct Response {
+    status: &'static str,
+    headers: Vec<(&'static str, Cow<'static, str>)>,
+    body: Vec<u8>,
+    /// whether only the head is sent
+    head: bool,
+    close: bool,
+}
+
+impl Response {
+    fn new(
+        status: &'static str,
+        content_type: impl Into<Cow<'static, str>>,
+        body: impl Into<Vec<u8>>,
+    ) -> Self {
+        Self {
+            status,
+            headers: vec![("Content-Type", content_type.into())],
+            body: body.into(),
+            head: false,
+            close: false,
+        }
+    }
+
+    fn text(status: &'static str, body: &str) -> Self {
+        Self::new(status, "text/plain; charset=utf-8", body)
+    }
+
+    fn with_header(mut self, name: &'static str, value: &'static str) -> Self {
+        self.headers.push((name, value.into()));
+        self
+    }
+
+    fn closing(mut self) -> Self {
+        self.close = true;
+        self
+    }
+
+    fn gzipped(mut self) -> Self {
+        if let Some(body) = gzip(&self.body) {
+            self.body = body;
+            self.headers.push(("Content-Encoding", "gzip".into()));
+        }
+        self.headers.push(("Vary", "Accept-Encoding".into()));
+        self
+    }
+
+    fn encode(&self, close: bool) -> Vec<u8> {
+        let mut response = format!(
+            "HTTP/1.1 {}\r\nContent-Length: {}\r\n",
+            self.status,
+            self.body.len()
+        );
+        for (name, value) in &self.headers {
+            response.push_str(&format!("{name}: {value}\r\n"));
+        }
+        if close {
+            response.push_str("Connection: close\r\n");
+        }
+        response.push_str("\r\n");
+        let mut response = response.into_bytes();
+        if !self.head {
+            response.extend_from_slice(&self.body);
+        }
+        response
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_request() {
+        let request = parse_request(
+            b"GET /metrics?x=1 HTTP/1.1\r\nHost: localhost\r\nAccept-Encoding: deflate, GZIP;q=0.5\r\n\
+              Accept: text/plain;q=0.5\r\naccept: application/openmetrics-text",
+        )
+        .unwrap();
+        assert_eq!(request.method, "GET");
+        assert_eq!(request.target, "/metrics?x=1");
+        assert_eq!(
+            request.accept,
+            "text/plain;q=0.5,application/openmetrics-text"
+        );
+        assert!(request.accepts_gzip);
+        assert!(!request.close);
+
+        let request = parse_request(b"HEAD / HTTP/1.0").unwrap();
+        assert!(request.close);
+        let request = parse_request(b"GET / HTTP/1.1\r\nConnection: keep-alive, Close").unwrap();
+        assert!(request.close);
+
+        assert!(matches!(
+            parse_request(b"GET /metrics"),
+            Err(RequestError::Malformed)
+        ));
+        assert!(matches!(
+            parse_request(b"GET / HTTP/1.1\r\nno header"),
+            Err(RequestError::Malformed)
+        ));
+    }
+}
@@ -0,0 +1,110 @@
+//! A [`tower_service::Service`] answering HTTP requests with the metrics of an
+//! [`OpenMetricsExporter`](crate::exporter::OpenMetricsExporter).
+
+use std::convert::Infallible;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use bytes::Bytes;
+use http::header::{ACCEPT, ACCEPT_ENCODING, CACHE_CONTROL, CONTENT_ENCODING, CONTENT_TYPE, VARY};
+use http::{HeaderMap, HeaderValue, Request, Response, StatusCode};
+use http_body_util::Full;
+
+use crate::exporter::{OpenMetricsExporter, not_acceptable};
+use crate::gzip::{accepts_gzip, gzip};
+
+/// A [`tower_service::Service`] responding to any request with the metrics of an [`OpenMetricsExporter`].
+///
+/// The metrics are written in the format negotiated from the `Accept` header among the OpenMetrics and Prometheus
+/// text formats, the OpenMetrics 2.0 draft if native histograms are enabled and the protobuf format with the
+/// `protobuf` feature. Without an `Accept` header, or with a wildcard, the text format of the exporter's options is
+/// chosen. The response is gzip-compressed if the `Accept-Encoding` header allows it and is never cached. Requests
+/// accepting none of the formats are answered with `406 Not Acceptable`.
+///
+/// Names are escaped as the `escaping` parameter of the chosen media range asks for, see
+/// [`NameEscaping`](crate::convert::NameEscaping), or else as the exporter's options say. The `Content-Type` of the
+/// response names the escaping used.
+///
+/// Only the text format and escaping of the exporter's options are written on every export, the others from the
+/// first export after a request asked for them. Until then, such requests get another acceptable format or
+/// escaping.
+///
+/// It can be mounted in axum with `Router::route_service`, or used as a handler through [`respond`](Self::respond):
+///
+/// ```
+/// use axum::routing::get;
+/// use http::HeaderMap;
+/// use ottotom::exporter::OpenMetricsExporter;
+/// use ottotom::service::MetricsService;
+///
+/// let service = MetricsService::new(OpenMetricsExporter::default());
+/// let router: axum::Router = axum::Router::new()
+///     .route_service("/metrics", service.clone())
+///     .route(
+///         "/other/metrics",
+///         get(move |headers: HeaderMap| async move { service.respond(&headers).await }),
+///     );
+/// ```
+#[derive(Debug, Clone)]
+pub struct MetricsService {
+    exporter: OpenMetricsExporter,
+}
+
+impl MetricsService {
+    /// Creates a service serving the metrics of `exporter`.
+    pub fn new(exporter: OpenMetricsExporter) -> Self {
+        Self { exporter }
+    }
+
+    /// Builds the response to a request with the `headers`.
+    pub async fn respond(&self, headers: &HeaderMap) -> Response<Full<Bytes>> {
+        let accept = headers
+            .get_all(ACCEPT)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .collect::<Vec<_>>()
+            .join(",");
+        let Some((format, escaping, rendered)) = self.exporter.negotiate(&accept).await else {
+            return Response::builder()
+                .status(StatusCode::NOT_ACCEPTABLE)
+                .header(CONTENT_TYPE, "text/plain; charset=utf-8")
+                .header(VARY, "Accept")
+                .body(Full::from(not_acceptable(&self.exporter.formats())))
+                .unwrap_or_default();
+        };
+
+        let mut body = Bytes::from(rendered);
+        let mut response = Response::builder()
+            .header(CONTENT_TYPE, format.content_type_with_escaping(escaping))
+            .header(CACHE_CONTROL, "no-store")
+            .header(VARY, "Accept, Accept-Encoding");
+        let gzip_accepted = headers
+            .get_all(ACCEPT_ENCODING)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .any(accepts_gzip);
+        if gzip_accepted && let Some(compressed) = gzip(&body) {
+            body = compressed.into();
+            response = response.header(CONTENT_ENCODING, HeaderValue::from_static("gzip"));
+        }
+        // All headers are valid, so building does not fail
+        response.body(Full::new(body)).unwrap_or_default()
+    }
+}
+
+impl<B> tower_service::Service<Request<B>> for MetricsService {
+    type Response = Response<Full<Bytes>>;
+    type Error = Infallible;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Infallible>> + Send>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: Request<B>) -> Self::Future {
+        let service = self.clone();
+        let (parts, _) = request.into_parts();
+        Box::pin(async move { Ok(service.respond(&parts.headers).await) })
+    }
+}
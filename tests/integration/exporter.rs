@@ -1,5 +1,6 @@
 use opentelemetry::metrics::MeterProvider;
-use opentelemetry_sdk::metrics::SdkMeterProvider;
+use opentelemetry_sdk::metrics::{SdkMeterProvider, Temporality};
+use ottotom::convert::{ConvertOptions, Timestamps};
 use ottotom::exporter::OpenMetricsExporter;
 
 #[test]
@@ -22,3 +23,94 @@ fn exporter_exports() {
     let metrics_text = rt.block_on(exporter.text());
     assert!(metrics_text.contains("# TYPE a_gauge"));
 }
+
+#[test]
+fn exporter_accumulates_delta_temporality() {
+    let exporter = OpenMetricsExporter::default().with_temporality(Temporality::Delta);
+    let meter_provider = SdkMeterProvider::builder()
+        .with_periodic_exporter(exporter.clone())
+        .build();
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+
+    let meter = meter_provider.meter("meter.one");
+    let counter = meter.u64_counter("a_counter").build();
+    counter.add(2, &[]);
+    meter_provider.force_flush().unwrap();
+    counter.add(3, &[]);
+    meter_provider.force_flush().unwrap();
+
+    let metrics_text = rt.block_on(exporter.text());
+    assert!(metrics_text.contains("# TYPE a_counter counter"));
+    let total = metrics_text
+        .lines()
+        .find(|line| line.starts_with("a_counter_total"))
+        .and_then(|line| line.split(' ').nth(1));
+    assert_eq!(total, Some("5"));
+}
+
+#[test]
+fn exporter_builder_sets_options() {
+    let options = ConvertOptions::default()
+        .with_scope_info(false)
+        .with_scope_labels(false)
+        .with_timestamps(Timestamps::Omit);
+    let exporter = OpenMetricsExporter::builder().with_options(options).build();
+    let meter_provider = SdkMeterProvider::builder()
+        .with_periodic_exporter(exporter.clone())
+        .build();
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+
+    let meter = meter_provider.meter("meter.one");
+    meter.f64_gauge("a_gauge").build().record(42.0, &[]);
+    meter_provider.force_flush().unwrap();
+
+    let metrics_text = rt.block_on(exporter.text());
+    assert_eq!(metrics_text, "# TYPE a_gauge gauge\na_gauge{} 42\n# EOF\n");
+}
+
+#[test]
+fn exporter_skips_metrics_with_diagnostics() {
+    let exporter = OpenMetricsExporter::default();
+    let meter_provider = SdkMeterProvider::builder()
+        .with_periodic_exporter(exporter.clone())
+        .build();
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+
+    meter_provider
+        .meter("meter.one")
+        .u64_counter("requests")
+        .build()
+        .add(1, &[]);
+    meter_provider
+        .meter("meter.two")
+        .f64_gauge("requests")
+        .build()
+        .record(42.0, &[]);
+    meter_provider.force_flush().unwrap();
+
+    let metrics_text = rt.block_on(exporter.text());
+    assert!(metrics_text.contains("# TYPE requests counter"));
+    let diagnostics = rt.block_on(exporter.diagnostics());
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].scope(), Some("meter.two"));
+
+    // A strict exporter fails the export instead
+    let options = ConvertOptions::default().with_lenient(false);
+    let exporter = OpenMetricsExporter::default().with_options(options);
+    let meter_provider = SdkMeterProvider::builder()
+        .with_periodic_exporter(exporter.clone())
+        .build();
+    meter_provider
+        .meter("meter.one")
+        .f64_counter("requests")
+        .build()
+        .add(-1.0, &[]);
+    assert!(meter_provider.force_flush().is_err());
+    assert_eq!(rt.block_on(exporter.text()), "");
+}
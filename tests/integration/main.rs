@@ -1,6 +1,11 @@
 #[cfg(feature = "exporter")]
 mod exporter;
 mod parsing;
-#[cfg(feature = "otel_scope_info")]
-// Changes attributes
+mod properties;
+#[cfg(feature = "reader")]
+mod reader;
+#[cfg(feature = "server")]
+mod server;
+#[cfg(feature = "tower")]
+mod service;
 mod snapshot;
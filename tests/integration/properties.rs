@@ -0,0 +1,104 @@
+use opentelemetry_sdk::metrics::data::ResourceMetrics;
+use ottotom::Error;
+use ottotom::convert::{
+    ConvertOptions, DeltaAccumulator, ExponentialBuckets, Format, NameEscaping, TextFormat,
+    Timestamps, WriteFormat,
+};
+use ottotom_testsupport::arbitrary_metrics::{arb_metrics_spec, arb_value};
+use proptest::collection::vec;
+use proptest::prelude::*;
+
+fn arb_options() -> impl Strategy<Value = ConvertOptions> {
+    let timestamps = prop_oneof![
+        Just(Timestamps::Native),
+        Just(Timestamps::Omit),
+        Just(Timestamps::Seconds),
+        Just(Timestamps::Milliseconds),
+    ];
+    let name_escaping = prop_oneof![
+        Just(NameEscaping::Underscores),
+        Just(NameEscaping::AllowUtf8),
+        Just(NameEscaping::Dots),
+        Just(NameEscaping::Values),
+    ];
+    let exponential_buckets = prop_oneof![
+        (0..50usize).prop_map(|max_buckets| ExponentialBuckets::Scale { max_buckets }),
+        vec(arb_value(), 0..6).prop_map(ExponentialBuckets::Bounds),
+    ];
+    let promoted = vec(prop_oneof!["[a-z]{1,3}\\*?", Just("*".to_owned())], 0..3);
+    (
+        (timestamps, name_escaping, exponential_buckets),
+        any::<[bool; 8]>(),
+        promoted,
+    )
+        .prop_map(
+            |((timestamps, name_escaping, buckets), switches, promoted)| {
+                ConvertOptions::default()
+                    .with_timestamps(timestamps)
+                    .with_name_escaping(name_escaping)
+                    .with_exponential_buckets(buckets)
+                    .with_native_histograms(switches[0])
+                    .with_start_timestamps(switches[1])
+                    .with_scope_info(switches[2])
+                    .with_scope_labels(switches[3])
+                    .with_histogram_min_max(switches[4])
+                    .with_exemplars(switches[5])
+                    .with_unit_suffixes(switches[6])
+                    .with_type_suffixes(switches[7])
+                    .with_promoted_resource_attributes(promoted)
+            },
+        )
+}
+
+/// Checks the result of a conversion into memory, which can only fail on metrics which cannot be converted.
+fn check_result(result: Result<Vec<Error>, Error>, lenient: bool) -> Result<(), TestCaseError> {
+    match result {
+        Ok(diagnostics) => prop_assert!(lenient || diagnostics.is_empty()),
+        Err(err) => {
+            prop_assert!(!lenient, "lenient conversion failed: {err}");
+            prop_assert!(!matches!(err, Error::Write { .. }), "{err}");
+            prop_assert!(err.metric().is_some());
+        }
+    }
+    Ok(())
+}
+
+/// Converts `metrics` into each format.
+fn convert(
+    metrics: &impl WriteFormat,
+    options: &ConvertOptions,
+    lenient: bool,
+) -> Result<(), TestCaseError> {
+    let formats = [
+        Format::Text(TextFormat::OpenMetrics),
+        Format::Text(TextFormat::Prometheus),
+        Format::Text(TextFormat::OpenMetrics2),
+        #[cfg(feature = "protobuf")]
+        Format::Protobuf,
+    ];
+    for format in formats {
+        let mut buf = Vec::new();
+        check_result(metrics.write_as_format(format, &mut buf, options), lenient)?;
+    }
+    Ok(())
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(128))]
+
+    /// Arbitrary metrics are converted or rejected with an error, but never panic.
+    #[test]
+    fn conversion_never_panics(
+        spec in arb_metrics_spec(),
+        options in arb_options(),
+        lenient in any::<bool>(),
+    ) {
+        let options = options.with_lenient(lenient);
+        let mut accumulator = DeltaAccumulator::default();
+        for metrics in spec.collect() {
+            convert(&metrics, &options, lenient)?;
+            convert(&accumulator.accumulate(&metrics), &options, lenient)?;
+        }
+        convert(&ResourceMetrics::default(), &options, lenient)?;
+    }
+}
@@ -0,0 +1,154 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use opentelemetry::metrics::MeterProvider;
+use opentelemetry_sdk::error::OTelSdkError;
+use opentelemetry_sdk::metrics::SdkMeterProvider;
+use ottotom::reader::OpenMetricsReader;
+
+fn runtime() -> tokio::runtime::Runtime {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_time()
+        .build()
+        .unwrap()
+}
+
+/// Registers an observable counter which counts how often the metrics are collected.
+fn count_collections(meter_provider: &SdkMeterProvider) -> Arc<AtomicU64> {
+    let collections = Arc::new(AtomicU64::new(0));
+    let counter = collections.clone();
+    meter_provider
+        .meter("meter.one")
+        .u64_observable_counter("collections")
+        .with_callback(move |observer| {
+            observer.observe(counter.fetch_add(1, Ordering::SeqCst) + 1, &[])
+        })
+        .build();
+    collections
+}
+
+#[test]
+fn reader_collects_on_text() {
+    let reader = OpenMetricsReader::default();
+    let meter_provider = SdkMeterProvider::builder()
+        .with_reader(reader.clone())
+        .build();
+    let rt = runtime();
+
+    let gauge = meter_provider
+        .meter("meter.one")
+        .f64_gauge("a_gauge")
+        .build();
+    gauge.record(42.0, &[]);
+    let metrics_text = rt.block_on(reader.text()).unwrap();
+    assert!(metrics_text.contains("# TYPE a_gauge gauge"));
+    assert!(metrics_text.contains("} 42 "));
+
+    gauge.record(43.0, &[]);
+    let metrics_text = rt.block_on(reader.text()).unwrap();
+    assert!(metrics_text.contains("} 43 "));
+}
+
+#[test]
+fn reader_skips_metrics_with_diagnostics() {
+    let reader = OpenMetricsReader::default();
+    let meter_provider = SdkMeterProvider::builder()
+        .with_reader(reader.clone())
+        .build();
+    let rt = runtime();
+
+    meter_provider
+        .meter("meter.one")
+        .f64_counter("requests")
+        .build()
+        .add(-1.0, &[]);
+    meter_provider
+        .meter("meter.one")
+        .f64_gauge("a_gauge")
+        .build()
+        .record(42.0, &[]);
+    let metrics_text = rt.block_on(reader.text()).unwrap();
+    assert!(metrics_text.contains("# TYPE a_gauge gauge"));
+    assert!(!metrics_text.contains("requests"));
+    let diagnostics = reader.diagnostics();
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].metric(), Some("requests"));
+}
+
+#[test]
+fn reader_coalesces_concurrent_requests() {
+    let reader = OpenMetricsReader::default();
+    let meter_provider = SdkMeterProvider::builder()
+        .with_reader(reader.clone())
+        .build();
+    let collections = count_collections(&meter_provider);
+    let rt = runtime();
+
+    let (first, second) = rt.block_on(async {
+        let first = tokio::spawn({
+            let reader = reader.clone();
+            async move { reader.text().await }
+        });
+        let second = tokio::spawn({
+            let reader = reader.clone();
+            async move { reader.text().await }
+        });
+        (first.await.unwrap(), second.await.unwrap())
+    });
+    assert_eq!(first.unwrap(), second.unwrap());
+    assert_eq!(collections.load(Ordering::SeqCst), 1);
+
+    rt.block_on(reader.text()).unwrap();
+    assert_eq!(collections.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn reader_times_out() {
+    let reader = OpenMetricsReader::default().with_timeout(Duration::from_millis(10));
+    let meter_provider = SdkMeterProvider::builder()
+        .with_reader(reader.clone())
+        .build();
+    let _slow = meter_provider
+        .meter("meter.one")
+        .u64_observable_gauge("slow")
+        .with_callback(|observer| {
+            std::thread::sleep(Duration::from_millis(200));
+            observer.observe(1, &[]);
+        })
+        .build();
+    let rt = runtime();
+
+    let result = rt.block_on(reader.text());
+    assert!(matches!(result, Err(OTelSdkError::Timeout(_))));
+}
+
+#[test]
+fn reader_does_not_pile_up_timed_out_collections() {
+    let reader = OpenMetricsReader::default().with_timeout(Duration::from_millis(10));
+    let meter_provider = SdkMeterProvider::builder()
+        .with_reader(reader.clone())
+        .build();
+    let collections = Arc::new(AtomicU64::new(0));
+    let counter = collections.clone();
+    let _slow = meter_provider
+        .meter("meter.one")
+        .u64_observable_gauge("slow")
+        .with_callback(move |observer| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            std::thread::sleep(Duration::from_millis(200));
+            observer.observe(1, &[]);
+        })
+        .build();
+    let rt = runtime();
+
+    let result = rt.block_on(reader.text());
+    assert!(matches!(result, Err(OTelSdkError::Timeout(_))));
+    // The timed out collection is still running, so the next request waits for it instead of starting another one
+    let result = rt.block_on(reader.text());
+    assert!(matches!(result, Err(OTelSdkError::Timeout(_))));
+    std::thread::sleep(Duration::from_millis(300));
+    let text = rt.block_on(reader.text()).unwrap();
+    assert!(text.contains("# TYPE slow gauge"));
+    assert_eq!(collections.load(Ordering::SeqCst), 1);
+}
@@ -0,0 +1,229 @@
+use std::io::Read;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+use opentelemetry::metrics::MeterProvider;
+use opentelemetry_sdk::metrics::SdkMeterProvider;
+use opentelemetry_sdk::metrics::exporter::PushMetricExporter;
+use ottotom::exporter::OpenMetricsExporter;
+use ottotom::server::{MetricsServer, ServerHandle};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+fn runtime() -> tokio::runtime::Runtime {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_io()
+        .build()
+        .unwrap()
+}
+
+async fn bind(exporter: &OpenMetricsExporter) -> ServerHandle {
+    MetricsServer::new(exporter.clone())
+        .bind(SocketAddr::from(([127, 0, 0, 1], 0)))
+        .await
+        .unwrap()
+}
+
+/// Sends `request` on a new connection and returns the head and body of the response.
+async fn send(addr: SocketAddr, request: &str) -> (String, Vec<u8>) {
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream.write_all(request.as_bytes()).await.unwrap();
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await.unwrap();
+    let end = response
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .unwrap();
+    let body = response.split_off(end + 4);
+    (String::from_utf8(response).unwrap(), body)
+}
+
+#[test]
+fn server_serves_metrics() {
+    let exporter = OpenMetricsExporter::default();
+    let meter_provider = SdkMeterProvider::builder()
+        .with_periodic_exporter(exporter.clone())
+        .build();
+    let gauge = meter_provider
+        .meter("meter.one")
+        .f64_gauge("a_gauge")
+        .build();
+    gauge.record(42.0, &[]);
+    meter_provider.force_flush().unwrap();
+
+    let rt = runtime();
+    rt.block_on(async {
+        let addr = bind(&exporter).await.local_addr();
+
+        let (head, body) = send(addr, "GET /metrics HTTP/1.1\r\nConnection: close\r\n\r\n").await;
+        assert!(head.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(head.contains(&format!(
+            "Content-Type: {}; escaping=underscores\r\n",
+            ottotom::convert::MIME_TYPE
+        )));
+        assert!(head.contains("Cache-Control: no-store\r\n"));
+        let text = String::from_utf8(body).unwrap();
+        assert!(text.contains("# TYPE a_gauge gauge"));
+        assert!(head.contains(&format!("Content-Length: {}\r\n", text.len())));
+
+        let (head, body) = send(addr, "HEAD /metrics HTTP/1.0\r\n\r\n").await;
+        assert!(head.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(head.contains(&format!("Content-Length: {}\r\n", text.len())));
+        assert!(body.is_empty());
+
+        let (head, body) = send(
+            addr,
+            "GET /metrics HTTP/1.1\r\nAccept-Encoding: gzip\r\nConnection: close\r\n\r\n",
+        )
+        .await;
+        assert!(head.contains("Content-Encoding: gzip\r\n"));
+        let mut decompressed = String::new();
+        flate2::read::GzDecoder::new(body.as_slice())
+            .read_to_string(&mut decompressed)
+            .unwrap();
+        assert_eq!(decompressed, text);
+    });
+}
+
+#[test]
+fn server_routes() {
+    let exporter = OpenMetricsExporter::default();
+    let rt = runtime();
+    rt.block_on(async {
+        let addr = bind(&exporter).await.local_addr();
+
+        let (head, body) = send(addr, "GET /health HTTP/1.0\r\n\r\n").await;
+        assert!(head.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert_eq!(body, b"OK\n");
+
+        let (head, _) = send(addr, "GET /other HTTP/1.0\r\n\r\n").await;
+        assert!(head.starts_with("HTTP/1.1 404 Not Found\r\n"));
+
+        let (head, _) = send(addr, "DELETE /metrics HTTP/1.0\r\n\r\n").await;
+        assert!(head.starts_with("HTTP/1.1 405 Method Not Allowed\r\n"));
+        assert!(head.contains("Allow: GET, HEAD\r\n"));
+
+        let (head, _) = send(addr, "garbage\r\n\r\n").await;
+        assert!(head.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+    });
+}
+
+#[test]
+fn server_keeps_connections_alive() {
+    let exporter = OpenMetricsExporter::default();
+    let rt = runtime();
+    rt.block_on(async {
+        let addr = bind(&exporter).await.local_addr();
+        let request =
+            "GET /health HTTP/1.1\r\n\r\nGET /health HTTP/1.1\r\nConnection: close\r\n\r\n";
+        let (_, body) = send(addr, request).await;
+        let responses = String::from_utf8(body).unwrap();
+        assert!(responses.starts_with("OK\nHTTP/1.1 200 OK\r\n"));
+        assert!(responses.ends_with("Connection: close\r\n\r\nOK\n"));
+    });
+}
+
+#[test]
+fn server_stops_on_shutdown() {
+    let exporter = OpenMetricsExporter::default();
+    let rt = runtime();
+    rt.block_on(async {
+        let server = bind(&exporter).await;
+        let addr = server.local_addr();
+        let (head, _) = send(addr, "GET /health HTTP/1.0\r\n\r\n").await;
+        assert!(head.starts_with("HTTP/1.1 200 OK\r\n"));
+
+        // An idle connection does not hold up the shutdown
+        let _idle = TcpStream::connect(addr).await.unwrap();
+        let shutdown = tokio::task::spawn_blocking({
+            let exporter = exporter.clone();
+            move || exporter.shutdown_with_timeout(Duration::from_secs(5))
+        });
+        server.stopped().await;
+        shutdown.await.unwrap().unwrap();
+        assert!(TcpStream::connect(addr).await.is_err());
+    });
+}
+
+#[test]
+fn server_shutdown_does_not_block_the_runtime() {
+    let exporter = OpenMetricsExporter::default();
+    let rt = runtime();
+    rt.block_on(async {
+        let server = bind(&exporter).await;
+        let addr = server.local_addr();
+        let _idle = TcpStream::connect(addr).await.unwrap();
+
+        // Blocking here would keep the connections on this thread from finishing until the timeout
+        let started = Instant::now();
+        exporter
+            .shutdown_with_timeout(Duration::from_secs(5))
+            .unwrap();
+        assert!(started.elapsed() < Duration::from_secs(1));
+        server.stopped().await;
+        assert!(TcpStream::connect(addr).await.is_err());
+    });
+}
+
+#[test]
+fn server_negotiates_format() {
+    let options = ottotom::convert::ConvertOptions::default()
+        .with_text_format(ottotom::convert::TextFormat::Prometheus);
+    let exporter = OpenMetricsExporter::default().with_options(options);
+    let meter_provider = SdkMeterProvider::builder()
+        .with_periodic_exporter(exporter.clone())
+        .build();
+    meter_provider
+        .meter("meter.one")
+        .u64_counter("requests")
+        .build()
+        .add(1, &[]);
+    meter_provider.force_flush().unwrap();
+
+    let rt = runtime();
+    rt.block_on(async {
+        let addr = bind(&exporter).await.local_addr();
+
+        let (head, body) = send(addr, "GET /metrics HTTP/1.0\r\n\r\n").await;
+        assert!(head.contains(&format!(
+            "Content-Type: {}; escaping=underscores\r\n",
+            ottotom::convert::PROMETHEUS_MIME_TYPE
+        )));
+        assert!(head.contains("Vary: Accept\r\n"));
+        assert_eq!(String::from_utf8(body).unwrap(), exporter.text().await);
+
+        // Other formats are written from the export after they were asked for
+        let request =
+            "GET /metrics HTTP/1.0\r\nAccept: application/openmetrics-text;version=1.0.0\r\n\r\n";
+        let (head, body) = send(addr, request).await;
+        assert!(head.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(body.is_empty());
+        meter_provider.force_flush().unwrap();
+        let (head, body) = send(addr, request).await;
+        assert!(head.contains(&format!(
+            "Content-Type: {}; escaping=underscores\r\n",
+            ottotom::convert::MIME_TYPE
+        )));
+        let text = String::from_utf8(body).unwrap();
+        assert!(text.contains("requests_total{"));
+        assert!(text.ends_with("# EOF\n"));
+
+        // The name escaping is negotiated as well
+        let request = "GET /metrics HTTP/1.0\r\n\
+                       Accept: application/openmetrics-text;version=1.0.0;escaping=allow-utf-8\r\n\r\n";
+        send(addr, request).await;
+        meter_provider.force_flush().unwrap();
+        let (head, _) = send(addr, request).await;
+        assert!(head.contains(&format!(
+            "Content-Type: {}; escaping=allow-utf-8\r\n",
+            ottotom::convert::MIME_TYPE
+        )));
+
+        let (head, _) = send(
+            addr,
+            "GET /metrics HTTP/1.0\r\nAccept: application/json\r\n\r\n",
+        )
+        .await;
+        assert!(head.starts_with("HTTP/1.1 406 Not Acceptable\r\n"));
+    });
+}
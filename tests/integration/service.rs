@@ -0,0 +1,332 @@
+use std::io::Read;
+
+use axum::body::Body;
+use axum::routing::get;
+use http::{HeaderMap, Request, StatusCode};
+use http_body_util::BodyExt;
+use opentelemetry::metrics::MeterProvider;
+use opentelemetry_sdk::metrics::SdkMeterProvider;
+use ottotom::convert::{
+    ConvertOptions, MIME_TYPE, OPENMETRICS_2_MIME_TYPE, PROMETHEUS_MIME_TYPE, TextFormat,
+};
+use ottotom::exporter::OpenMetricsExporter;
+use ottotom::service::MetricsService;
+use tower::ServiceExt;
+
+fn runtime() -> tokio::runtime::Runtime {
+    tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap()
+}
+
+/// An exporter with a single exported gauge.
+fn make_exporter(options: ConvertOptions) -> (OpenMetricsExporter, SdkMeterProvider) {
+    let exporter = OpenMetricsExporter::default().with_options(options);
+    let meter_provider = SdkMeterProvider::builder()
+        .with_periodic_exporter(exporter.clone())
+        .build();
+    meter_provider
+        .meter("meter.one")
+        .f64_gauge("a_gauge")
+        .build()
+        .record(42.0, &[]);
+    meter_provider.force_flush().unwrap();
+    (exporter, meter_provider)
+}
+
+/// The `Content-Type` of metrics in the format of `content_type` with names escaped by underscores.
+fn underscores(content_type: &str) -> String {
+    format!("{content_type}; escaping=underscores")
+}
+
+fn request(uri: &str, headers: &[(&str, &str)]) -> Request<Body> {
+    let mut request = Request::get(uri);
+    for (name, value) in headers {
+        request = request.header(*name, *value);
+    }
+    request.body(Body::empty()).unwrap()
+}
+
+#[test]
+fn service_responds_with_metrics() {
+    let (exporter, _meter_provider) = make_exporter(ConvertOptions::default());
+    let rt = runtime();
+    rt.block_on(async {
+        let service = MetricsService::new(exporter.clone());
+        let response = service
+            .clone()
+            .oneshot(request(
+                "/metrics",
+                &[("Accept", "application/openmetrics-text")],
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["Content-Type"], underscores(MIME_TYPE));
+        assert_eq!(response.headers()["Cache-Control"], "no-store");
+        assert!(response.headers().get("Content-Encoding").is_none());
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, exporter.text().await);
+
+        let response = service
+            .oneshot(request("/metrics", &[("Accept-Encoding", "gzip, br")]))
+            .await
+            .unwrap();
+        assert_eq!(response.headers()["Content-Encoding"], "gzip");
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let mut decompressed = String::new();
+        flate2::read::GzDecoder::new(&body[..])
+            .read_to_string(&mut decompressed)
+            .unwrap();
+        assert_eq!(decompressed, exporter.text().await);
+    });
+}
+
+#[test]
+fn service_negotiates_content_type() {
+    let options = ConvertOptions::default().with_text_format(TextFormat::Prometheus);
+    let (exporter, meter_provider) = make_exporter(options);
+    let rt = runtime();
+    rt.block_on(async {
+        let service = MetricsService::new(exporter.clone());
+        let response = service
+            .clone()
+            .oneshot(request(
+                "/metrics",
+                &[("Accept", "text/plain;version=0.0.4")],
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()["Content-Type"],
+            underscores(PROMETHEUS_MIME_TYPE)
+        );
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, exporter.text().await);
+
+        // Wildcards get the text format of the exporter
+        let response = service
+            .clone()
+            .oneshot(request("/metrics", &[("Accept", "*/*")]))
+            .await
+            .unwrap();
+        assert_eq!(
+            response.headers()["Content-Type"],
+            underscores(PROMETHEUS_MIME_TYPE)
+        );
+
+        // Other formats are written from the export after they were asked for, acceptable written ones are
+        // answered until then
+        let accept = "application/openmetrics-text;version=1.0.0,text/plain;q=0.5";
+        let response = service
+            .clone()
+            .oneshot(request("/metrics", &[("Accept", accept)]))
+            .await
+            .unwrap();
+        assert_eq!(
+            response.headers()["Content-Type"],
+            underscores(PROMETHEUS_MIME_TYPE)
+        );
+        meter_provider.force_flush().unwrap();
+        let response = service
+            .clone()
+            .oneshot(request("/metrics", &[("Accept", accept)]))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["Content-Type"], underscores(MIME_TYPE));
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert!(body.ends_with("# EOF\n"));
+        openmetrics_parser::openmetrics::parse_openmetrics(&body).unwrap();
+
+        #[cfg(feature = "protobuf")]
+        {
+            let accept = "application/vnd.google.protobuf;proto=io.prometheus.client.MetricFamily;\
+                          encoding=delimited";
+            let response = service
+                .clone()
+                .oneshot(request("/metrics", &[("Accept", accept)]))
+                .await
+                .unwrap();
+            // Nothing acceptable was written yet
+            assert_eq!(response.status(), StatusCode::OK);
+            let body = response.into_body().collect().await.unwrap().to_bytes();
+            assert!(body.is_empty());
+            meter_provider.force_flush().unwrap();
+            let response = service
+                .clone()
+                .oneshot(request("/metrics", &[("Accept", accept)]))
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(
+                response.headers()["Content-Type"],
+                underscores(ottotom::convert::PROTOBUF_MIME_TYPE)
+            );
+            let body = response.into_body().collect().await.unwrap().to_bytes();
+            assert!(!body.is_empty());
+        }
+
+        let response = service
+            .oneshot(request("/metrics", &[("Accept", "application/json")]))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_ACCEPTABLE);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert!(
+            String::from_utf8(body.to_vec())
+                .unwrap()
+                .contains(MIME_TYPE)
+        );
+    });
+}
+
+#[test]
+fn service_negotiates_name_escaping() {
+    let exporter = OpenMetricsExporter::default();
+    let meter_provider = SdkMeterProvider::builder()
+        .with_periodic_exporter(exporter.clone())
+        .build();
+    meter_provider
+        .meter("meter.one")
+        .f64_gauge("system.temperature")
+        .build()
+        .record(21.5, &[]);
+    meter_provider.force_flush().unwrap();
+    let rt = runtime();
+    rt.block_on(async {
+        let service = MetricsService::new(exporter);
+        let accept = "application/openmetrics-text;version=1.0.0;escaping=allow-utf-8";
+        // The escaping is written from the export after it was asked for, the one of the options until then
+        let response = service
+            .clone()
+            .oneshot(request("/metrics", &[("Accept", accept)]))
+            .await
+            .unwrap();
+        assert_eq!(response.headers()["Content-Type"], underscores(MIME_TYPE));
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert!(
+            String::from_utf8(body.to_vec())
+                .unwrap()
+                .contains("\nsystem_temperature{")
+        );
+
+        meter_provider.force_flush().unwrap();
+        let response = service
+            .clone()
+            .oneshot(request("/metrics", &[("Accept", accept)]))
+            .await
+            .unwrap();
+        assert_eq!(
+            response.headers()["Content-Type"],
+            format!("{MIME_TYPE}; escaping=allow-utf-8")
+        );
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert!(body.contains("\n{\"system.temperature\","));
+
+        // Requests without an escaping still get the one of the options
+        let response = service
+            .oneshot(request(
+                "/metrics",
+                &[("Accept", "application/openmetrics-text;version=1.0.0")],
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.headers()["Content-Type"], underscores(MIME_TYPE));
+    });
+}
+
+#[test]
+fn service_offers_native_histograms_separately() {
+    let options = ConvertOptions::default().with_native_histograms(true);
+    let exporter = OpenMetricsExporter::default().with_options(options);
+    let meter_provider = SdkMeterProvider::builder()
+        .with_periodic_exporter(exporter.clone())
+        .with_view(|_: &opentelemetry_sdk::metrics::Instrument| {
+            opentelemetry_sdk::metrics::Stream::builder()
+                .with_aggregation(
+                    opentelemetry_sdk::metrics::Aggregation::Base2ExponentialHistogram {
+                        max_size: 160,
+                        max_scale: 20,
+                        record_min_max: false,
+                    },
+                )
+                .build()
+                .ok()
+        })
+        .build();
+    meter_provider
+        .meter("meter.one")
+        .f64_histogram("latency")
+        .build()
+        .record(1.5, &[]);
+    meter_provider.force_flush().unwrap();
+    let rt = runtime();
+    rt.block_on(async {
+        let service = MetricsService::new(exporter);
+        let accept =
+            "application/openmetrics-text;version=2.0.0,application/openmetrics-text;q=0.5";
+        let response = service
+            .clone()
+            .oneshot(request("/metrics", &[("Accept", accept)]))
+            .await
+            .unwrap();
+        assert_eq!(response.headers()["Content-Type"], underscores(MIME_TYPE));
+        meter_provider.force_flush().unwrap();
+        let response = service
+            .clone()
+            .oneshot(request("/metrics", &[("Accept", accept)]))
+            .await
+            .unwrap();
+        assert_eq!(
+            response.headers()["Content-Type"],
+            underscores(OPENMETRICS_2_MIME_TYPE)
+        );
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert!(
+            String::from_utf8(body.to_vec())
+                .unwrap()
+                .contains("schema:")
+        );
+
+        // Scrapers of OpenMetrics 1.0 get converted buckets
+        let response = service
+            .oneshot(request(
+                "/metrics",
+                &[("Accept", "application/openmetrics-text")],
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.headers()["Content-Type"], underscores(MIME_TYPE));
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert!(!body.contains("schema:"));
+        assert!(body.contains("latency_bucket{"));
+    });
+}
+
+#[test]
+fn service_mounts_in_axum() {
+    let (exporter, _meter_provider) = make_exporter(ConvertOptions::default());
+    let rt = runtime();
+    rt.block_on(async {
+        let service = MetricsService::new(exporter.clone());
+        let router = axum::Router::new()
+            .route_service("/metrics", service.clone())
+            .route(
+                "/handler",
+                get(move |headers: HeaderMap| async move { service.respond(&headers).await }),
+            );
+
+        for uri in ["/metrics", "/handler"] {
+            let response = router.clone().oneshot(request(uri, &[])).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(response.headers()["Content-Type"], underscores(MIME_TYPE));
+            let body = response.into_body().collect().await.unwrap().to_bytes();
+            assert_eq!(body, exporter.text().await);
+        }
+    });
+}
@@ -1,23 +1,48 @@
 use std::time::SystemTime;
 
 use insta::assert_snapshot;
-use ottotom::convert::WriteOpenMetrics;
+use ottotom::convert::{ConvertOptions, TextFormat, Timestamps, WriteOpenMetrics};
 
 use ottotom_testsupport::resource_metrics::make_test_metrics;
 use ottotom_testsupport::timestamps::get_all_timestamps;
 
+/// The options of the snapshots, independent of the features which set their defaults.
+fn snapshot_options() -> ConvertOptions {
+    ConvertOptions::default()
+        .with_scope_info(true)
+        .with_scope_labels(true)
+        .with_histogram_min_max(false)
+}
+
 #[test]
 fn matches_snapshot() {
     let metrics = make_test_metrics();
     let erasable_timestamps = get_all_timestamps(&metrics);
-    let mut formatted = metrics.to_openmetrics_string().unwrap();
+    let mut formatted = String::new();
+    metrics
+        .write_as_openmetrics_with(&mut formatted, &snapshot_options())
+        .unwrap();
     for (i, ts) in erasable_timestamps.iter().enumerate().rev() {
         let ts = ts
             .duration_since(SystemTime::UNIX_EPOCH)
             .unwrap()
             .as_secs_f64()
             .to_string();
-        formatted = formatted.replace(&format!("{ts}"), &format!("<TIMESTAMP_{}>", i));
+        formatted = formatted.replace(&ts, &format!("<TIMESTAMP_{}>", i));
     }
     assert_snapshot!(formatted);
 }
+
+#[test]
+fn matches_prometheus_snapshot() {
+    let metrics = make_test_metrics();
+    // The Prometheus text format has no `_created` samples, so without timestamps nothing needs to be erased
+    let options = snapshot_options()
+        .with_text_format(TextFormat::Prometheus)
+        .with_timestamps(Timestamps::Omit);
+    let mut formatted = String::new();
+    metrics
+        .write_as_openmetrics_with(&mut formatted, &options)
+        .unwrap();
+    assert_snapshot!(formatted);
+}
@@ -0,0 +1,368 @@
+//! Generation of arbitrary metrics for property tests of the conversion.
+//!
+//! Metrics can only be created by recording them with the SDK, so the [`MetricsSpec`] generated by
+//! [`arb_metrics_spec`] describes the instruments and the measurements recorded with them, and is turned into
+//! [`ResourceMetrics`] by [`MetricsSpec::collect`].
+
+use opentelemetry::metrics::MeterProvider;
+use opentelemetry::{InstrumentationScope, KeyValue};
+use opentelemetry_sdk::Resource;
+use opentelemetry_sdk::metrics::data::ResourceMetrics;
+use opentelemetry_sdk::metrics::reader::MetricReader;
+use opentelemetry_sdk::metrics::{Aggregation, Instrument, SdkMeterProvider, Stream, Temporality};
+use proptest::collection::vec;
+use proptest::prelude::*;
+
+use crate::reader::TestMetricsReader;
+
+/// The kind of an instrument and the type of its measurements.
+#[derive(Debug, Clone)]
+pub enum InstrumentKind {
+    F64Counter,
+    U64Counter,
+    F64UpDownCounter,
+    I64UpDownCounter,
+    F64Gauge,
+    U64Gauge,
+    I64Gauge,
+    /// A histogram with the given bucket boundaries, or the default ones.
+    F64Histogram(Option<Vec<f64>>),
+    U64Histogram(Option<Vec<f64>>),
+    /// A histogram aggregated into a base 2 exponential histogram with at most `max_size` buckets.
+    F64ExponentialHistogram {
+        max_size: u32,
+        max_scale: i8,
+    },
+}
+
+/// An instrument and the measurements recorded with it, in rounds between which the metrics are collected.
+#[derive(Debug, Clone)]
+pub struct InstrumentSpec {
+    pub scope: String,
+    pub scope_version: Option<String>,
+    pub name: String,
+    pub unit: String,
+    pub description: String,
+    pub kind: InstrumentKind,
+    pub rounds: Vec<Vec<(f64, Vec<KeyValue>)>>,
+}
+
+/// Instruments of a meter provider with a resource, see [`arb_metrics_spec`].
+#[derive(Debug, Clone)]
+pub struct MetricsSpec {
+    pub temporality: Temporality,
+    pub resource: Vec<KeyValue>,
+    pub instruments: Vec<InstrumentSpec>,
+}
+
+impl MetricsSpec {
+    /// Records the measurements of all instruments, and collects the metrics after each round.
+    ///
+    /// Instruments with names the SDK rejects record nothing, as in production.
+    pub fn collect(&self) -> Vec<ResourceMetrics> {
+        let reader = TestMetricsReader::with_temporality(self.temporality);
+        let exponential: Vec<_> = self
+            .instruments
+            .iter()
+            .filter_map(|spec| match spec.kind {
+                InstrumentKind::F64ExponentialHistogram {
+                    max_size,
+                    max_scale,
+                } => Some((spec.scope.clone(), spec.name.clone(), max_size, max_scale)),
+                _ => None,
+            })
+            .collect();
+        let meter_provider = SdkMeterProvider::builder()
+            .with_reader(reader.clone())
+            .with_resource(
+                Resource::builder_empty()
+                    .with_attributes(self.resource.iter().cloned())
+                    .build(),
+            )
+            .with_view(move |instrument: &Instrument| {
+                let &(.., max_size, max_scale) = exponential.iter().find(|(scope, name, ..)| {
+                    scope == instrument.scope().name() && name == instrument.name()
+                })?;
+                Stream::builder()
+                    .with_aggregation(Aggregation::Base2ExponentialHistogram {
+                        max_size,
+                        max_scale,
+                        record_min_max: true,
+                    })
+                    .build()
+                    .ok()
+            })
+            .build();
+
+        let rounds = self
+            .instruments
+            .iter()
+            .map(|spec| spec.rounds.len())
+            .max()
+            .unwrap_or(0)
+            .max(1);
+        let recorders: Vec<_> = self
+            .instruments
+            .iter()
+            .map(|spec| Recorder::new(&meter_provider, spec))
+            .collect();
+        let mut collected = Vec::with_capacity(rounds);
+        for round in 0..rounds {
+            for (spec, recorder) in self.instruments.iter().zip(&recorders) {
+                for (value, attributes) in spec.rounds.get(round).into_iter().flatten() {
+                    recorder.record(*value, attributes);
+                }
+            }
+            let mut metrics = ResourceMetrics::default();
+            reader.collect(&mut metrics).unwrap();
+            collected.push(metrics);
+        }
+        collected
+    }
+}
+
+/// An instrument of any kind, recording `f64` measurements converted to its type.
+enum Recorder {
+    F64Counter(opentelemetry::metrics::Counter<f64>),
+    U64Counter(opentelemetry::metrics::Counter<u64>),
+    F64UpDownCounter(opentelemetry::metrics::UpDownCounter<f64>),
+    I64UpDownCounter(opentelemetry::metrics::UpDownCounter<i64>),
+    F64Gauge(opentelemetry::metrics::Gauge<f64>),
+    U64Gauge(opentelemetry::metrics::Gauge<u64>),
+    I64Gauge(opentelemetry::metrics::Gauge<i64>),
+    F64Histogram(opentelemetry::metrics::Histogram<f64>),
+    U64Histogram(opentelemetry::metrics::Histogram<u64>),
+}
+
+/// The bound of `u64` histogram measurements, whose sum the SDK adds up with overflow checks in debug builds, for the
+/// at most ten measurements of an instrument. Counters are not bounded, their totals wrap around within the SDK.
+const MAX_HISTOGRAM_INTEGER: u64 = u64::MAX / 16;
+
+impl Recorder {
+    fn new(meter_provider: &SdkMeterProvider, spec: &InstrumentSpec) -> Self {
+        let mut scope = InstrumentationScope::builder(spec.scope.clone());
+        if let Some(version) = &spec.scope_version {
+            scope = scope.with_version(version.clone());
+        }
+        let meter = meter_provider.meter_with_scope(scope.build());
+        let name = spec.name.clone();
+        let unit = spec.unit.clone();
+        let description = spec.description.clone();
+        match &spec.kind {
+            InstrumentKind::F64Counter => Recorder::F64Counter(
+                meter
+                    .f64_counter(name)
+                    .with_unit(unit)
+                    .with_description(description)
+                    .build(),
+            ),
+            InstrumentKind::U64Counter => Recorder::U64Counter(
+                meter
+                    .u64_counter(name)
+                    .with_unit(unit)
+                    .with_description(description)
+                    .build(),
+            ),
+            InstrumentKind::F64UpDownCounter => Recorder::F64UpDownCounter(
+                meter
+                    .f64_up_down_counter(name)
+                    .with_unit(unit)
+                    .with_description(description)
+                    .build(),
+            ),
+            InstrumentKind::I64UpDownCounter => Recorder::I64UpDownCounter(
+                meter
+                    .i64_up_down_counter(name)
+                    .with_unit(unit)
+                    .with_description(description)
+                    .build(),
+            ),
+            InstrumentKind::F64Gauge => Recorder::F64Gauge(
+                meter
+                    .f64_gauge(name)
+                    .with_unit(unit)
+                    .with_description(description)
+                    .build(),
+            ),
+            InstrumentKind::U64Gauge => Recorder::U64Gauge(
+                meter
+                    .u64_gauge(name)
+                    .with_unit(unit)
+                    .with_description(description)
+                    .build(),
+            ),
+            InstrumentKind::I64Gauge => Recorder::I64Gauge(
+                meter
+                    .i64_gauge(name)
+                    .with_unit(unit)
+                    .with_description(description)
+                    .build(),
+            ),
+            InstrumentKind::F64Histogram(boundaries) => {
+                let mut builder = meter
+                    .f64_histogram(name)
+                    .with_unit(unit)
+                    .with_description(description);
+                if let Some(boundaries) = boundaries {
+                    builder = builder.with_boundaries(boundaries.clone());
+                }
+                Recorder::F64Histogram(builder.build())
+            }
+            InstrumentKind::U64Histogram(boundaries) => {
+                let mut builder = meter
+                    .u64_histogram(name)
+                    .with_unit(unit)
+                    .with_description(description);
+                if let Some(boundaries) = boundaries {
+                    builder = builder.with_boundaries(boundaries.clone());
+                }
+                Recorder::U64Histogram(builder.build())
+            }
+            InstrumentKind::F64ExponentialHistogram { .. } => Recorder::F64Histogram(
+                meter
+                    .f64_histogram(name)
+                    .with_unit(unit)
+                    .with_description(description)
+                    .build(),
+            ),
+        }
+    }
+
+    fn record(&self, value: f64, attributes: &[KeyValue]) {
+        match self {
+            Recorder::F64Counter(counter) => counter.add(value, attributes),
+            Recorder::U64Counter(counter) => counter.add(value.abs() as u64, attributes),
+            Recorder::F64UpDownCounter(counter) => counter.add(value, attributes),
+            Recorder::I64UpDownCounter(counter) => counter.add(value as i64, attributes),
+            Recorder::F64Gauge(gauge) => gauge.record(value, attributes),
+            Recorder::U64Gauge(gauge) => gauge.record(value.abs() as u64, attributes),
+            Recorder::I64Gauge(gauge) => gauge.record(value as i64, attributes),
+            Recorder::F64Histogram(histogram) => histogram.record(value, attributes),
+            Recorder::U64Histogram(histogram) => {
+                histogram.record((value.abs() as u64).min(MAX_HISTOGRAM_INTEGER), attributes)
+            }
+        }
+    }
+}
+
+/// Names which are mostly accepted by the SDK, mixed with arbitrary strings.
+fn arb_name() -> impl Strategy<Value = String> {
+    prop_oneof![
+        4 => "[a-z][a-z0-9_.]{0,12}",
+        1 => "[A-Za-z][A-Za-z0-9_./-]{0,8}(_total|_seconds|_bytes)?",
+        1 => any::<String>(),
+    ]
+}
+
+/// Units known to the unit translation, mixed with arbitrary ones.
+fn arb_unit() -> impl Strategy<Value = String> {
+    prop_oneof![
+        Just(String::new()),
+        Just("s".to_owned()),
+        Just("ms".to_owned()),
+        Just("By".to_owned()),
+        Just("1".to_owned()),
+        Just("{request}".to_owned()),
+        Just("m/s".to_owned()),
+        "[ -~]{0,8}",
+    ]
+}
+
+/// Any `f64`, including infinities, NaN and subnormals, biased towards small values.
+pub fn arb_value() -> impl Strategy<Value = f64> {
+    prop_oneof![
+        4 => -1e3..1e3f64,
+        1 => any::<f64>(),
+        1 => prop_oneof![
+            Just(0.0),
+            Just(-0.0),
+            Just(f64::NAN),
+            Just(f64::INFINITY),
+            Just(f64::NEG_INFINITY),
+            Just(f64::MAX),
+            Just(f64::MIN_POSITIVE),
+        ],
+    ]
+}
+
+/// Attributes with colliding, reserved and arbitrary keys and values of any type.
+pub fn arb_attributes() -> impl Strategy<Value = Vec<KeyValue>> {
+    let key = prop_oneof![
+        "[a-z]{1,3}",
+        Just("otel_scope_name".to_owned()),
+        Just("le".to_owned()),
+        Just("trace_id".to_owned()),
+        any::<String>(),
+    ];
+    let attribute =
+        (key, any::<u8>(), any::<String>()).prop_map(|(key, kind, value)| match kind % 4 {
+            0 => KeyValue::new(key, value),
+            1 => KeyValue::new(key, i64::from(kind)),
+            2 => KeyValue::new(key, f64::from(kind) / 3.0),
+            _ => KeyValue::new(key, kind % 2 == 0),
+        });
+    vec(attribute, 0..3)
+}
+
+fn arb_boundaries() -> impl Strategy<Value = Option<Vec<f64>>> {
+    proptest::option::of(vec(arb_value(), 0..6))
+}
+
+fn arb_kind() -> impl Strategy<Value = InstrumentKind> {
+    prop_oneof![
+        Just(InstrumentKind::F64Counter),
+        Just(InstrumentKind::U64Counter),
+        Just(InstrumentKind::F64UpDownCounter),
+        Just(InstrumentKind::I64UpDownCounter),
+        Just(InstrumentKind::F64Gauge),
+        Just(InstrumentKind::U64Gauge),
+        Just(InstrumentKind::I64Gauge),
+        arb_boundaries().prop_map(InstrumentKind::F64Histogram),
+        arb_boundaries().prop_map(InstrumentKind::U64Histogram),
+        (1..40u32, -10..20i8).prop_map(|(max_size, max_scale)| {
+            InstrumentKind::F64ExponentialHistogram {
+                max_size,
+                max_scale,
+            }
+        }),
+    ]
+}
+
+fn arb_instrument() -> impl Strategy<Value = InstrumentSpec> {
+    let scope = prop_oneof!["meter\\.[a-c]", any::<String>()];
+    let scope_version = proptest::option::of("[0-9]\\.[0-9]");
+    let measurements = vec((arb_value(), arb_attributes()), 0..6);
+    (
+        scope,
+        scope_version,
+        arb_name(),
+        arb_unit(),
+        any::<String>(),
+        arb_kind(),
+        vec(measurements, 1..3),
+    )
+        .prop_map(
+            |(scope, scope_version, name, unit, description, kind, rounds)| InstrumentSpec {
+                scope,
+                scope_version,
+                name,
+                unit,
+                description,
+                kind,
+                rounds,
+            },
+        )
+}
+
+/// Generates a [`MetricsSpec`] with up to eight instruments of any kind, recording arbitrary values and attributes
+/// with cumulative or delta temporality.
+pub fn arb_metrics_spec() -> impl Strategy<Value = MetricsSpec> {
+    let temporality = prop_oneof![Just(Temporality::Cumulative), Just(Temporality::Delta)];
+    (temporality, arb_attributes(), vec(arb_instrument(), 0..8)).prop_map(
+        |(temporality, resource, instruments)| MetricsSpec {
+            temporality,
+            resource,
+            instruments,
+        },
+    )
+}
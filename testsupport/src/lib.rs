@@ -1,3 +1,4 @@
+pub mod arbitrary_metrics;
 pub mod metric_data;
 pub mod reader;
 pub mod resource_metrics;
@@ -12,8 +12,18 @@ pub struct TestMetricsReader {
 
 impl Default for TestMetricsReader {
     fn default() -> Self {
+        Self::with_temporality(Temporality::Cumulative)
+    }
+}
+
+impl TestMetricsReader {
+    pub fn with_temporality(temporality: Temporality) -> Self {
         Self {
-            inner: Arc::new(ManualReader::builder().build()),
+            inner: Arc::new(
+                ManualReader::builder()
+                    .with_temporality(temporality)
+                    .build(),
+            ),
         }
     }
 }
@@ -39,7 +49,7 @@ impl MetricReader for TestMetricsReader {
         self.inner.shutdown_with_timeout(timeout)
     }
 
-    fn temporality(&self, _kind: InstrumentKind) -> Temporality {
-        Temporality::Cumulative
+    fn temporality(&self, kind: InstrumentKind) -> Temporality {
+        self.inner.temporality(kind)
     }
 }
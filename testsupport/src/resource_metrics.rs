@@ -1,14 +1,26 @@
 use crate::reader::TestMetricsReader;
 use opentelemetry::KeyValue;
 use opentelemetry::metrics::MeterProvider;
-use opentelemetry_sdk::metrics::SdkMeterProvider;
 use opentelemetry_sdk::metrics::data::ResourceMetrics;
 use opentelemetry_sdk::metrics::reader::MetricReader;
+use opentelemetry_sdk::metrics::{Aggregation, Instrument, SdkMeterProvider, Stream};
 
 pub fn make_test_metrics() -> ResourceMetrics {
     let reader = TestMetricsReader::default();
     let meter_provider = SdkMeterProvider::builder()
         .with_reader(reader.clone())
+        .with_view(|instrument: &Instrument| {
+            (instrument.name() == "histo.exp").then(|| {
+                Stream::builder()
+                    .with_aggregation(Aggregation::Base2ExponentialHistogram {
+                        max_size: 20,
+                        max_scale: 20,
+                        record_min_max: true,
+                    })
+                    .build()
+                    .unwrap()
+            })
+        })
         .build();
     let meter = meter_provider.meter("meter.1");
 
@@ -29,6 +41,12 @@ pub fn make_test_metrics() -> ResourceMetrics {
     hist.record(1.4, &[]);
     hist.record(13.0, &[]);
 
+    let exp_hist = meter.f64_histogram("histo.exp").build();
+    exp_hist.record(2.0, &[]);
+    exp_hist.record(0.0, &[]);
+    exp_hist.record(1.3, &[]);
+    exp_hist.record(13.0, &[]);
+
     let mut metrics = ResourceMetrics::default();
     reader.collect(&mut metrics).unwrap();
 